@@ -0,0 +1,103 @@
+//! per-unit and batched forward of same-shaped NNs, as in a depth layer of blobs
+//!
+//! `cargo bench --bench nn`
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use ndarray::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+#[allow(dead_code)]
+#[path = "../src/consts.rs"]
+mod consts;
+// tests of the module are not built in benches, so their imports are unused
+#[allow(dead_code, unused_imports)]
+#[path = "../src/brain/nn.rs"]
+mod nn;
+
+use consts::{INWARD_NN_INPUT_LEN, INWARD_NN_OUTPUT_LEN};
+use nn::{forward_batched, Activation, BaseNN, NNBatches};
+
+/// hidden layers of the default `NNConfig`
+const HIDDEN_LAYER: usize = 8;
+
+fn inward_layer(len: usize, rng: &mut ChaCha8Rng) -> (Vec<BaseNN>, Vec<Array1<f32>>) {
+    let shape = vec![INWARD_NN_INPUT_LEN, HIDDEN_LAYER, INWARD_NN_OUTPUT_LEN];
+    let nns = (0..len)
+        .map(|_| BaseNN::new_rand(shape.clone(), Activation::Sigmoid, rng))
+        .collect();
+    let inputs = (0..len)
+        .map(|_| Array1::from_shape_fn(INWARD_NN_INPUT_LEN, |_| rng.gen_range(-1.0..1.0)))
+        .collect();
+    (nns, inputs)
+}
+
+fn bench_forward(c: &mut Criterion) {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut group = c.benchmark_group("inward_layer");
+    for len in [16, 64, 256, 1024] {
+        let (nns, inputs) = inward_layer(len, &mut rng);
+        let refs: Vec<&BaseNN> = nns.iter().collect();
+
+        group.bench_with_input(BenchmarkId::new("per_unit", len), &len, |b, _| {
+            b.iter(|| {
+                nns.iter()
+                    .zip(inputs.iter())
+                    .map(|(nn, input)| nn.forward(black_box(input.clone())))
+                    .collect::<Vec<_>>()
+            })
+        });
+        // weights stacked in each call
+        group.bench_with_input(BenchmarkId::new("batched_unstacked", len), &len, |b, _| {
+            b.iter(|| forward_batched(&refs, black_box(&inputs)))
+        });
+
+        // weights stacked once and kept between frames, as in `BevyBlockNeurons`
+        let batches = NNBatches::new(&refs);
+        group.bench_with_input(BenchmarkId::new("batched", len), &len, |b, _| {
+            b.iter(|| batches.forward(black_box(&inputs)))
+        });
+
+        // survivers and their offspring share unmutated networks,
+        // each network has 4 copies, whose inputs pass in one matrix product
+        let copies: Vec<&BaseNN> = nns[..len / 4].iter().cycle().take(len).collect();
+        let per_unit: Vec<BaseNN> = copies.iter().map(|&nn| nn.clone()).collect();
+        group.bench_with_input(BenchmarkId::new("per_unit_copies", len), &len, |b, _| {
+            b.iter(|| {
+                per_unit
+                    .iter()
+                    .zip(inputs.iter())
+                    .map(|(nn, input)| nn.forward(black_box(input.clone())))
+                    .collect::<Vec<_>>()
+            })
+        });
+        let batches = NNBatches::new(&copies);
+        group.bench_with_input(BenchmarkId::new("batched_copies", len), &len, |b, _| {
+            b.iter(|| batches.forward(black_box(&inputs)))
+        });
+
+        // each network has its own node activations, as after topology mutation,
+        // so that each network is passed alone
+        let mutated: Vec<BaseNN> = nns
+            .iter()
+            .enumerate()
+            .map(|(idx, nn)| {
+                let mut nn = nn.clone();
+                for node in 0..HIDDEN_LAYER {
+                    let activation = Activation::ALL[(idx >> (node * 2)) % 4].clone();
+                    nn.set_activation(0, node, activation);
+                }
+                nn
+            })
+            .collect();
+        let refs: Vec<&BaseNN> = mutated.iter().collect();
+        let batches = NNBatches::new(&refs);
+        group.bench_with_input(BenchmarkId::new("batched_mutated", len), &len, |b, _| {
+            b.iter(|| batches.forward(black_box(&inputs)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_forward);
+criterion_main!(benches);
@@ -0,0 +1,138 @@
+//! routing of signals between NNs of blobs and of contact events to blocks,
+//! time per block should stay the same as the population grows
+//!
+//! `cargo bench --bench signal`
+
+use bevy::prelude::{Entity, Vec2};
+use bevy_rapier2d::prelude::ContactForceEvent;
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use evosim::{
+    blob::block::{BlockDepth, ParentAnchor},
+    brain::{
+        neuron::{BlockNN, BrainNN, GenericNN},
+        resource::BevyBlockNeurons,
+        signal::{BrainSignal, InwardNNInputSignal, SignalHandler},
+    },
+    config::NNConfig,
+    contorl::update::contact_map,
+};
+
+/// depth of the leaves of a blob
+const DEPTH: u32 = 3;
+/// children of each block, a blob has 2 + 4 + 8 blocks
+const CHILDREN: usize = 2;
+
+/// a block of a blob, as pushed to `SignalHandler`
+struct Block {
+    nn_id: usize,
+    parent_nn_id: usize,
+    depth: u32,
+    anchor: usize,
+}
+
+/// NNs and blocks of `population` full blobs, blobs are interleaved in each depth
+/// as blocks of the same depth are spawned together
+fn population(
+    population: usize,
+    rng: &mut ChaCha8Rng,
+) -> (BevyBlockNeurons, Vec<usize>, Vec<Block>) {
+    let config = NNConfig::default();
+    let mut bbn = BevyBlockNeurons::default();
+    let brains: Vec<usize> = (0..population).collect();
+    for _ in 0..population {
+        bbn.nnvec
+            .push(GenericNN::BRAINNN(BrainNN::new(&config, rng)));
+    }
+
+    let mut blocks = Vec::new();
+    let mut parents = brains.clone();
+    for depth in 1..=DEPTH {
+        let mut layer = Vec::new();
+        for anchor in 0..CHILDREN {
+            for &parent_nn_id in parents.iter() {
+                let nn_id = bbn.nnvec.len();
+                bbn.nnvec
+                    .push(GenericNN::BLOCKNN(BlockNN::new(&config, rng)));
+                blocks.push(Block {
+                    nn_id,
+                    parent_nn_id,
+                    depth,
+                    anchor,
+                });
+                layer.push(nn_id);
+            }
+        }
+        parents = layer;
+    }
+    (bbn, brains, blocks)
+}
+
+fn signal_handler(brains: &[usize], blocks: &[Block]) -> SignalHandler {
+    let mut handler = SignalHandler::default();
+    for block in blocks {
+        handler.push_inward(
+            InwardNNInputSignal::default().with_joint_singal((0.1, -0.1, 0.2, -0.2)),
+            block.nn_id,
+            block.parent_nn_id,
+            &BlockDepth(block.depth),
+            &ParentAnchor(Some(block.anchor)),
+            Entity::from_raw(block.nn_id as u32),
+        );
+    }
+    for &nn_id in brains {
+        handler.push_brain(BrainSignal::default(), nn_id);
+    }
+    handler
+}
+
+/// each block touches its parent
+fn contact_events(blocks: &[Block]) -> Vec<ContactForceEvent> {
+    blocks
+        .iter()
+        .map(|block| ContactForceEvent {
+            collider1: Entity::from_raw(block.nn_id as u32),
+            collider2: Entity::from_raw(block.parent_nn_id as u32),
+            total_force: Vec2::X,
+            total_force_magnitude: 1.0,
+            max_force_direction: Vec2::X,
+            max_force_magnitude: 1.0,
+        })
+        .collect()
+}
+
+fn bench_routing(c: &mut Criterion) {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut group = c.benchmark_group("signal_routing");
+    for size in [75, 150, 300] {
+        let (mut bbn, brains, blocks) = population(size, &mut rng);
+        group.throughput(Throughput::Elements(blocks.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("nn_pass", size), &size, |b, _| {
+            b.iter_batched(
+                || signal_handler(&brains, &blocks),
+                |handler| bbn.get_outputs_chunked(handler, 1, false),
+                BatchSize::SmallInput,
+            )
+        });
+
+        let events = contact_events(&blocks);
+        group.bench_with_input(BenchmarkId::new("contact_map", size), &size, |b, _| {
+            b.iter(|| {
+                let cf_map = contact_map(black_box(&events));
+                blocks
+                    .iter()
+                    .filter(|block| cf_map.contains_key(&Entity::from_raw(block.nn_id as u32)))
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_routing);
+criterion_main!(benches);
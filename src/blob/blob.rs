@@ -1,7 +1,5 @@
 //! `BlobInfo` componet and `BlobBundle`
 
-use std::f32::NAN;
-
 use bevy::prelude::*;
 
 /// flag of a blob entity
@@ -24,20 +22,41 @@ pub struct BlobInfo {
     /// cumulated moving distance,
     /// base on `mass_center`
     pub move_distance: [f32;2],
-    pub crowding_distance: f32
+    /// cumulated length of the moving path,
+    /// base on `mass_center`
+    pub path_length: f32,
+    /// how many frames `move_distance` and `path_length` cumulated
+    pub move_frames: u32,
+    /// total mass of all blocks
+    pub mass: f32,
+    /// cumulated energy spent by joint motors and blocks
+    pub energy: f32,
+    /// energy spent in last frame, per second
+    pub power: f32,
+    /// morphological novelty,
+    /// average tree edit distance to all blobs in the population
+    pub novelty: f32,
+    /// frames since the blob is spawned, clock of its oscillator
+    pub age: u32,
 }
 
 impl Default for BlobInfo {
     fn default() -> Self {
         Self {
             center_block_pos: Vec2::NAN,
-            xbound: [NAN, NAN],
-            ybound: [NAN, NAN],
+            xbound: [f32::NAN, f32::NAN],
+            ybound: [f32::NAN, f32::NAN],
             color: Color::LIME_GREEN,
             mass_center: [0.0, 0.0],
             velocity: [0.0,0.0],
             move_distance: [0.0,0.0],
-            crowding_distance: 0.0
+            path_length: 0.0,
+            move_frames: 0,
+            mass: 0.0,
+            energy: 0.0,
+            power: 0.0,
+            novelty: 0.0,
+            age: 0,
         }
     }
 }
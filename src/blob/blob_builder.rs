@@ -4,10 +4,11 @@ use std::f32::consts::PI;
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
+use rand::RngCore;
 
 use crate::{
     brain::neuron::{BlockNN, BrainNN, GenericNN},
-    consts::*,
+    config::EvoConfig,
 };
 
 use super::{blob::*, block::*, geno_blob_builder::BlobGeno};
@@ -35,6 +36,8 @@ pub struct BlobBuilder<'a> {
     // tools
     commands: Commands<'a, 'a>,
     nnvec: &'a mut Vec<GenericNN>,
+    config: &'a EvoConfig,
+    rng: &'a mut dyn RngCore,
 
     // builder info
     blob_bundle: Entity,
@@ -57,11 +60,18 @@ impl<'a> BlobBuilder<'a> {
     ///
     /// To generate multiple blobs, or want to use BlobBuilder in loops,
     /// please use `clean()` so that there won't be joints connects.
-    pub fn from_commands(mut commands: Commands<'a, 'a>, nnvec: &'a mut Vec<GenericNN>) -> Self {
+    pub fn from_commands(
+        mut commands: Commands<'a, 'a>,
+        nnvec: &'a mut Vec<GenericNN>,
+        config: &'a EvoConfig,
+        rng: &'a mut dyn RngCore,
+    ) -> Self {
         Self {
             blob_bundle: commands.spawn(BlobBundle::default()).id(),
-            commands: commands,
-            nnvec: nnvec,
+            commands,
+            nnvec,
+            config,
+            rng,
             blocks: Vec::new(),
             current_pos: None,
             info: BlobInfo::default(),
@@ -165,7 +175,7 @@ impl<'a> BlobBuilder<'a> {
         phy_block_bundle: PhysiBlockBundle,
         others: T,
     ) -> Option<usize> {
-        let nn = BrainNN::default();
+        let nn = BrainNN::new(&self.config.nn, self.rng);
         self.nnvec.push(GenericNN::BRAINNN(nn));
         // push first so the real id should minus one
         let nn_id = self.nnvec.len() - 1;
@@ -177,6 +187,10 @@ impl<'a> BlobBuilder<'a> {
                 phy_block_bundle
                     .clone()
                     .with_color(self.info.color)
+                    .with_damping(
+                        self.config.physics.damping_linear,
+                        self.config.physics.damping_angular,
+                    )
                     .with_nn_id(nn_id, None)
                     .with_blob(self.blob_bundle.index()),
             )
@@ -185,7 +199,7 @@ impl<'a> BlobBuilder<'a> {
             .id();
 
         let block = BlobBlock {
-            id: id,
+            id,
             top: None,
             bottom: None,
             left: None,
@@ -195,7 +209,7 @@ impl<'a> BlobBuilder<'a> {
             translation: phy_block_bundle.sprite.transform.translation.truncate(),
             anchors: phy_block_bundle.anchors,
             depth: 0,
-            nn_id: nn_id,
+            nn_id,
         };
 
         // update blob_info in bundle
@@ -232,7 +246,7 @@ impl<'a> BlobBuilder<'a> {
             return None;
         }
 
-        let nn = BlockNN::default();
+        let nn = BlockNN::new(&self.config.nn, self.rng);
         self.nnvec.push(GenericNN::BLOCKNN(nn));
         let nn_id = self.nnvec.len() - 1;
 
@@ -240,7 +254,11 @@ impl<'a> BlobBuilder<'a> {
         let spawn_y = block.translation.y;
         let phy_block_bundle = PhysiBlockBundle::from_xy_dx_dy(spawn_x, spawn_y, dx, dy)
             .with_color(self.info.color)
-            .with_density(DEFAULT_DENSITY)
+            .with_density(self.config.physics.density)
+            .with_damping(
+                self.config.physics.damping_linear,
+                self.config.physics.damping_angular,
+            )
             .with_nn_id(nn_id, Some(block.nn_id))
             .with_blob(self.blob_bundle.index())
             .with_parent_anchor(2);
@@ -250,7 +268,7 @@ impl<'a> BlobBuilder<'a> {
             .insert(others)
             .id();
         let new_block = BlobBlock {
-            id: id,
+            id,
             top: None,
             bottom: None,
             left: None,
@@ -260,7 +278,7 @@ impl<'a> BlobBuilder<'a> {
             anchors: phy_block_bundle.anchors,
             depth: block.depth + 1,
             vec_index: self.blocks.len(),
-            nn_id: nn_id,
+            nn_id,
         };
 
         let block = &mut self.blocks[pos];
@@ -273,24 +291,30 @@ impl<'a> BlobBuilder<'a> {
         // set joint motor
         let mut stiff = 0.0;
         let mut motor_target = 0.0;
-        if motor_pos.is_some() {
-            stiff = MOTOR_STIFFNESS;
-            motor_target = motor_pos.unwrap();
+        if let Some(motor_pos) = motor_pos {
+            stiff = self.config.joint.motor_stiffness;
+            motor_target = motor_pos;
         }
 
         // set joint limits
         let mut limits = [-PI * 0.9, PI * 0.9];
-        if motor_limits.is_some() {
-            limits = motor_limits.unwrap()
+        if let Some(motor_limits) = motor_limits {
+            limits = motor_limits
         }
 
         let joint = RevoluteJointBuilder::new()
             .local_anchor1(block.anchors.left)
             .local_anchor2(new_block.anchors.right)
-            .motor_position(motor_target, stiff, MOTOR_DAMPING)
+            .motor_position(motor_target, stiff, self.config.joint.motor_damping)
             .limits(limits);
 
-        bind_joint(&mut self.commands, block.id, new_block.id, joint);
+        bind_joint(
+            &mut self.commands,
+            block.id,
+            new_block.id,
+            joint,
+            self.config.joint.enable_contacts,
+        );
 
         // update info
         self.info.add(block.translation, block.size);
@@ -325,7 +349,7 @@ impl<'a> BlobBuilder<'a> {
             return None;
         }
 
-        let nn = BlockNN::default();
+        let nn = BlockNN::new(&self.config.nn, self.rng);
         self.nnvec.push(GenericNN::BLOCKNN(nn));
         let nn_id = self.nnvec.len() - 1;
 
@@ -333,7 +357,11 @@ impl<'a> BlobBuilder<'a> {
         let spawn_y = block.translation.y;
         let phy_block_bundle = PhysiBlockBundle::from_xy_dx_dy(spawn_x, spawn_y, dx, dy)
             .with_color(self.info.color)
-            .with_density(DEFAULT_DENSITY)
+            .with_density(self.config.physics.density)
+            .with_damping(
+                self.config.physics.damping_linear,
+                self.config.physics.damping_angular,
+            )
             .with_nn_id(nn_id, Some(block.nn_id))
             .with_blob(self.blob_bundle.index())
             .with_parent_anchor(3);
@@ -343,7 +371,7 @@ impl<'a> BlobBuilder<'a> {
             .insert(others)
             .id();
         let new_block = BlobBlock {
-            id: id,
+            id,
             top: None,
             bottom: None,
             left: Some(pos),
@@ -353,7 +381,7 @@ impl<'a> BlobBuilder<'a> {
             anchors: phy_block_bundle.anchors,
             depth: block.depth + 1,
             vec_index: self.blocks.len(),
-            nn_id: nn_id,
+            nn_id,
         };
 
         let block = &mut self.blocks[pos];
@@ -366,24 +394,30 @@ impl<'a> BlobBuilder<'a> {
         // set joint motor
         let mut stiff = 0.0;
         let mut motor_target = 0.0;
-        if motor_pos.is_some() {
-            stiff = MOTOR_STIFFNESS;
-            motor_target = motor_pos.unwrap();
+        if let Some(motor_pos) = motor_pos {
+            stiff = self.config.joint.motor_stiffness;
+            motor_target = motor_pos;
         }
 
         // set joint limits
         let mut limits = [-PI * 0.9, PI * 0.9];
-        if motor_limits.is_some() {
-            limits = motor_limits.unwrap()
+        if let Some(motor_limits) = motor_limits {
+            limits = motor_limits
         }
 
         let joint = RevoluteJointBuilder::new()
             .local_anchor1(block.anchors.right)
             .local_anchor2(new_block.anchors.left)
-            .motor_position(motor_target, stiff, MOTOR_DAMPING)
+            .motor_position(motor_target, stiff, self.config.joint.motor_damping)
             .limits(limits);
 
-        bind_joint(&mut self.commands, block.id, new_block.id, joint);
+        bind_joint(
+            &mut self.commands,
+            block.id,
+            new_block.id,
+            joint,
+            self.config.joint.enable_contacts,
+        );
 
         // update info
         self.info.add(block.translation, block.size);
@@ -418,7 +452,7 @@ impl<'a> BlobBuilder<'a> {
             return None;
         }
 
-        let nn = BlockNN::default();
+        let nn = BlockNN::new(&self.config.nn, self.rng);
         self.nnvec.push(GenericNN::BLOCKNN(nn));
         let nn_id = self.nnvec.len() - 1;
 
@@ -426,7 +460,11 @@ impl<'a> BlobBuilder<'a> {
         let spawn_y = block.translation.y + block.size.y + dy;
         let phy_block_bundle = PhysiBlockBundle::from_xy_dx_dy(spawn_x, spawn_y, dx, dy)
             .with_color(self.info.color)
-            .with_density(DEFAULT_DENSITY)
+            .with_density(self.config.physics.density)
+            .with_damping(
+                self.config.physics.damping_linear,
+                self.config.physics.damping_angular,
+            )
             .with_nn_id(nn_id, Some(block.nn_id))
             .with_blob(self.blob_bundle.index())
             .with_parent_anchor(0);
@@ -436,7 +474,7 @@ impl<'a> BlobBuilder<'a> {
             .insert(others)
             .id();
         let new_block = BlobBlock {
-            id: id,
+            id,
             top: None,
             bottom: Some(pos),
             left: None,
@@ -446,7 +484,7 @@ impl<'a> BlobBuilder<'a> {
             anchors: phy_block_bundle.anchors,
             depth: block.depth + 1,
             vec_index: self.blocks.len(),
-            nn_id: nn_id,
+            nn_id,
         };
 
         let block = &mut self.blocks[pos];
@@ -459,24 +497,30 @@ impl<'a> BlobBuilder<'a> {
         // set joint motor
         let mut stiff = 0.0;
         let mut motor_target = 0.0;
-        if motor_pos.is_some() {
-            stiff = MOTOR_STIFFNESS;
-            motor_target = motor_pos.unwrap();
+        if let Some(motor_pos) = motor_pos {
+            stiff = self.config.joint.motor_stiffness;
+            motor_target = motor_pos;
         }
 
         // set joint limits
         let mut limits = [-PI * 0.9, PI * 0.9];
-        if motor_limits.is_some() {
-            limits = motor_limits.unwrap()
+        if let Some(motor_limits) = motor_limits {
+            limits = motor_limits
         }
 
         let joint = RevoluteJointBuilder::new()
             .local_anchor1(block.anchors.top)
             .local_anchor2(new_block.anchors.bottom)
-            .motor_position(motor_target, stiff, MOTOR_DAMPING)
+            .motor_position(motor_target, stiff, self.config.joint.motor_damping)
             .limits(limits);
 
-        bind_joint(&mut self.commands, block.id, new_block.id, joint);
+        bind_joint(
+            &mut self.commands,
+            block.id,
+            new_block.id,
+            joint,
+            self.config.joint.enable_contacts,
+        );
 
         // update info
         self.info.add(block.translation, block.size);
@@ -511,7 +555,7 @@ impl<'a> BlobBuilder<'a> {
             return None;
         }
 
-        let nn = BlockNN::default();
+        let nn = BlockNN::new(&self.config.nn, self.rng);
         self.nnvec.push(GenericNN::BLOCKNN(nn));
         let nn_id = self.nnvec.len() - 1;
 
@@ -519,7 +563,11 @@ impl<'a> BlobBuilder<'a> {
         let spawn_y = block.translation.y - block.size.y - dy;
         let phy_block_bundle = PhysiBlockBundle::from_xy_dx_dy(spawn_x, spawn_y, dx, dy)
             .with_color(self.info.color)
-            .with_density(DEFAULT_DENSITY)
+            .with_density(self.config.physics.density)
+            .with_damping(
+                self.config.physics.damping_linear,
+                self.config.physics.damping_angular,
+            )
             .with_nn_id(nn_id, Some(block.nn_id))
             .with_blob(self.blob_bundle.index())
             .with_parent_anchor(1);
@@ -529,7 +577,7 @@ impl<'a> BlobBuilder<'a> {
             .insert(others)
             .id();
         let new_block = BlobBlock {
-            id: id,
+            id,
             top: Some(pos),
             bottom: None,
             left: None,
@@ -539,7 +587,7 @@ impl<'a> BlobBuilder<'a> {
             anchors: phy_block_bundle.anchors,
             depth: block.depth + 1,
             vec_index: self.blocks.len(),
-            nn_id: nn_id,
+            nn_id,
         };
 
         let block = &mut self.blocks[pos];
@@ -552,24 +600,30 @@ impl<'a> BlobBuilder<'a> {
         // set joint motor
         let mut stiff = 0.0;
         let mut motor_target = 0.0;
-        if motor_pos.is_some() {
-            stiff = MOTOR_STIFFNESS;
-            motor_target = motor_pos.unwrap();
+        if let Some(motor_pos) = motor_pos {
+            stiff = self.config.joint.motor_stiffness;
+            motor_target = motor_pos;
         }
 
         // set joint limits
         let mut limits = [-PI * 0.9, PI * 0.9];
-        if motor_limits.is_some() {
-            limits = motor_limits.unwrap()
+        if let Some(motor_limits) = motor_limits {
+            limits = motor_limits
         }
 
         let joint = RevoluteJointBuilder::new()
             .local_anchor1(block.anchors.bottom)
             .local_anchor2(new_block.anchors.top)
-            .motor_position(motor_target, stiff, MOTOR_DAMPING)
+            .motor_position(motor_target, stiff, self.config.joint.motor_damping)
             .limits(limits);
 
-        bind_joint(&mut self.commands, block.id, new_block.id, joint);
+        bind_joint(
+            &mut self.commands,
+            block.id,
+            new_block.id,
+            joint,
+            self.config.joint.enable_contacts,
+        );
 
         // update info
         self.info.add(block.translation, block.size);
@@ -599,10 +653,11 @@ pub fn bind_joint(
     parent: Entity,
     child: Entity,
     joint: RevoluteJointBuilder,
+    enable_contacts: bool,
 ) {
     commands.entity(child).with_children(|cmd| {
         let mut new_joint = ImpulseJoint::new(parent, joint);
-        new_joint.data.set_contacts_enabled(ENABLE_CONTACTS);
+        new_joint.data.set_contacts_enabled(enable_contacts);
         cmd.spawn(new_joint);
     });
 }
@@ -35,7 +35,6 @@ impl BlockAnchors {
 #[derive(Component, Clone, Debug)]
 pub struct BlockDepth(pub u32);
 
-
 /// ParentAnchor can only be 0(up), 1(down), 2(left), 3(right)
 /// 
 /// Considering using enum
@@ -43,21 +42,15 @@ pub struct BlockDepth(pub u32);
 pub struct ParentAnchor(pub Option<usize>);
 
 /// id for relate Neuron
-#[derive(Component, Clone, Debug)]
+#[derive(Component, Clone, Debug, Default)]
 pub struct NeuronId{
     pub id:usize,
     pub parent_id:Option<usize>
 }
 
-impl Default for NeuronId {
-    fn default() -> Self {
-        Self { id: 0, parent_id: None }
-    }
-}
-
 impl NeuronId {
     pub fn new(id:usize, parent_id:Option<usize>) -> Self {
-        Self { id: id, parent_id: parent_id }
+        Self { id, parent_id }
     }
 }
 
@@ -186,6 +179,14 @@ impl PhysiBlockBundle {
         self
     }
 
+    pub fn with_damping(mut self, linear_damping: f32, angular_damping: f32) -> Self {
+        self.damping = Damping {
+            linear_damping,
+            angular_damping,
+        };
+        self
+    }
+
     pub fn with_depth(mut self, depth: u32) -> Self {
         self.depth = BlockDepth(depth);
         self
@@ -8,11 +8,14 @@ use rand::prelude::*;
 use serde::{Serialize, Deserialize};
 
 use crate::blob::block::NeuronId;
+use crate::brain::cpg::CpgGeno;
 use crate::brain::neuron::GenericNN;
+use crate::config::{EvoConfig, GenoConfig};
 use crate::consts::*;
 
 use super::blob_builder::BlobBuilder;
 use super::block::PhysiBlockBundle;
+use super::lineage::Lineage;
 
 /// Generate Blob according to Genotype
 /// Wrapper around BlobBuilder
@@ -21,9 +24,14 @@ pub struct GenoBlobBuilder<'a> {
 }
 
 impl<'a> GenoBlobBuilder<'a> {
-    pub fn from_commands(commands: Commands<'a, 'a>, nnvec: &'a mut Vec<GenericNN>) -> Self {
+    pub fn from_commands(
+        commands: Commands<'a, 'a>,
+        nnvec: &'a mut Vec<GenericNN>,
+        config: &'a EvoConfig,
+        rng: &'a mut dyn RngCore,
+    ) -> Self {
         Self {
-            builder: BlobBuilder::from_commands(commands, nnvec),
+            builder: BlobBuilder::from_commands(commands, nnvec, config, rng),
         }
     }
 
@@ -92,7 +100,7 @@ fn build_node(
         // );
 
         // top
-        if let Some(mut node) = tree.nodes.get_mut(children[0]).and_then(lambda) {
+        if let Some(node) = tree.nodes.get_mut(children[0]).and_then(lambda) {
 
             let nn_id = builder.add_to_top(
                 node.size[0],
@@ -113,7 +121,7 @@ fn build_node(
         }
 
         // bottom
-        if let Some(mut node) = tree.nodes.get_mut(children[1]).and_then(lambda) {
+        if let Some(node) = tree.nodes.get_mut(children[1]).and_then(lambda) {
             let nn_id = builder.add_to_bottom(
                 node.size[0],
                 node.size[1],
@@ -258,12 +266,26 @@ fn build_node_with_nn(
 #[derive(Debug, Component, Clone, Serialize, Deserialize)]
 pub struct BlobGeno {
     pub vec_tree: QuadTree<GenericGenoNode>,
+    /// species assigned by speciation, offspring inherit it from the parent.
+    ///
+    /// `None` if the blob has never been speciated
+    #[serde(default)]
+    pub species: Option<usize>,
+    /// genome id, parents and mutations, see `lineage.rs`
+    #[serde(default)]
+    pub lineage: Lineage,
+    /// oscillator of the blob, see `cpg.rs`
+    #[serde(default)]
+    pub cpg: CpgGeno,
 }
 
 impl Default for BlobGeno {
     fn default() -> Self {
         Self {
-            vec_tree: QuadTree::<GenericGenoNode>::new(GENO_MAX_DEPTH),
+            vec_tree: QuadTree::<GenericGenoNode>::new(GenoConfig::default().max_depth),
+            species: None,
+            lineage: Lineage::default(),
+            cpg: CpgGeno::default(),
         }
     }
 }
@@ -271,7 +293,7 @@ impl Default for BlobGeno {
 impl BlobGeno {
     // TODO: Clean the code. Ugly long function
     /// generate a random GenoType that don't have conflict limbs
-    pub fn new_rand() -> BlobGeno {
+    pub fn new_rand(config: &GenoConfig, rng: &mut dyn RngCore) -> BlobGeno {
         // prevent tree-structural block conflict
         let mut occupied_region = Vec::<[f32; 4]>::new();
 
@@ -294,7 +316,7 @@ impl BlobGeno {
                 }
             }
             occupied_region.push([x_min, x_max, y_min, y_max]);
-            return false;
+            false
         }
 
         /// function to acquire a new rand node
@@ -302,8 +324,9 @@ impl BlobGeno {
             parent: &GenoNode,
             direction: usize,
             occupied_region: &mut Vec<[f32; 4]>,
+            config: &GenoConfig,
+            rng: &mut dyn RngCore,
         ) -> Option<GenericGenoNode> {
-            let mut rng = thread_rng();
 
             let parent_size = parent.size;
             let parent_center = parent.center;
@@ -311,28 +334,30 @@ impl BlobGeno {
             // set limitation
             // limitation can only avoid block conflict
             // it can not avoid conflict caused by tree structure
+            let default_block_size = config.default_block_size;
+            let rand_size_scaler = config.rand_size_scaler;
             let dx_dy_limits_top_bottom =
-                [parent_size[0], DEFAULT_BLOCK_SIZE[0] * RAND_SIZE_SCALER[1]];
+                [parent_size[0], default_block_size[0] * rand_size_scaler[1]];
             let dx_dy_limits_left_right =
-                [DEFAULT_BLOCK_SIZE[0] * RAND_SIZE_SCALER[1], parent_size[1]];
+                [default_block_size[0] * rand_size_scaler[1], parent_size[1]];
 
-            if rng.gen_bool(RAND_NODE_NOT_NONE) {
+            if rng.gen_bool(config.rand_node_not_none) {
                 let joint_limits = [rng.gen_range(-PI * 0.9..0.0), rng.gen_range(0.0..PI * 0.9)];
                 let mut size = [
                     rng.gen_range(
-                        RAND_SIZE_SCALER[0] * DEFAULT_BLOCK_SIZE[0]..dx_dy_limits_top_bottom[0],
+                        rand_size_scaler[0] * default_block_size[0]..dx_dy_limits_top_bottom[0],
                     ),
                     rng.gen_range(
-                        RAND_SIZE_SCALER[0] * DEFAULT_BLOCK_SIZE[1]..dx_dy_limits_top_bottom[1],
+                        rand_size_scaler[0] * default_block_size[1]..dx_dy_limits_top_bottom[1],
                     ),
                 ];
                 if direction == 2 || direction == 3 {
                     size = [
                         rng.gen_range(
-                            RAND_SIZE_SCALER[0] * DEFAULT_BLOCK_SIZE[0]..dx_dy_limits_left_right[0],
+                            rand_size_scaler[0] * default_block_size[0]..dx_dy_limits_left_right[0],
                         ),
                         rng.gen_range(
-                            RAND_SIZE_SCALER[0] * DEFAULT_BLOCK_SIZE[1]..dx_dy_limits_left_right[1],
+                            rand_size_scaler[0] * default_block_size[1]..dx_dy_limits_left_right[1],
                         ),
                     ];
                 }
@@ -365,11 +390,12 @@ impl BlobGeno {
                         joint_limits,
                         size,
                         center,
-                        nn_id: None
+                        nn_id: None,
+                        phase_offset: rng.gen_range(-PI..PI),
                     }));
                 }
             };
-            return None;
+            None
         }
 
         /// recursive function
@@ -377,8 +403,9 @@ impl BlobGeno {
             tree: &mut QuadTree<GenericGenoNode>,
             index: usize,
             occupied_region: &mut Vec<[f32; 4]>,
+            config: &GenoConfig,
+            rng: &mut dyn RngCore,
         ) {
-            let mut rng = thread_rng();
 
             let children = tree.children(index);
 
@@ -390,27 +417,36 @@ impl BlobGeno {
             // random init four nodes, avoid self-conflict
             if let Some(GenericGenoNode::Child(node)) = tree.nodes[index].clone() {
                 for (i, &child) in children.iter().enumerate() {
-                    tree.nodes[child] = rand_nodes(&node, i, occupied_region)
+                    tree.nodes[child] = rand_nodes(&node, i, occupied_region, config, rng)
                 }
 
                 // one parent indicator
-                let parent_idx = *children.choose(&mut rng).unwrap();
+                let parent_idx = *children.choose(rng).unwrap();
                 tree.nodes[parent_idx] = Some(GenericGenoNode::Parent);
 
                 // keep recursion
                 for &i in children.iter() {
                     if i != parent_idx {
-                        build(tree, i, occupied_region);
+                        build(tree, i, occupied_region, config, rng);
                     }
                 }
             }
         }
 
         // init tree
-        let mut bg = BlobGeno::default();
+        let mut bg = BlobGeno {
+            vec_tree: QuadTree::<GenericGenoNode>::new(config.max_depth),
+            species: None,
+            lineage: Lineage::default(),
+            cpg: CpgGeno::default(),
+        };
         // root node
-        bg.vec_tree.nodes[0] = Some(GenericGenoNode::Child(GenoNode::default()));
-        build(&mut bg.vec_tree, 0, &mut occupied_region);
+        bg.vec_tree.nodes[0] = Some(GenericGenoNode::Child(GenoNode {
+            size: config.default_block_size,
+            ..default()
+        }));
+        build(&mut bg.vec_tree, 0, &mut occupied_region, config, rng);
+        bg.cpg = CpgGeno::new_rand(config, rng);
         bg
     }
 
@@ -448,19 +484,19 @@ impl BlobGeno {
                 }
             }
             occupied_region.push([x_min, x_max, y_min, y_max]);
-            return false;
+            false
         }
 
         /// recursively add to `occupied_region`
         fn check (
             tree: &QuadTree<GenericGenoNode>,
-            mut occupied_region: &mut Vec<[f32; 4]>,
+            occupied_region: &mut Vec<[f32; 4]>,
             idx: usize
         ) -> bool {
             // println!("is_valid checking {}", idx);
             // println!("occupied_region {:?}", occupied_region);
             if let Some(Some(GenericGenoNode::Child(cur))) = tree.nodes.get(idx) {
-                if !is_overlapped(cur.center, cur.size, &mut occupied_region) {
+                if !is_overlapped(cur.center, cur.size, occupied_region) {
                     tree.children(idx).iter().all(|&i| check(tree, occupied_region, i))
                 } else {
                     // println!("not valid {}", idx);
@@ -501,6 +537,23 @@ impl BlobGeno {
         result
     }
 
+    /// number of limbs on the longest path from the center block,
+    /// `0` for a blob with the center block only
+    pub fn tree_depth(&self) -> usize {
+        self.all_nn_ids_indices()
+            .into_iter()
+            .map(|mut idx| {
+                let mut depth = 0;
+                while let Some(parent) = self.vec_tree.parent(idx) {
+                    idx = parent;
+                    depth += 1;
+                }
+                depth
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
     /// assign an nn_id to root (sometimes builder don't need new random geno)
     pub fn assign_nn_id_to_root(&mut self, id: usize) {
         if let Some(Some(GenericGenoNode::Child(node))) = self.vec_tree.nodes.get_mut(0) {
@@ -710,15 +763,19 @@ pub struct GenoNode {
     pub size: [f32; 2],
     pub center: [f32; 2],
     pub nn_id: Option<usize>,
+    /// phase of the oscillator relative to the parent block, see `cpg.rs`
+    #[serde(default)]
+    pub phase_offset: f32,
 }
 
 impl Default for GenoNode {
     fn default() -> Self {
         Self {
             joint_limits: [-PI, PI],
-            size: DEFAULT_BLOCK_SIZE,
+            size: GenoConfig::default().default_block_size,
             center: [0.0, 0.0],
-            nn_id: None
+            nn_id: None,
+            phase_offset: 0.0,
         }
     }
 }
@@ -727,9 +784,10 @@ impl GenoNode {
     pub fn from_nn_id(nn_id: usize) -> Self {
         Self {
             joint_limits: [-PI, PI],
-            size: DEFAULT_BLOCK_SIZE,
+            size: GenoConfig::default().default_block_size,
             center: [0.0, 0.0],
-            nn_id: Some(nn_id)
+            nn_id: Some(nn_id),
+            phase_offset: 0.0,
         }
     }
     /// generate `PhysiBlockBundle` from GenoNode
@@ -864,7 +922,7 @@ impl<T> QuadTree<T> {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// let tree1 = QuadTree::new(...);
     /// let tree2 = QuadTree::new(...);
     /// let distance = tree1.tree_edit_distance(&tree2);
@@ -961,8 +1019,9 @@ mod builder_validation_test {
 
     #[test]
     fn test_geno_builder_validation() {
+        let mut rng = thread_rng();
         for _ in 0..100 {
-            let geno = BlobGeno::new_rand();
+            let geno = BlobGeno::new_rand(&GenoConfig::default(), &mut rng);
             assert!(geno.is_valid());
         }
     }
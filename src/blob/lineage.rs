@@ -0,0 +1,108 @@
+//! `Lineage` of blobs and the `Genealogy` resource
+//!
+//! every blob has a stable genome id, its parents' ids, its birth iteration
+//! and the mutations applied to it, so that the phylogenetic tree can be traced,
+//! see `io/lineage.rs`
+
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::geno_blob_builder::BlobGeno;
+
+/// where a blob comes from, stored in `BlobGeno`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Lineage {
+    /// genome id, unique in a run
+    pub id: usize,
+    /// genome ids of the parents, empty for random blobs.
+    ///
+    /// offspring of crossover have two parents
+    pub parents: Vec<usize>,
+    /// iteration the blob was born at, random blobs are born at 0
+    pub birth: usize,
+    /// mutations applied since birth, in the order of applying
+    pub mutations: Vec<Mutation>,
+}
+
+/// a single mutation applied to a blob,
+/// nodes are indices of the geno tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mutation {
+    /// a new limb at the node
+    GainLimb(usize),
+    /// the node and its subtree are dropped
+    LoseLimb(usize),
+    /// block size of the nodes changed
+    BlockSize(Vec<usize>),
+    /// joint limits of the nodes changed
+    JointLimit(Vec<usize>),
+    /// weights and bias of NNs of the nodes perturbed, or their topology changed
+    Nn(Vec<usize>),
+    /// oscillator and phase offsets of all nodes perturbed
+    Cpg,
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn join(nodes: &[usize]) -> String {
+            let nodes: Vec<String> = nodes.iter().map(|node| node.to_string()).collect();
+            nodes.join(" ")
+        }
+
+        match self {
+            Mutation::GainLimb(node) => write!(f, "gain_limb {}", node),
+            Mutation::LoseLimb(node) => write!(f, "lose_limb {}", node),
+            Mutation::BlockSize(nodes) => write!(f, "block_size {}", join(nodes)),
+            Mutation::JointLimit(nodes) => write!(f, "joint_limit {}", join(nodes)),
+            Mutation::Nn(nodes) => write!(f, "nn {}", join(nodes)),
+            Mutation::Cpg => write!(f, "cpg"),
+        }
+    }
+}
+
+/// hand out genome ids, as a bevy resource
+#[derive(Resource, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Genealogy {
+    next_id: usize,
+    /// current iteration, new blobs are born at.
+    ///
+    /// updated by `train_move` at the end of each iteration
+    pub iteration: usize,
+}
+
+impl Genealogy {
+    /// lineage of a new blob
+    pub fn birth(&mut self, parents: Vec<usize>) -> Lineage {
+        let id = self.next_id;
+        self.next_id += 1;
+        Lineage {
+            id,
+            parents,
+            birth: self.iteration,
+            mutations: Vec::new(),
+        }
+    }
+
+    /// register loaded blobs, so that new ids never collide with them.
+    ///
+    /// blobs sharing the same id (e.g. files exported before lineage existed)
+    /// keep the first one, the others get new ids
+    pub fn register<'a>(&mut self, genos: impl IntoIterator<Item = &'a mut BlobGeno>) {
+        let mut genos: Vec<&mut BlobGeno> = genos.into_iter().collect();
+        for geno in genos.iter() {
+            self.next_id = self.next_id.max(geno.lineage.id + 1);
+        }
+
+        let mut seen = Vec::<usize>::new();
+        for geno in genos.iter_mut() {
+            if seen.contains(&geno.lineage.id) {
+                geno.lineage.id = self.next_id;
+                self.next_id += 1;
+            }
+            seen.push(geno.lineage.id);
+        }
+    }
+}
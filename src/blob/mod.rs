@@ -2,5 +2,7 @@
 
 pub mod block;
 pub mod blob_builder;
+#[allow(clippy::module_inception)]
 pub mod blob;
-pub mod geno_blob_builder;
\ No newline at end of file
+pub mod geno_blob_builder;
+pub mod lineage;
\ No newline at end of file
@@ -0,0 +1,140 @@
+//! central pattern generator, rhythmic drive of blobs
+//!
+//! Each blob has an oscillator in its genotype,
+//! the brain gets `amplitude * sin(2π * frequency * t + phase)` as an input,
+//! where `t` is the simulated time since the blob is spawned.
+//!
+//! If `CpgConfig::inward` is set, each block gets the oscillator as an input as well.
+//! If `CpgConfig::coupled` is set, the phase of a block is the phase of its parent
+//! plus `phase_offset` of its node, so waves travel along limbs,
+//! otherwise all blocks oscillate with the phase of the blob.
+
+use std::f64::consts::TAU;
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blob::geno_blob_builder::{BlobGeno, GenericGenoNode},
+    config::{EvoConfig, GenoConfig},
+};
+
+/// evolvable oscillator of a blob
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpgGeno {
+    /// in Hz of simulated time
+    pub frequency: f32,
+    pub amplitude: f32,
+    /// in radians, in `[-PI, PI)`
+    pub phase: f32,
+}
+
+impl Default for CpgGeno {
+    fn default() -> Self {
+        Self {
+            frequency: 1.0,
+            amplitude: 1.0,
+            phase: 0.0,
+        }
+    }
+}
+
+impl CpgGeno {
+    pub fn new_rand(config: &GenoConfig, rng: &mut dyn RngCore) -> Self {
+        Self {
+            frequency: rng.gen_range(config.cpg_frequency[0]..=config.cpg_frequency[1]),
+            amplitude: rng.gen_range(config.cpg_amplitude[0]..=config.cpg_amplitude[1]),
+            phase: rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI),
+        }
+    }
+
+    /// phase of the blob after `age` frames of `dt` seconds.
+    ///
+    /// computed in f64 and wrapped, so it stays accurate for long runs
+    pub fn phase(&self, age: u32, dt: f32) -> f32 {
+        let cycles = self.frequency as f64 * age as f64 * dt as f64;
+        (cycles.fract() * TAU) as f32 + self.phase
+    }
+}
+
+/// write the oscillator output of each block of the blob to `outputs`, indexed by nn_id.
+///
+/// ids out of range of `outputs` are ignored
+pub fn oscillator_outputs(geno: &BlobGeno, age: u32, config: &EvoConfig, outputs: &mut [f32]) {
+    let cpg = &geno.cpg;
+    let blob_phase = cpg.phase(age, config.timestep.rapier_dt);
+    let tree = &geno.vec_tree;
+    // phase of each tree node, parents come before their children in index order
+    let mut phases = vec![blob_phase; tree.nodes.len()];
+    for (idx, node) in tree.nodes.iter().enumerate() {
+        let Some(GenericGenoNode::Child(node)) = node else {
+            continue;
+        };
+        if config.cpg.coupled {
+            if let Some(parent) = tree.parent(idx) {
+                phases[idx] = phases[parent] + node.phase_offset;
+            }
+        }
+        if let Some(output) = node.nn_id.and_then(|nn_id| outputs.get_mut(nn_id)) {
+            *output = cpg.amplitude * phases[idx].sin();
+        }
+    }
+}
+
+#[cfg(test)]
+mod cpg_test {
+    use std::f32::consts::PI;
+
+    use crate::blob::geno_blob_builder::GenoNode;
+
+    use super::*;
+
+    #[test]
+    fn test_phase() {
+        let cpg = CpgGeno {
+            frequency: 0.5,
+            amplitude: 1.0,
+            phase: 0.25,
+        };
+        // a quarter of a cycle
+        assert!((cpg.phase(30, 1.0 / 60.0) - (PI / 2.0 + 0.25)).abs() < 1e-5);
+        // full cycles are wrapped
+        assert!((cpg.phase(1_280_000, 1.0 / 64.0) - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_oscillator_outputs() {
+        let mut geno = BlobGeno {
+            cpg: CpgGeno {
+                frequency: 1.0,
+                amplitude: 2.0,
+                phase: PI / 2.0,
+            },
+            ..Default::default()
+        };
+        geno.vec_tree.nodes[0] = Some(GenericGenoNode::Child(GenoNode::from_nn_id(0)));
+        geno.vec_tree.nodes[1] = Some(GenericGenoNode::Child(GenoNode {
+            phase_offset: PI / 2.0,
+            ..GenoNode::from_nn_id(1)
+        }));
+        geno.vec_tree.nodes[2] = Some(GenericGenoNode::Parent);
+        geno.vec_tree.nodes[5] = Some(GenericGenoNode::Child(GenoNode {
+            phase_offset: PI / 2.0,
+            ..GenoNode::from_nn_id(2)
+        }));
+
+        let mut config = EvoConfig::default();
+        config.cpg.coupled = true;
+        let mut outputs = vec![0.0; 3];
+        oscillator_outputs(&geno, 0, &config, &mut outputs);
+        let expected = [2.0, 0.0, -2.0];
+        for (output, expected) in outputs.iter().zip(expected) {
+            assert!((output - expected).abs() < 1e-5);
+        }
+
+        // uncoupled blocks follow the blob
+        config.cpg.coupled = false;
+        oscillator_outputs(&geno, 0, &config, &mut outputs);
+        assert!(outputs.iter().all(|output| (output - 2.0).abs() < 1e-5));
+    }
+}
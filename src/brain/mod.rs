@@ -3,4 +3,5 @@
 pub mod resource;
 pub mod neuron;
 pub mod signal;
-pub mod nn;
\ No newline at end of file
+pub mod nn;
+pub mod cpg;
\ No newline at end of file
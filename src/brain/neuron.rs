@@ -6,6 +6,7 @@ use ndarray::prelude::*;
 use rand::prelude::*;
 use serde::{Serialize, Deserialize};
 
+use crate::config::NNConfig;
 use crate::consts::*;
 
 use super::{
@@ -13,27 +14,49 @@ use super::{
     signal::{BrainSignal, InwardNNInputSignal, OutwardNNInputSignal},
 };
 
+#[allow(dead_code)]
 const CL: usize = INWARD_NN_CHILDREN_INPUT_LEN;
 const DL: usize = OUTWARD_NN_PARENT_INPUT_LEN;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum GenericNN {
     BLOCKNN(BlockNN),
     BRAINNN(BrainNN),
 }
 
+impl GenericNN {
+    /// forget the memory of recurrent NNs, the blob starts over after respawn
+    pub fn reset_state(&mut self) {
+        match self {
+            GenericNN::BLOCKNN(nn) => {
+                nn.inward_nn.nn.reset_state();
+                nn.outward_nn.nn.reset_state();
+            }
+            GenericNN::BRAINNN(nn) => nn.nn.reset_state(),
+        }
+    }
+}
+
+/// random NN of `shape`, recurrent if set in `config`
+fn new_base_nn(shape: Vec<usize>, config: &NNConfig, rng: &mut dyn RngCore) -> BaseNN {
+    let nn = BaseNN::new_rand(shape, config.activation.clone(), rng);
+    if config.recurrent {
+        nn.with_recurrent(rng)
+    } else {
+        nn
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InwardNN {
     pub nn: BaseNN,
 }
 
-impl Default for InwardNN {
-    fn default() -> Self {
+impl InwardNN {
+    pub fn new(config: &NNConfig, rng: &mut dyn RngCore) -> Self {
         Self {
-            nn: BaseNN::new_rand(
-                Vec::from_iter(INWARD_NN_SHAPE.into_iter().clone()),
-                ACTIVATION_FUNCTION,
-            ),
+            nn: new_base_nn(config.inward_shape(), config, rng),
         }
     }
 }
@@ -43,13 +66,10 @@ pub struct OutwardNN {
     pub nn: BaseNN,
 }
 
-impl Default for OutwardNN {
-    fn default() -> Self {
+impl OutwardNN {
+    pub fn new(config: &NNConfig, rng: &mut dyn RngCore) -> Self {
         Self {
-            nn: BaseNN::new_rand(
-                Vec::from_iter(OUTWARD_NN_SHAPE.into_iter().clone()),
-                ACTIVATION_FUNCTION,
-            ),
+            nn: new_base_nn(config.outward_shape(), config, rng),
         }
     }
 }
@@ -65,25 +85,31 @@ pub struct BlockNN {
     pub outward_signal: OutwardNNInputSignal,
 }
 
-impl Default for BlockNN {
-    fn default() -> Self {
+impl BlockNN {
+    pub fn new(config: &NNConfig, rng: &mut dyn RngCore) -> Self {
         Self {
-            inward_nn: InwardNN::default(),
-            outward_nn: OutwardNN::default(),
+            inward_nn: InwardNN::new(config, rng),
+            outward_nn: OutwardNN::new(config, rng),
             outward_signal: OutwardNNInputSignal::default(),
         }
     }
 }
 
 impl BlockNN {
-    /// forward function for inward nn
+    /// input of inward nn
     ///
     /// also update the `inherited` element in outward nn
-    fn inward_forward(&mut self, signal: &InwardNNInputSignal) -> Array1<f32> {
+    pub fn inward_input(&mut self, signal: &InwardNNInputSignal) -> Array1<f32> {
         let array_signal = signal.to_array();
         // save duplicate signals for ourward usage
         self.outward_signal.inherit(&array_signal);
-        self.inward_nn.nn.forward(array_signal.clone())
+        array_signal
+    }
+
+    /// forward function for inward nn
+    fn inward_forward(&mut self, signal: &InwardNNInputSignal) -> Array1<f32> {
+        let input = self.inward_input(signal);
+        self.inward_nn.nn.step(input)
     }
 
     /// output inward signal that passing to next layer
@@ -92,15 +118,20 @@ impl BlockNN {
         self.inward_forward(signal)
     }
 
-    pub fn get_rand_inward_output(&self) -> Array1<f32> {
-        let mut rng = thread_rng();
+    pub fn get_rand_inward_output(&self, rng: &mut dyn RngCore) -> Array1<f32> {
         Array1::from_shape_fn((4,), |_| rng.gen::<f32>())
     }
 
-    pub fn get_outward_output(&mut self, parent_signal: &Array1<f32>) -> Array1<f32> {
+    /// input of outward nn, inherited signal and parent's output
+    pub fn outward_input(&mut self, parent_signal: &Array1<f32>) -> Array1<f32> {
         assert_eq!(parent_signal.len(), DL);
         self.outward_signal.parent_input = parent_signal.clone();
-        self.outward_nn.nn.forward(self.outward_signal.to_array())
+        self.outward_signal.to_array()
+    }
+
+    pub fn get_outward_output(&mut self, parent_signal: &Array1<f32>) -> Array1<f32> {
+        let input = self.outward_input(parent_signal);
+        self.outward_nn.nn.step(input)
     }
 }
 
@@ -110,24 +141,20 @@ pub struct BrainNN {
     pub nn: BaseNN,
 }
 
-impl Default for BrainNN {
-    fn default() -> Self {
+impl BrainNN {
+    pub fn new(config: &NNConfig, rng: &mut dyn RngCore) -> Self {
         Self {
-            nn: BaseNN::new_rand(
-                Vec::from_iter(BRAIN_NN_SHAPE.into_iter().clone()),
-                ACTIVATION_FUNCTION,
-            ),
+            nn: new_base_nn(config.brain_shape(), config, rng),
         }
     }
 }
 
 impl BrainNN {
-    pub fn forward(&self, signal: &BrainSignal) -> Array1<f32> {
-        self.nn.forward(signal.to_array())
+    pub fn forward(&mut self, signal: &BrainSignal) -> Array1<f32> {
+        self.nn.step(signal.to_array())
     }
 
-    pub fn get_rand_brain_output(&self) -> Array1<f32> {
-        let mut rng = thread_rng();
+    pub fn get_rand_brain_output(&self, rng: &mut dyn RngCore) -> Array1<f32> {
         Array1::from_shape_fn((4,), |_| rng.gen::<f32>())
     }
 }
@@ -1,55 +1,123 @@
 //! base neuron implementation
+//!
+//! `BaseNN::forward` runs a single network,
+//! `BaseNN::step` also updates the state of its `RecurrentLayer`.
+//! `NNBatches` runs many networks at once,
+//! networks of the same shape are stacked into a `BatchedNN`,
+//! which is kept as long as the networks do not change.
+//! Copies of a network are stacked once, their inputs are stacked instead.
+//!
+//! The topology of a network evolves, hidden nodes and layers are added and removed
+//! by `add_node`, `remove_node`, `add_layer` and `remove_layer`,
+//! and nodes can have their own activations, see `BaseLayer::activations`.
+//! Widths of input and output never change.
 
-use std::fmt;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt,
+    hash::{Hash, Hasher},
+};
 
-use ndarray::prelude::*;
-use rand::{distributions::Uniform, prelude::Distribution};
+use ndarray::{prelude::*, Zip};
+use rand::{distributions::Uniform, prelude::Distribution, RngCore};
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Activation {
     ReLU,
     Sigmoid,
+    Tanh,
+    Sine,
+    Identity,
+    /// `exp(-x^2)`
+    Gaussian,
 }
 
 impl Activation {
+    /// all activations, a node switches to one of them in mutation
+    pub const ALL: [Activation; 6] = [
+        Activation::ReLU,
+        Activation::Sigmoid,
+        Activation::Tanh,
+        Activation::Sine,
+        Activation::Identity,
+        Activation::Gaussian,
+    ];
+
     fn apply(&self, input: f32) -> f32 {
         match self {
             Activation::ReLU => input.max(0.0),
             Activation::Sigmoid => 1.0 / (1.0 + (-input).exp()),
+            Activation::Tanh => input.tanh(),
+            Activation::Sine => input.sin(),
+            Activation::Identity => input,
+            Activation::Gaussian => (-input * input).exp(),
         }
     }
 }
 
+/// apply the activation of each node to `z`,
+/// `default` to all nodes if `activations` is empty
+fn activate(mut z: ArrayViewMut1<f32>, activations: &[Activation], default: &Activation) {
+    if activations.is_empty() {
+        z.mapv_inplace(|x| default.apply(x));
+    } else {
+        Zip::from(&mut z)
+            .and(activations)
+            .for_each(|x, activation| *x = activation.apply(*x));
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BaseLayer {
     pub weights: Array2<f32>,
     pub bias: Array1<f32>,
+    /// activation of each node, all nodes use the activation of the network if empty
+    #[serde(default)]
+    pub activations: Vec<Activation>,
 }
 
 impl BaseLayer {
-    fn new_rand(nodes_in: usize, nodes_out: usize) -> BaseLayer {
+    fn new_rand(nodes_in: usize, nodes_out: usize, rng: &mut dyn RngCore) -> BaseLayer {
         let weight_dist = Uniform::new(-1.0, 1.0);
         let bias_dist = Uniform::new(-1.0, 1.0);
 
         let weights = Array::from_shape_fn((nodes_out, nodes_in), |_| {
-            weight_dist.sample(&mut rand::thread_rng())
+            weight_dist.sample(rng)
         });
-        let bias = Array::from_shape_fn(nodes_out, |_| bias_dist.sample(&mut rand::thread_rng()));
+        let bias = Array::from_shape_fn(nodes_out, |_| bias_dist.sample(rng));
 
-        BaseLayer { weights, bias }
+        BaseLayer {
+            weights,
+            bias,
+            activations: Vec::new(),
+        }
     }
 
     fn new_empty(nodes_in: usize, nodes_out: usize) -> BaseLayer {
         let weights = Array2::<f32>::zeros((nodes_out, nodes_in));
         let bias = Array1::<f32>::zeros(nodes_out);
-        BaseLayer { weights, bias }
+        BaseLayer {
+            weights,
+            bias,
+            activations: Vec::new(),
+        }
     }
 
     fn forward(&self, input: &Array1<f32>, activation: &Activation) -> Array1<f32> {
         assert_eq!(input.len(), self.weights.shape()[1]);
-        let z = self.weights.dot(input) + &self.bias;
-        z.mapv(|x| activation.apply(x))
+        let mut z = self.weights.dot(input) + &self.bias;
+        activate(z.view_mut(), &self.activations, activation);
+        z
+    }
+
+    /// keep the nodes of `keep` in order, with their incoming weights
+    fn select_nodes(&mut self, keep: &[usize]) {
+        self.weights = self.weights.select(Axis(0), keep);
+        self.bias = self.bias.select(Axis(0), keep);
+        if !self.activations.is_empty() {
+            self.activations = keep.iter().map(|&idx| self.activations[idx].clone()).collect();
+        }
     }
 }
 
@@ -64,22 +132,65 @@ impl fmt::Display for BaseLayer {
     }
 }
 
+/// recurrent connection of the first layer, as in an Elman network.
+///
+/// Output of the first layer in the last step is fed back to it through `weights`,
+/// so that the network keeps a memory between frames, e.g. the rhythm of a gait.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurrentLayer {
+    /// weights from the last output of the layer to the layer, shape `(nodes, nodes)`
+    pub weights: Array2<f32>,
+    /// output of the layer in the last step
+    pub state: Array1<f32>,
+}
+
+impl RecurrentLayer {
+    fn new_rand(nodes: usize, rng: &mut dyn RngCore) -> RecurrentLayer {
+        let weight_dist = Uniform::new(-1.0, 1.0);
+        let weights = Array::from_shape_fn((nodes, nodes), |_| weight_dist.sample(rng));
+        RecurrentLayer {
+            weights,
+            state: Array1::<f32>::zeros(nodes),
+        }
+    }
+
+    /// forward of `layer` with the state as extra input
+    fn forward(
+        &self,
+        layer: &BaseLayer,
+        input: &Array1<f32>,
+        activation: &Activation,
+    ) -> Array1<f32> {
+        assert_eq!(input.len(), layer.weights.shape()[1]);
+        let mut z = layer.weights.dot(input) + &layer.bias + self.weights.dot(&self.state);
+        activate(z.view_mut(), &layer.activations, activation);
+        z
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BaseNN {
     pub layers: Vec<BaseLayer>,
     activation: Activation,
+    /// feed-forward network if `None`
+    #[serde(default)]
+    pub recurrent: Option<RecurrentLayer>,
 }
 
 impl BaseNN {
-    pub fn new_rand(layer_sizes: Vec<usize>, activation: Activation) -> Self {
+    pub fn new_rand(layer_sizes: Vec<usize>, activation: Activation, rng: &mut dyn RngCore) -> Self {
         let mut layers = Vec::<BaseLayer>::new();
         if layer_sizes.len() <= 1 {
             panic!()
         }
         for i in 1..layer_sizes.len() {
-            layers.push(BaseLayer::new_rand(layer_sizes[i - 1], layer_sizes[i]));
+            layers.push(BaseLayer::new_rand(layer_sizes[i - 1], layer_sizes[i], rng));
+        }
+        Self {
+            layers,
+            activation,
+            recurrent: None,
         }
-        Self { layers, activation }
     }
 
     pub fn new_empty(layer_sizes: Vec<usize>, activation: Activation) -> Self {
@@ -90,18 +201,431 @@ impl BaseNN {
         for i in 1..layer_sizes.len() {
             layers.push(BaseLayer::new_empty(layer_sizes[i - 1], layer_sizes[i]));
         }
-        Self { layers, activation }
+        Self {
+            layers,
+            activation,
+            recurrent: None,
+        }
+    }
+
+    /// add a random recurrent connection to the first layer, see `RecurrentLayer`
+    pub fn with_recurrent(mut self, rng: &mut dyn RngCore) -> Self {
+        let nodes = self.layers[0].bias.len();
+        self.recurrent = Some(RecurrentLayer::new_rand(nodes, rng));
+        self
+    }
+
+    /// forget the output of the recurrent layer, e.g. when the blob is respawned
+    pub fn reset_state(&mut self) {
+        if let Some(recurrent) = &mut self.recurrent {
+            recurrent.state.fill(0.0);
+        }
     }
 
-    pub fn forward(&self, mut input: Array1<f32>) -> Array1<f32> {
-        // println!("{}",input.len());
+    /// node count of each layer, including input layer
+    pub fn shape(&self) -> Vec<usize> {
+        let mut shape = Vec::<usize>::new();
+        if let Some(first) = self.layers.first() {
+            shape.push(first.weights.shape()[1]);
+        }
         for layer in &self.layers {
-            input = layer.forward(&input, &self.activation);
+            shape.push(layer.weights.shape()[0]);
+        }
+        shape
+    }
+
+    /// same node count of each layer, without allocating the shape
+    pub fn same_shape(&self, other: &BaseNN) -> bool {
+        self.layers.len() == other.layers.len()
+            && self
+                .layers
+                .iter()
+                .zip(other.layers.iter())
+                .all(|(a, b)| a.weights.dim() == b.weights.dim())
+    }
+
+    /// same activation of the network and of each node
+    pub fn same_activations(&self, other: &BaseNN) -> bool {
+        self.activation == other.activation
+            && self.layers.len() == other.layers.len()
+            && self
+                .layers
+                .iter()
+                .zip(other.layers.iter())
+                .all(|(a, b)| a.activations == b.activations)
+    }
+
+    /// number of layers except the output layer
+    pub fn hidden_layers(&self) -> usize {
+        self.layers.len().saturating_sub(1)
+    }
+
+    /// add a node to hidden layer `idx`, with random incoming weights and zero outgoing weights,
+    /// so that the output does not change until the outgoing weights mutate
+    pub fn add_node(&mut self, idx: usize, rng: &mut dyn RngCore) {
+        assert!(idx < self.hidden_layers(), "layer {} is not a hidden layer", idx);
+        let weight_dist = Uniform::new(-1.0, 1.0);
+        let layer = &mut self.layers[idx];
+        let incoming = Array1::from_shape_fn(layer.weights.ncols(), |_| weight_dist.sample(rng));
+        layer.weights.push_row(incoming.view()).unwrap();
+        layer.bias.append(Axis(0), aview1(&[weight_dist.sample(rng)])).unwrap();
+        if !layer.activations.is_empty() {
+            layer.activations.push(self.activation.clone());
+        }
+
+        let next = &mut self.layers[idx + 1];
+        let outgoing = Array1::<f32>::zeros(next.weights.nrows());
+        next.weights.push_column(outgoing.view()).unwrap();
+
+        if idx == 0 {
+            if let Some(recurrent) = &mut self.recurrent {
+                let nodes = recurrent.state.len();
+                recurrent.weights.push_column(Array1::zeros(nodes).view()).unwrap();
+                let incoming = Array1::from_shape_fn(nodes + 1, |_| weight_dist.sample(rng));
+                recurrent.weights.push_row(incoming.view()).unwrap();
+                recurrent.state.append(Axis(0), aview1(&[0.0])).unwrap();
+            }
+        }
+    }
+
+    /// remove `node` of hidden layer `idx`, with its incoming and outgoing weights.
+    ///
+    /// a hidden layer keeps at least one node
+    pub fn remove_node(&mut self, idx: usize, node: usize) {
+        assert!(idx < self.hidden_layers(), "layer {} is not a hidden layer", idx);
+        let nodes = self.layers[idx].bias.len();
+        assert!(nodes > 1, "can not remove the last node of layer {}", idx);
+        let keep: Vec<usize> = (0..nodes).filter(|&other| other != node).collect();
+
+        self.layers[idx].select_nodes(&keep);
+        let next = &mut self.layers[idx + 1];
+        next.weights = next.weights.select(Axis(1), &keep);
+
+        if idx == 0 {
+            if let Some(recurrent) = &mut self.recurrent {
+                recurrent.weights = recurrent.weights.select(Axis(0), &keep).select(Axis(1), &keep);
+                recurrent.state = recurrent.state.select(Axis(0), &keep);
+            }
+        }
+    }
+
+    /// insert a hidden layer before layer `idx`, which passes its input on unchanged,
+    /// identity weights with identity activations.
+    ///
+    /// the recurrent layer stays the first layer, so `idx` is not `0` for recurrent networks
+    pub fn add_layer(&mut self, idx: usize) {
+        assert!(idx < self.layers.len(), "layer {} does not exist", idx);
+        assert!(
+            idx > 0 || self.recurrent.is_none(),
+            "can not insert before the recurrent layer"
+        );
+        let nodes = self.layers[idx].weights.ncols();
+        self.layers.insert(
+            idx,
+            BaseLayer {
+                weights: Array2::eye(nodes),
+                bias: Array1::zeros(nodes),
+                activations: vec![Activation::Identity; nodes],
+            },
+        );
+    }
+
+    /// remove hidden layer `idx`, the next layer takes its input
+    /// through the product of the weights of both layers.
+    ///
+    /// the output does not change if the removed layer has identity activations
+    pub fn remove_layer(&mut self, idx: usize) {
+        assert!(idx < self.hidden_layers(), "layer {} is not a hidden layer", idx);
+        assert!(
+            idx > 0 || self.recurrent.is_none(),
+            "can not remove the recurrent layer"
+        );
+        let removed = self.layers.remove(idx);
+        let next = &mut self.layers[idx];
+        next.bias = next.weights.dot(&removed.bias) + &next.bias;
+        next.weights = next.weights.dot(&removed.weights);
+    }
+
+    /// set the activation of `node` of layer `idx`
+    pub fn set_activation(&mut self, idx: usize, node: usize, activation: Activation) {
+        let layer = &mut self.layers[idx];
+        if layer.activations.is_empty() {
+            layer.activations = vec![self.activation.clone(); layer.bias.len()];
+        }
+        layer.activations[node] = activation;
+    }
+
+    /// forward without changing the state of the recurrent layer, see `step`
+    pub fn forward(&self, input: Array1<f32>) -> Array1<f32> {
+        self.pass(input).1
+    }
+
+    /// forward and keep the output of the recurrent layer for the next step,
+    /// same as `forward` for feed-forward networks
+    pub fn step(&mut self, input: Array1<f32>) -> Array1<f32> {
+        let (state, output) = self.pass(input);
+        if let (Some(recurrent), Some(state)) = (&mut self.recurrent, state) {
+            recurrent.state = state;
+        }
+        output
+    }
+
+    /// output of the recurrent layer if any, and output of the network
+    fn pass(&self, mut input: Array1<f32>) -> (Option<Array1<f32>>, Array1<f32>) {
+        let mut state = None;
+        for (idx, layer) in self.layers.iter().enumerate() {
+            input = match &self.recurrent {
+                Some(recurrent) if idx == 0 => {
+                    let output = recurrent.forward(layer, &input, &self.activation);
+                    state = Some(output.clone());
+                    output
+                }
+                _ => layer.forward(&input, &self.activation),
+            };
+        }
+        (state, input)
+    }
+}
+
+/// same-shaped networks with the same activations, stacked layer by layer.
+///
+/// networks with identical weights, such as unmutated NNs inherited by offspring,
+/// are stacked once, and the inputs of all their copies pass each layer
+/// in one matrix-matrix product
+#[derive(Debug, Clone)]
+pub struct BatchedNN {
+    /// weights `(nets, out, in)`, bias `(nets, out)` and node activations of each layer,
+    /// of each distinct network
+    layers: Vec<(Array3<f32>, Array2<f32>, Vec<Activation>)>,
+    /// rows of the batch grouped by distinct network, and the row count of each network
+    order: Vec<usize>,
+    counts: Vec<usize>,
+    activation: Activation,
+}
+
+impl BatchedNN {
+    /// stack networks in order, panic if they have different shapes or activations.
+    ///
+    /// recurrent networks are not stacked, since their state changes in each `step`
+    pub fn stack(nns: &[&BaseNN]) -> Self {
+        let first = nns.first().expect("can not stack zero networks");
+        for nn in nns.iter() {
+            assert!(nn.recurrent.is_none(), "recurrent networks can not be stacked");
+            assert!(nn.same_shape(first), "networks of different shapes");
+            assert!(nn.same_activations(first), "networks of different activations");
+        }
+
+        // rows of each distinct network, copies are found by the hash of their weights
+        let mut distinct: Vec<(usize, Vec<usize>)> = Vec::new();
+        let mut hashes: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (row, nn) in nns.iter().enumerate() {
+            let candidates = hashes.entry(weights_hash(nn)).or_default();
+            match candidates
+                .iter()
+                .find(|&&net| same_weights(nns[distinct[net].0], nn))
+            {
+                Some(&net) => distinct[net].1.push(row),
+                None => {
+                    candidates.push(distinct.len());
+                    distinct.push((row, vec![row]));
+                }
+            }
         }
-        input
+
+        let layers = (0..first.layers.len())
+            .map(|idx| {
+                let (nodes_out, nodes_in) = first.layers[idx].weights.dim();
+                let mut weights = Vec::with_capacity(distinct.len() * nodes_out * nodes_in);
+                let mut bias = Vec::with_capacity(distinct.len() * nodes_out);
+                for (row, _) in distinct.iter() {
+                    extend_from_array(&mut weights, &nns[*row].layers[idx].weights);
+                    extend_from_array(&mut bias, &nns[*row].layers[idx].bias);
+                }
+                (
+                    Array3::from_shape_vec((distinct.len(), nodes_out, nodes_in), weights)
+                        .unwrap(),
+                    Array2::from_shape_vec((distinct.len(), nodes_out), bias).unwrap(),
+                    first.layers[idx].activations.clone(),
+                )
+            })
+            .collect();
+        Self {
+            layers,
+            order: distinct.iter().flat_map(|(_, rows)| rows.iter().copied()).collect(),
+            counts: distinct.iter().map(|(_, rows)| rows.len()).collect(),
+            activation: first.activation.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// number of distinct networks
+    pub fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// forward the i-th row of `input` `(batch, in)` through the i-th network,
+    /// output is `(batch, out)`
+    pub fn forward(&self, input: Array2<f32>) -> Array2<f32> {
+        assert_eq!(input.nrows(), self.len());
+        // inputs of the copies of a network are consecutive rows
+        let mut x = input.select(Axis(0), &self.order);
+        for (weights, bias, activations) in &self.layers {
+            let (_, nodes_out, nodes_in) = weights.dim();
+            assert_eq!(x.ncols(), nodes_in);
+            let mut z = Array2::<f32>::zeros((x.nrows(), nodes_out));
+            // z = x w^T + bias for the rows of each network, in one pass over contiguous memory
+            let weights = weights.as_slice().unwrap().chunks_exact(nodes_out * nodes_in);
+            let bias = bias.as_slice().unwrap().chunks_exact(nodes_out);
+            let mut rows = x
+                .as_slice()
+                .unwrap()
+                .chunks_exact(nodes_in)
+                .zip(z.as_slice_mut().unwrap().chunks_exact_mut(nodes_out));
+            for ((weights, bias), &count) in weights.zip(bias).zip(self.counts.iter()) {
+                for (x, z) in rows.by_ref().take(count) {
+                    let x = ArrayView1::from(x);
+                    for ((z, w), b) in z.iter_mut().zip(weights.chunks_exact(nodes_in)).zip(bias) {
+                        *z = ArrayView1::from(w).dot(&x) + b;
+                    }
+                }
+            }
+            for z in z.rows_mut() {
+                activate(z, activations, &self.activation);
+            }
+            x = z;
+        }
+        let mut output = Array2::<f32>::zeros(x.dim());
+        for (row, &idx) in x.rows().into_iter().zip(self.order.iter()) {
+            output.row_mut(idx).assign(&row);
+        }
+        output
+    }
+}
+
+/// hash of the bits of all weights and biases, equal for identical networks
+fn weights_hash(nn: &BaseNN) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for layer in nn.layers.iter() {
+        for x in layer.weights.iter().chain(layer.bias.iter()) {
+            x.to_bits().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn same_weights(a: &BaseNN, b: &BaseNN) -> bool {
+    a.layers
+        .iter()
+        .zip(b.layers.iter())
+        .all(|(a, b)| a.weights == b.weights && a.bias == b.bias)
+}
+
+/// copy elements in logical order, as a slice if possible
+fn extend_from_array<D: Dimension>(vec: &mut Vec<f32>, array: &Array<f32, D>) {
+    match array.as_slice() {
+        Some(slice) => vec.extend_from_slice(slice),
+        None => vec.extend(array.iter()),
     }
 }
 
+/// shape, activation and node activations, networks of the same key can be stacked
+type BatchKey<'a> = (Vec<usize>, &'a Activation, Vec<&'a [Activation]>);
+
+/// feed-forward networks, e.g. of a depth layer of blobs,
+/// networks of the same shape and activations are stacked into one `BatchedNN`.
+///
+/// stacking copies all weights, keep the batches until the networks change.
+///
+/// networks with mutated topologies or node activations rarely share a batch,
+/// a network without others of its shape and activations is passed alone,
+/// so batching only gains as much as the population shares topologies
+#[derive(Debug, Clone)]
+pub struct NNBatches {
+    /// indices of the networks of each batch, in the order of first appearance
+    batches: Vec<(Vec<usize>, BatchedNN)>,
+    /// networks passed alone, and their indices
+    singles: Vec<(usize, BaseNN)>,
+    len: usize,
+}
+
+impl NNBatches {
+    pub fn new(nns: &[&BaseNN]) -> Self {
+        // index of the group of each shape and activations, in linear time of networks
+        let mut keys: HashMap<BatchKey, usize> = HashMap::new();
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (idx, nn) in nns.iter().enumerate() {
+            let activations = nn.layers.iter().map(|layer| &layer.activations[..]).collect();
+            let group = *keys
+                .entry((nn.shape(), &nn.activation, activations))
+                .or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+            groups[group].push(idx);
+        }
+        let mut batches = Vec::new();
+        let mut singles = Vec::new();
+        for members in groups {
+            if let [idx] = members[..] {
+                // a stack of one network passes slower than the network
+                singles.push((idx, nns[idx].clone()));
+                continue;
+            }
+            let batch: Vec<&BaseNN> = members.iter().map(|&idx| nns[idx]).collect();
+            let stacked = BatchedNN::stack(&batch);
+            batches.push((members, stacked));
+        }
+        Self {
+            batches,
+            singles,
+            len: nns.len(),
+        }
+    }
+
+    /// number of networks
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// forward the i-th input through the i-th network
+    pub fn forward(&self, inputs: &[Array1<f32>]) -> Vec<Array1<f32>> {
+        assert_eq!(inputs.len(), self.len);
+        let mut outputs = vec![Array1::<f32>::zeros(0); self.len];
+        for (members, stacked) in &self.batches {
+            let mut input = Array2::<f32>::zeros((members.len(), inputs[members[0]].len()));
+            for (mut row, &idx) in input.rows_mut().into_iter().zip(members.iter()) {
+                row.assign(&inputs[idx]);
+            }
+            let output = stacked.forward(input);
+            for (row, &idx) in output.rows().into_iter().zip(members.iter()) {
+                outputs[idx] = row.to_owned();
+            }
+        }
+        for (idx, nn) in &self.singles {
+            outputs[*idx] = nn.forward(inputs[*idx].clone());
+        }
+        outputs
+    }
+}
+
+/// forward the i-th input through the i-th feed-forward network,
+/// stacked for this call only, see `NNBatches`
+pub fn forward_batched(nns: &[&BaseNN], inputs: &[Array1<f32>]) -> Vec<Array1<f32>> {
+    assert_eq!(nns.len(), inputs.len());
+    NNBatches::new(nns).forward(inputs)
+}
+
 impl fmt::Display for BaseNN {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let layers_str: Vec<String> = self
@@ -117,3 +641,151 @@ impl fmt::Display for BaseNN {
         )
     }
 }
+
+#[cfg(test)]
+mod nn_test {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_forward_batched() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut nns = Vec::new();
+        for idx in 0..9 {
+            // networks of two shapes and two activations, interleaved
+            let (shape, activation) = match idx % 3 {
+                0 => (vec![6, 5, 3], Activation::Sigmoid),
+                1 => (vec![6, 4], Activation::Sigmoid),
+                _ => (vec![6, 5, 3], Activation::ReLU),
+            };
+            nns.push(BaseNN::new_rand(shape, activation, &mut rng));
+        }
+        let inputs: Vec<Array1<f32>> = (0..nns.len())
+            .map(|_| Array1::from_shape_fn(6, |_| Uniform::new(-1.0, 1.0).sample(&mut rng)))
+            .collect();
+
+        let refs: Vec<&BaseNN> = nns.iter().collect();
+        let batched = forward_batched(&refs, &inputs);
+        for ((nn, input), output) in nns.iter().zip(inputs.iter()).zip(batched.iter()) {
+            assert_eq!(nn.forward(input.clone()), output);
+        }
+
+        let stacked = BatchedNN::stack(&[&nns[0], &nns[3]]);
+        assert_eq!(stacked.len(), 2);
+        let output = stacked.forward(ndarray::stack![Axis(0), inputs[0], inputs[3]]);
+        assert_eq!(output.row(1), nns[3].forward(inputs[3].clone()));
+
+        // batches are kept for the inputs of the next frames
+        let batches = NNBatches::new(&refs);
+        assert_eq!(batches.len(), nns.len());
+        // grouped by shape and activations, in the order of first appearance
+        let members: Vec<&[usize]> = batches.batches.iter().map(|(ids, _)| &ids[..]).collect();
+        assert_eq!(members, [[0, 3, 6], [1, 4, 7], [2, 5, 8]]);
+        for scale in [0.5, -2.0] {
+            let inputs: Vec<Array1<f32>> = inputs.iter().map(|x| x * scale).collect();
+            for ((nn, input), output) in nns.iter().zip(&inputs).zip(batches.forward(&inputs)) {
+                assert_eq!(nn.forward(input.clone()), output);
+            }
+        }
+    }
+
+    #[test]
+    fn test_batched_copies() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let nns: Vec<BaseNN> = (0..3)
+            .map(|_| BaseNN::new_rand(vec![6, 5, 3], Activation::Sigmoid, &mut rng))
+            .collect();
+        // copies are apart in the batch
+        let refs = vec![&nns[0], &nns[1], &nns[0], &nns[2], &nns[1], &nns[0]];
+        let inputs: Vec<Array1<f32>> = (0..refs.len())
+            .map(|_| Array1::from_shape_fn(6, |_| Uniform::new(-1.0, 1.0).sample(&mut rng)))
+            .collect();
+
+        let stacked = BatchedNN::stack(&refs);
+        assert_eq!(stacked.len(), 6);
+        assert_eq!(stacked.distinct(), 3);
+        let views: Vec<ArrayView1<f32>> = inputs.iter().map(|x| x.view()).collect();
+        let output = stacked.forward(ndarray::stack(Axis(0), &views).unwrap());
+        for ((nn, input), output) in refs.iter().zip(&inputs).zip(output.rows()) {
+            assert_eq!(nn.forward(input.clone()), output);
+        }
+    }
+
+    #[test]
+    fn test_recurrent_step() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut nn = BaseNN::new_rand(vec![6, 5, 3], Activation::Sigmoid, &mut rng)
+            .with_recurrent(&mut rng);
+        let input = Array1::from_shape_fn(6, |_| Uniform::new(-1.0, 1.0).sample(&mut rng));
+
+        // forward does not change the state
+        let first = nn.forward(input.clone());
+        assert_eq!(nn.forward(input.clone()), first);
+        assert_eq!(nn.step(input.clone()), first);
+
+        // the same input gives another output with the state of the last step
+        let second = nn.step(input.clone());
+        assert_ne!(second, first);
+        let saved: BaseNN = serde_json::from_str(&serde_json::to_string(&nn).unwrap()).unwrap();
+        assert_eq!(saved.forward(input.clone()), nn.forward(input.clone()));
+
+        nn.reset_state();
+        assert_eq!(nn.step(input.clone()), first);
+
+        // NNs saved before recurrent layers are feed-forward
+        let mut value = serde_json::to_value(&nn).unwrap();
+        value.as_object_mut().unwrap().remove("recurrent");
+        let saved: BaseNN = serde_json::from_value(value).unwrap();
+        assert!(saved.recurrent.is_none());
+    }
+
+    #[test]
+    fn test_topology() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut nn = BaseNN::new_rand(vec![6, 5, 3], Activation::Tanh, &mut rng)
+            .with_recurrent(&mut rng);
+        let input = Array1::from_shape_fn(6, |_| Uniform::new(-1.0, 1.0).sample(&mut rng));
+        let close = |a: &Array1<f32>, b: &Array1<f32>| {
+            a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() < 1e-5)
+        };
+        nn.step(input.clone());
+        let output = nn.forward(input.clone());
+
+        // new nodes and layers do not change the output
+        nn.add_node(0, &mut rng);
+        assert_eq!(nn.shape(), vec![6, 6, 3]);
+        assert!(close(&nn.forward(input.clone()), &output));
+        nn.add_layer(1);
+        assert_eq!(nn.shape(), vec![6, 6, 6, 3]);
+        assert!(close(&nn.forward(input.clone()), &output));
+        nn.remove_layer(1);
+        assert_eq!(nn.shape(), vec![6, 6, 3]);
+        assert!(close(&nn.forward(input.clone()), &output));
+
+        nn.remove_node(0, 2);
+        assert_eq!(nn.shape(), vec![6, 5, 3]);
+        assert_eq!(nn.recurrent.as_ref().unwrap().weights.dim(), (5, 5));
+        nn.set_activation(1, 0, Activation::Gaussian);
+        assert_eq!(
+            nn.layers[1].activations,
+            vec![Activation::Gaussian, Activation::Tanh, Activation::Tanh]
+        );
+
+        // topology and activations are saved
+        let saved: BaseNN = serde_json::from_str(&serde_json::to_string(&nn).unwrap()).unwrap();
+        assert_eq!(saved.shape(), nn.shape());
+        assert!(saved.same_activations(&nn));
+        assert_eq!(saved.forward(input.clone()), nn.forward(input.clone()));
+
+        // networks with their own node activations are batched apart
+        nn.recurrent = None;
+        let mut other = nn.clone();
+        other.layers[1].activations.clear();
+        let outputs = forward_batched(&[&nn, &other, &nn], &vec![input.clone(); 3]);
+        assert_eq!(outputs[0], nn.forward(input.clone()));
+        assert_eq!(outputs[1], other.forward(input.clone()));
+        assert_ne!(outputs[0], outputs[1]);
+    }
+}
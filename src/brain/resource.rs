@@ -1,65 +1,168 @@
 //! implementation about `BevyBlockNeurons`, which is a wrapper around neural network to make it into a bevy resource.
 
-use std::f32::consts::PI;
+use std::{collections::HashMap, f32::consts::PI};
 
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
+};
 use ndarray::prelude::*;
 use rand::prelude::*;
 
 use crate::{
     brain::signal::InwardNNInputSignalUnit,
-    consts::{MOTOR_MAX_TARGET_V, OUTWARD_NN_PARENT_INPUT_LEN},
+    consts::OUTWARD_NN_PARENT_INPUT_LEN,
 };
 
 use super::{
-    neuron::GenericNN,
-    signal::{BrainSignalUnit, SignalHandler},
+    neuron::{BlockNN, GenericNN},
+    nn::{BaseNN, NNBatches},
+    signal::{parent_indices, BrainSignalUnit, SignalHandler},
 };
 
 const DL: usize = OUTWARD_NN_PARENT_INPUT_LEN;
 
-// TODO: add random generator and oscillator
+// TODO: add random generator, oscillator is in `cpg.rs`
 /// Bevy resource, which make sure the neurons can be accessed
 /// and modified from bevy side
 #[derive(Resource, Debug)]
 pub struct BevyBlockNeurons {
     pub nnvec: Vec<GenericNN>,
+    /// stacked NNs of each chunk in the batched pass, kept between frames,
+    /// see `forget_batches`
+    batches: Vec<ChunkBatches>,
 }
 
 impl Default for BevyBlockNeurons {
     fn default() -> Self {
         let nnv = Vec::<GenericNN>::new();
-        Self { nnvec: nnv }
+        Self {
+            nnvec: nnv,
+            batches: Vec::new(),
+        }
     }
 }
 
 impl BevyBlockNeurons {
-    // TODO: parallel, gpu
+    // TODO: gpu
     /// start neuron computing and return outputs
-    pub fn get_outputs(&mut self, mut signal_handler: SignalHandler) -> Vec<(Entity, f32, f32)> {
+    ///
+    /// blobs are independent, so they are split into chunks of whole blobs,
+    /// one chunk for each thread of `ComputeTaskPool`.
+    ///
+    /// NNs are run one by one, or as batches of same-shaped NNs if `batched`.
+    /// batches are stacked once and kept, call `forget_batches` whenever `nnvec` changes
+    pub fn get_outputs(
+        &mut self,
+        signal_handler: SignalHandler,
+        batched: bool,
+    ) -> Vec<(Entity, f32, f32)> {
+        let threads = ComputeTaskPool::init(TaskPool::default).thread_num();
+        self.get_outputs_chunked(signal_handler, threads, batched)
+    }
+
+    /// pass blobs in `chunks` chunks in parallel, each chunk is passed layer by layer.
+    ///
+    /// one chunk passes all blobs in the calling thread, which is the serial path.
+    /// outputs do not depend on `chunks` or `batched` except for their order
+    pub fn get_outputs_chunked(
+        &mut self,
+        mut signal_handler: SignalHandler,
+        chunks: usize,
+        batched: bool,
+    ) -> Vec<(Entity, f32, f32)> {
+        let pass = move |(chunk, batches): (&mut NNChunk, &mut ChunkBatches)| {
+            if batched {
+                chunk.pass_batched(batches)
+            } else {
+                chunk.pass()
+            }
+        };
+        let mut chunks = split_chunks(&mut signal_handler, &mut self.nnvec, chunks);
+        self.batches.resize_with(chunks.len(), Default::default);
+        if chunks.len() <= 1 {
+            return chunks.iter_mut().zip(self.batches.iter_mut()).flat_map(pass).collect();
+        }
+
+        // the pool of the app, or a new one if there is no app, e.g. in tests
+        ComputeTaskPool::init(TaskPool::default)
+            .scope(|scope| {
+                for chunk in chunks.iter_mut().zip(self.batches.iter_mut()) {
+                    scope.spawn(async move { pass(chunk) });
+                }
+            })
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    pub fn get_rand_outputs(
+        &self,
+        signal_handler: SignalHandler,
+        max_target_v: f32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<[f32; 2]> {
+        let len = signal_handler.inward_len();
+        vec![
+            [
+                rng.gen_range(-PI..PI),
+                rng.gen_range(-max_target_v..max_target_v),
+            ];
+            len
+        ]
+    }
+
+    /// drop all the values inside
+    pub fn clear(&mut self) {
+        self.nnvec.clear();
+        self.forget_batches();
+    }
+
+    /// drop the stacked NNs of the batched pass,
+    /// so that NNs are stacked again with their current weights
+    pub fn forget_batches(&mut self) {
+        self.batches.clear();
+    }
+}
+
+/// signals and NNs of some whole blobs, passed independently of other blobs
+struct NNChunk<'a> {
+    /// inward signals grouped by depth, from depth 1
+    grouped_signal: Vec<Vec<&'a mut InwardNNInputSignalUnit>>,
+    brain_signal: Vec<&'a mut BrainSignalUnit>,
+    /// index of the parent of each inward signal, see `parent_indices`
+    parents: Vec<Vec<usize>>,
+    /// NNs of the blobs, index is nn_id, `None` for NNs of other chunks
+    nnvec: Vec<Option<&'a mut GenericNN>>,
+}
+
+impl NNChunk<'_> {
+    /// pass all the signal inward to brains, then outward to joints
+    fn pass(&mut self) -> Vec<(Entity, f32, f32)> {
         // store output value for joint motors
         let mut outputs: Vec<(Entity, f32, f32)> = Vec::new();
         // store internal outward_nn's outputs, index is nn_id
-        let mut outward_passes = vec![Array1::<f32>::zeros(DL); self.nnvec.len()];
-
-        // generate grouped signal
-        let (mut grouped_signal, mut brain_signal) = signal_handler.get_sig_mut();
+        let mut outward_passes: Vec<Option<Array1<f32>>> = vec![None; self.nnvec.len()];
 
-        // println!("grouped signal {:#?}",grouped_signal);
-        // println!("brain signal {:#?}",brain_signal);
         // passing through all inward layers
-        for idx in (1..grouped_signal.len()).rev() {
-            inward_bulk_pass(&mut grouped_signal, &mut self.nnvec, idx)
+        for idx in (1..self.grouped_signal.len()).rev() {
+            inward_bulk_pass(&mut self.grouped_signal, &self.parents, &mut self.nnvec, idx)
         }
 
         // passing to brain
-        brain_pass(&mut brain_signal, &grouped_signal[0], &mut self.nnvec);
-        // println!("{:#?}",brain_signal[0].signal);
-        brain_forward(&brain_signal, &mut self.nnvec, &mut outward_passes);
+        if let Some(first_layer) = self.grouped_signal.first() {
+            brain_pass(
+                &mut self.brain_signal,
+                first_layer,
+                &self.parents[0],
+                &mut self.nnvec,
+            );
+        }
+        brain_forward(&self.brain_signal, &mut self.nnvec, &mut outward_passes);
 
-        for idx in 0..grouped_signal.len() {
+        for idx in 0..self.grouped_signal.len() {
             outward_bulk_pass(
-                &mut grouped_signal,
+                &self.grouped_signal,
                 &mut self.nnvec,
                 idx,
                 &mut outputs,
@@ -70,30 +173,256 @@ impl BevyBlockNeurons {
         outputs
     }
 
-    pub fn get_rand_outputs(&self, signal_handler: SignalHandler) -> Vec<[f32; 2]> {
-        let mut rng = thread_rng();
-        let len = signal_handler.inward_len();
-        vec![
-            [
-                rng.gen_range(-PI..PI),
-                rng.gen_range(-MOTOR_MAX_TARGET_V..MOTOR_MAX_TARGET_V),
-            ];
-            len
-        ]
+    /// same as `pass`, but NNs of each layer run as batches of same-shaped NNs,
+    /// see `NNBatches`
+    fn pass_batched(&mut self, batches: &mut ChunkBatches) -> Vec<(Entity, f32, f32)> {
+        let mut outputs: Vec<(Entity, f32, f32)> = Vec::new();
+        let mut outward_passes: Vec<Option<Array1<f32>>> = vec![None; self.nnvec.len()];
+
+        // passing through all inward layers, the first layer passes to brain
+        for idx in (0..self.grouped_signal.len()).rev() {
+            let (left, right) = self.grouped_signal.split_at_mut(idx);
+            let current_layer = &right[0];
+            let inputs: Vec<Array1<f32>> = current_layer
+                .iter()
+                .map(|unit| block_nn_mut(&mut self.nnvec, unit.nn_id).inward_input(&unit.signal))
+                .collect();
+            let ids: Vec<usize> = current_layer.iter().map(|unit| unit.nn_id).collect();
+            let results = forward_layer(&mut self.nnvec, &ids, inputs, Pass::Inward, batches);
+
+            for ((unit, result), &parent) in
+                current_layer.iter().zip(results).zip(&self.parents[idx])
+            {
+                if idx == 0 {
+                    self.brain_signal[parent]
+                        .get_signal_mut()
+                        .push_child_signal(result, unit.anchor_pos);
+                } else {
+                    left[idx - 1][parent]
+                        .get_signal_mut()
+                        .push_child_signal(result, unit.anchor_pos);
+                }
+            }
+        }
+
+        // brains
+        let inputs: Vec<Array1<f32>> = self
+            .brain_signal
+            .iter()
+            .map(|unit| unit.signal.to_array())
+            .collect();
+        let ids: Vec<usize> = self.brain_signal.iter().map(|unit| unit.nn_id).collect();
+        let results = forward_layer(&mut self.nnvec, &ids, inputs, Pass::Brain, batches);
+        for (unit, result) in self.brain_signal.iter().zip(results) {
+            outward_passes[unit.nn_id] = Some(result);
+        }
+
+        // outward layers, from brain to the leaves
+        for current_layer in self.grouped_signal.iter() {
+            let inputs: Vec<Array1<f32>> = current_layer
+                .iter()
+                .map(|unit| {
+                    // a parent without result passes zeros
+                    let parent_pass = outward_passes[unit.parent_nn_id]
+                        .clone()
+                        .unwrap_or_else(|| Array1::<f32>::zeros(DL));
+                    block_nn_mut(&mut self.nnvec, unit.nn_id).outward_input(&parent_pass)
+                })
+                .collect();
+            let ids: Vec<usize> = current_layer.iter().map(|unit| unit.nn_id).collect();
+            let results = forward_layer(&mut self.nnvec, &ids, inputs, Pass::Outward, batches);
+            for (unit, a) in current_layer.iter().zip(results) {
+                outward_passes[unit.nn_id] = Some(a.slice(s![..DL]).to_owned());
+                outputs.push((unit.entity_id, a[DL], a[DL + 1]));
+            }
+        }
+
+        batches.end_frame();
+        outputs
     }
+}
 
-    /// drop all the values inside
-    pub fn clear(&mut self) {
-        self.nnvec.clear();
+/// split signals and NNs into at most `chunks` chunks of whole blobs,
+/// blobs are assigned in the order of brain signals
+fn split_chunks<'a>(
+    signal_handler: &'a mut SignalHandler,
+    nnvec: &'a mut [GenericNN],
+    chunks: usize,
+) -> Vec<NNChunk<'a>> {
+    let (grouped_signal, brain_signal) = signal_handler.get_sig_mut();
+    let brains = brain_signal.len().max(1);
+    let chunks = chunks.clamp(1, brains);
+
+    // chunk of each nn, a block is in the chunk of its parent
+    let mut owner: Vec<Option<usize>> = vec![None; nnvec.len()];
+    for (idx, unit) in brain_signal.iter().enumerate() {
+        owner[unit.nn_id] = Some(idx * chunks / brains);
+    }
+    for layer in grouped_signal.iter() {
+        for unit in layer {
+            owner[unit.nn_id] = owner[unit.parent_nn_id];
+        }
+    }
+
+    let mut split: Vec<NNChunk> = (0..chunks)
+        .map(|_| NNChunk {
+            grouped_signal: (0..grouped_signal.len()).map(|_| Vec::new()).collect(),
+            brain_signal: Vec::new(),
+            parents: Vec::new(),
+            nnvec: (0..nnvec.len()).map(|_| None).collect(),
+        })
+        .collect();
+    for unit in brain_signal {
+        // unwrap since owner of all brains are set above
+        split[owner[unit.nn_id].unwrap()].brain_signal.push(unit);
+    }
+    for (depth_idx, layer) in grouped_signal.into_iter().enumerate() {
+        for unit in layer {
+            let chunk = owner[unit.nn_id].unwrap_or_else(|| {
+                panic!("nn with id {} is not connected to a brain", unit.nn_id)
+            });
+            split[chunk].grouped_signal[depth_idx].push(unit);
+        }
+    }
+    for (nn_id, nn) in nnvec.iter_mut().enumerate() {
+        if let Some(chunk) = owner[nn_id] {
+            split[chunk].nnvec[nn_id] = Some(nn);
+        }
+    }
+    for chunk in split.iter_mut() {
+        chunk.parents = parent_indices(&chunk.grouped_signal, &chunk.brain_signal, owner.len());
+    }
+    split
+}
+
+/// nn of the chunk by id
+fn chunk_nn<'a>(nnvec: &'a mut [Option<&mut GenericNN>], nn_id: usize) -> &'a mut GenericNN {
+    nnvec[nn_id]
+        .as_deref_mut()
+        .unwrap_or_else(|| panic!("nn with id {} is not in the chunk", nn_id))
+}
+
+fn chunk_nn_ref<'a>(nnvec: &'a [Option<&mut GenericNN>], nn_id: usize) -> &'a GenericNN {
+    nnvec[nn_id]
+        .as_deref()
+        .unwrap_or_else(|| panic!("nn with id {} is not in the chunk", nn_id))
+}
+
+fn block_nn_mut<'a>(nnvec: &'a mut [Option<&mut GenericNN>], nn_id: usize) -> &'a mut BlockNN {
+    match chunk_nn(nnvec, nn_id) {
+        GenericNN::BLOCKNN(nn) => nn,
+        GenericNN::BRAINNN(_) => panic!(
+            "nn with id {} is expected to be BLOCKNN, but found BRAINNN",
+            nn_id
+        ),
+    }
+}
+
+/// network of a `GenericNN` run in a batched pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Pass {
+    Inward,
+    Outward,
+    Brain,
+}
+
+impl Pass {
+    fn nn(self, nn: &GenericNN) -> &BaseNN {
+        match (self, nn) {
+            (Pass::Inward, GenericNN::BLOCKNN(nn)) => &nn.inward_nn.nn,
+            (Pass::Outward, GenericNN::BLOCKNN(nn)) => &nn.outward_nn.nn,
+            (Pass::Brain, GenericNN::BRAINNN(nn)) => &nn.nn,
+            _ => panic!("nn does not match the pass"),
+        }
+    }
+
+    fn nn_mut(self, nn: &mut GenericNN) -> &mut BaseNN {
+        match (self, nn) {
+            (Pass::Inward, GenericNN::BLOCKNN(nn)) => &mut nn.inward_nn.nn,
+            (Pass::Outward, GenericNN::BLOCKNN(nn)) => &mut nn.outward_nn.nn,
+            (Pass::Brain, GenericNN::BRAINNN(nn)) => &mut nn.nn,
+            _ => panic!("nn does not match the pass"),
+        }
+    }
+}
+
+/// stacked NNs of the layers of a chunk, keyed by the pass and the ids of the batched NNs
+#[derive(Debug, Default)]
+struct ChunkBatches {
+    /// batches used in the last frame
+    last: HashMap<(Pass, Vec<usize>), NNBatches>,
+    /// batches used in this frame
+    current: HashMap<(Pass, Vec<usize>), NNBatches>,
+}
+
+impl ChunkBatches {
+    /// batches of the NNs `ids` in `pass`, `nns` are stacked if they are not in the last frame
+    fn get<'a>(
+        &mut self,
+        pass: Pass,
+        ids: Vec<usize>,
+        nns: impl FnOnce() -> Vec<&'a BaseNN>,
+    ) -> &NNBatches {
+        let key = (pass, ids);
+        let batches = self
+            .last
+            .remove(&key)
+            .unwrap_or_else(|| NNBatches::new(&nns()));
+        self.current.entry(key).or_insert(batches)
+    }
+
+    /// drop batches of layers that are not passed in this frame
+    fn end_frame(&mut self) {
+        self.last = std::mem::take(&mut self.current);
     }
 }
 
+/// forward the i-th input through the network of the i-th nn in `pass`.
+///
+/// feed-forward networks run as batches, see `ChunkBatches`,
+/// recurrent networks run one by one, since their state is updated in each step
+fn forward_layer(
+    nnvec: &mut [Option<&mut GenericNN>],
+    ids: &[usize],
+    inputs: Vec<Array1<f32>>,
+    pass: Pass,
+    batches: &mut ChunkBatches,
+) -> Vec<Array1<f32>> {
+    let mut outputs: Vec<Option<Array1<f32>>> = vec![None; ids.len()];
+    let mut batch_idx = Vec::new();
+    let mut batch_inputs = Vec::new();
+    for (idx, (&nn_id, input)) in ids.iter().zip(inputs).enumerate() {
+        let nn = pass.nn_mut(chunk_nn(nnvec, nn_id));
+        if nn.recurrent.is_some() {
+            outputs[idx] = Some(nn.step(input));
+        } else {
+            batch_idx.push(idx);
+            batch_inputs.push(input);
+        }
+    }
+
+    let nnvec = &*nnvec;
+    let batch_ids = batch_idx.iter().map(|&idx| ids[idx]).collect();
+    let stacked = batches.get(pass, batch_ids, || {
+        batch_idx
+            .iter()
+            .map(|&idx| pass.nn(chunk_nn_ref(nnvec, ids[idx])))
+            .collect()
+    });
+    for (idx, output) in batch_idx.iter().zip(stacked.forward(&batch_inputs)) {
+        outputs[*idx] = Some(output);
+    }
+    // unwrap since each output is either stepped or batched
+    outputs.into_iter().map(Option::unwrap).collect()
+}
+
 /// Pass the signal from the leaf to the root layer by layer
 ///
 /// bulk_idx can not be 0
 fn inward_bulk_pass(
-    grouped_signal: &mut Vec<Vec<&mut InwardNNInputSignalUnit>>,
-    nnvec: &mut Vec<GenericNN>,
+    grouped_signal: &mut [Vec<&mut InwardNNInputSignalUnit>],
+    parents: &[Vec<usize>],
+    nnvec: &mut [Option<&mut GenericNN>],
     bulk_idx: usize,
 ) {
     if bulk_idx == 0 {
@@ -105,13 +434,9 @@ fn inward_bulk_pass(
     let passed_layer: &mut Vec<&mut InwardNNInputSignalUnit> = &mut left[bulk_idx - 1];
     let current_layer: &mut Vec<&mut InwardNNInputSignalUnit> = &mut right[0];
 
-    // TODO: parallel this for loop
-    for unit in current_layer {
-        if let GenericNN::BLOCKNN(nn) = &mut nnvec[unit.nn_id] {
-            passed_layer
-                .iter_mut()
-                .find(|u| u.nn_id == unit.parent_nn_id)
-                .unwrap()
+    for (unit, &parent) in current_layer.iter().zip(&parents[bulk_idx]) {
+        if let GenericNN::BLOCKNN(nn) = chunk_nn(nnvec, unit.nn_id) {
+            passed_layer[parent]
                 .get_signal_mut()
                 .push_child_signal(nn.get_inward_output(&unit.signal), unit.anchor_pos);
         } else {
@@ -125,16 +450,14 @@ fn inward_bulk_pass(
 
 /// pass the signal from last inward layer to brain
 fn brain_pass(
-    brain_signal: &mut Vec<&mut BrainSignalUnit>,
-    current_layer: &Vec<&mut InwardNNInputSignalUnit>,
-    nnvec: &mut Vec<GenericNN>,
+    brain_signal: &mut [&mut BrainSignalUnit],
+    current_layer: &[&mut InwardNNInputSignalUnit],
+    parents: &[usize],
+    nnvec: &mut [Option<&mut GenericNN>],
 ) {
-    for unit in current_layer {
-        if let GenericNN::BLOCKNN(nn) = &mut nnvec[unit.nn_id] {
-            brain_signal
-                .iter_mut()
-                .find(|u: &&mut &mut BrainSignalUnit| u.nn_id == unit.parent_nn_id)
-                .unwrap()
+    for (unit, &parent) in current_layer.iter().zip(parents) {
+        if let GenericNN::BLOCKNN(nn) = chunk_nn(nnvec, unit.nn_id) {
+            brain_signal[parent]
                 .get_signal_mut()
                 .push_child_signal(nn.get_inward_output(&unit.signal), unit.anchor_pos);
         } else {
@@ -149,14 +472,13 @@ fn brain_pass(
 /// run brain_nn and start outward pass
 fn brain_forward(
     brain_signal: &Vec<&mut BrainSignalUnit>,
-    nnvec: &mut Vec<GenericNN>,
-    outward_passes: &mut Vec<Array1<f32>>,
+    nnvec: &mut [Option<&mut GenericNN>],
+    outward_passes: &mut [Option<Array1<f32>>],
 ) {
     for signal in brain_signal {
-        if let Some(GenericNN::BRAINNN(brain)) = nnvec.get(signal.nn_id) {
-            // println!("{:#?}",signal.signal);
+        if let GenericNN::BRAINNN(brain) = chunk_nn(nnvec, signal.nn_id) {
             // store forward result
-            outward_passes[signal.nn_id] = brain.forward(&signal.signal);
+            outward_passes[signal.nn_id] = Some(brain.forward(&signal.signal));
         } else {
             panic!()
         }
@@ -164,20 +486,23 @@ fn brain_forward(
 }
 
 fn outward_bulk_pass(
-    grouped_signal: &mut Vec<Vec<&mut InwardNNInputSignalUnit>>,
-    nnvec: &mut Vec<GenericNN>,
+    grouped_signal: &[Vec<&mut InwardNNInputSignalUnit>],
+    nnvec: &mut [Option<&mut GenericNN>],
     bulk_idx: usize,
     outputs: &mut Vec<(Entity, f32, f32)>,
-    outward_passes: &mut Vec<Array1<f32>>,
+    outward_passes: &mut [Option<Array1<f32>>],
 ) {
     let current_layer = &grouped_signal[bulk_idx];
 
-    // TODO: parallel this loop
     for unit in current_layer {
-        if let GenericNN::BLOCKNN(nn) = &mut nnvec[unit.nn_id] {
-            // get result from parent and write output back
-            let a = nn.get_outward_output(&outward_passes[unit.parent_nn_id]);
-            outward_passes[unit.nn_id] = a.slice(s![..DL]).map(|x| *x).clone();
+        if let GenericNN::BLOCKNN(nn) = chunk_nn(nnvec, unit.nn_id) {
+            // get result from parent and write output back,
+            // a parent without result passes zeros
+            let a = match &outward_passes[unit.parent_nn_id] {
+                Some(parent_pass) => nn.get_outward_output(parent_pass),
+                None => nn.get_outward_output(&Array1::<f32>::zeros(DL)),
+            };
+            outward_passes[unit.nn_id] = Some(a.slice(s![..DL]).map(|x| *x).clone());
             // push result
             outputs.push((unit.entity_id, a[DL], a[DL + 1]));
         } else {
@@ -185,3 +510,124 @@ fn outward_bulk_pass(
         }
     }
 }
+
+#[cfg(test)]
+mod resource_test {
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::{
+        blob::block::{BlockDepth, ParentAnchor},
+        brain::{
+            neuron::{BlockNN, BrainNN},
+            signal::{BrainSignal, InwardNNInputSignal},
+        },
+        config::NNConfig,
+    };
+
+    /// blobs with a brain, two blocks at depth 1 and a block at depth 2,
+    /// NNs of blobs are interleaved in `nnvec`
+    fn population(blobs: usize, config: &NNConfig) -> (BevyBlockNeurons, SignalHandler) {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut bbn = BevyBlockNeurons::default();
+        let mut handler = SignalHandler::default();
+        for blob in 0..blobs {
+            bbn.nnvec.push(GenericNN::BRAINNN(BrainNN::new(config, &mut rng)));
+            handler.push_brain(
+                BrainSignal::default().with_blob_info([blob as f32, 1.0], [0.5, -0.5], 2.0),
+                blob,
+            );
+        }
+        let mut entity = 0;
+        for (depth, parent_offset) in [(1, 0), (1, 0), (2, blobs)] {
+            for blob in 0..blobs {
+                let nn_id = bbn.nnvec.len();
+                bbn.nnvec.push(GenericNN::BLOCKNN(BlockNN::new(config, &mut rng)));
+                let signal = InwardNNInputSignal::default().with_joint_singal((
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                ));
+                handler.push_inward(
+                    signal,
+                    nn_id,
+                    parent_offset + blob,
+                    &BlockDepth(depth),
+                    &ParentAnchor(Some(nn_id / blobs % 4)),
+                    Entity::from_raw(entity),
+                );
+                entity += 1;
+            }
+        }
+        (bbn, handler)
+    }
+
+    #[test]
+    fn test_chunked_outputs() {
+        let config = NNConfig::default();
+        let (mut serial, handler) = population(7, &config);
+        let mut outputs = serial.get_outputs_chunked(handler, 1, false);
+        outputs.sort_by_key(|output| output.0);
+        assert_eq!(outputs.len(), 21);
+        let serial_nn = serde_json::to_string(&serial.nnvec).unwrap();
+
+        let modes = [(2, false), (3, false), (7, false), (16, false), (1, true), (3, true)];
+        for (chunks, batched) in modes {
+            let (mut bbn, handler) = population(7, &config);
+            let mut chunked = bbn.get_outputs_chunked(handler, chunks, batched);
+            chunked.sort_by_key(|output| output.0);
+            assert_eq!(chunked, outputs);
+            // state kept in NNs for the outward pass is the same as well
+            assert_eq!(serde_json::to_string(&bbn.nnvec).unwrap(), serial_nn);
+        }
+    }
+
+    #[test]
+    fn test_recurrent_outputs() {
+        let config = NNConfig {
+            recurrent: true,
+            ..Default::default()
+        };
+        // the second frame passes the same signals with the state of the first
+        let frames = |chunks, batched| {
+            let (mut bbn, handler) = population(7, &config);
+            let mut outputs = Vec::new();
+            for handler in [handler, population(7, &config).1] {
+                let mut frame = bbn.get_outputs_chunked(handler, chunks, batched);
+                frame.sort_by_key(|output| output.0);
+                outputs.push(frame);
+            }
+            outputs
+        };
+        let serial = frames(1, false);
+        assert_ne!(serial[0], serial[1]);
+        assert_eq!(frames(3, false), serial);
+        assert_eq!(frames(3, true), serial);
+    }
+
+    #[test]
+    fn test_batch_cache() {
+        let config = NNConfig::default();
+        let frame = |bbn: &mut BevyBlockNeurons, batched| {
+            let mut outputs = bbn.get_outputs_chunked(population(7, &config).1, 3, batched);
+            outputs.sort_by_key(|output| output.0);
+            outputs
+        };
+        let (mut serial, _) = population(7, &config);
+        let (mut bbn, _) = population(7, &config);
+
+        // NNs stacked in the first frame are passed in the next
+        let first = frame(&mut bbn, true);
+        assert_eq!(frame(&mut bbn, true), first);
+        assert_eq!(frame(&mut serial, false), first);
+
+        // changed NNs are stacked again
+        serial.nnvec.swap(7, 8);
+        bbn.nnvec.swap(7, 8);
+        bbn.forget_batches();
+        let second = frame(&mut bbn, true);
+        assert_ne!(second, first);
+        assert_eq!(frame(&mut serial, false), second);
+    }
+}
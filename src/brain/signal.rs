@@ -16,20 +16,12 @@ const DL: usize = OUTWARD_NN_PARENT_INPUT_LEN;
 
 // TODO: test correctness of signal
 /// `SignalHandler` handles input signal from bevy
+#[derive(Default)]
 pub struct SignalHandler {
     pub inward_signal_vec: Vec<InwardNNInputSignalUnit>,
     pub brain_signal_vec: Vec<BrainSignalUnit>,
 }
 
-impl Default for SignalHandler {
-    fn default() -> Self {
-        Self {
-            inward_signal_vec: Vec::<InwardNNInputSignalUnit>::new(),
-            brain_signal_vec: Vec::<BrainSignalUnit>::new(),
-        }
-    }
-}
-
 impl SignalHandler {
     pub fn inward_len(&self) -> usize {
         self.inward_signal_vec.len()
@@ -70,7 +62,7 @@ impl SignalHandler {
         Vec<Vec<&mut InwardNNInputSignalUnit>>,
         Vec<&mut BrainSignalUnit>,
     ) {
-        self.inward_signal_vec.sort_by(|a, b| a.depth.cmp(&b.depth));
+        self.inward_signal_vec.sort_by_key(|a| a.depth);
 
         (
             self.inward_signal_vec
@@ -94,24 +86,72 @@ impl SignalHandler {
         entity_id: Entity,
     ) {
         self.inward_signal_vec.push(InwardNNInputSignalUnit {
-            signal: signal,
-            nn_id: nn_id,
-            parent_nn_id: parent_nn_id,
+            signal,
+            nn_id,
+            parent_nn_id,
             depth: depth.0 as usize,
             // inward nn must have parent anchor so unwarp
             anchor_pos: anchor.0.unwrap(),
-            entity_id: entity_id,
+            entity_id,
         })
     }
 
     pub fn push_brain(&mut self, signal: BrainSignal, nn_id: usize) {
         self.brain_signal_vec.push(BrainSignalUnit {
-            signal: signal,
-            nn_id: nn_id,
+            signal,
+            nn_id,
         })
     }
 }
 
+/// index of the parent of each inward signal in the layer above, grouped as `grouped_signal`.
+///
+/// parent of `grouped_signal[d][i]` is `grouped_signal[d - 1][parents[d][i]]`,
+/// parent of the first layer is `brain_signal[parents[0][i]]`.
+///
+/// The table is built in one pass through a position table by nn id,
+/// so signals are routed without searching the layer above for each child.
+pub fn parent_indices(
+    grouped_signal: &[Vec<&mut InwardNNInputSignalUnit>],
+    brain_signal: &[&mut BrainSignalUnit],
+    nn_len: usize,
+) -> Vec<Vec<usize>> {
+    // position of each nn in its layer
+    let mut position = vec![usize::MAX; nn_len];
+    for (idx, unit) in brain_signal.iter().enumerate() {
+        position[unit.nn_id] = idx;
+    }
+
+    let mut parents = Vec::with_capacity(grouped_signal.len());
+    for (depth_idx, layer) in grouped_signal.iter().enumerate() {
+        let layer_parents: Vec<usize> = layer
+            .iter()
+            .map(|unit| {
+                let idx = position[unit.parent_nn_id];
+                // position of a nn in other layers is stale
+                let parent_id = if depth_idx == 0 {
+                    brain_signal.get(idx).map(|u| u.nn_id)
+                } else {
+                    grouped_signal[depth_idx - 1].get(idx).map(|u| u.nn_id)
+                };
+                if parent_id != Some(unit.parent_nn_id) {
+                    panic!(
+                        "parent {} of nn {} is not in the layer above",
+                        unit.parent_nn_id, unit.nn_id
+                    )
+                }
+                idx
+            })
+            .collect();
+        parents.push(layer_parents);
+
+        for (idx, unit) in layer.iter().enumerate() {
+            position[unit.nn_id] = idx;
+        }
+    }
+    parents
+}
+
 pub struct InwardNNInputSignalUnit {
     pub signal: InwardNNInputSignal,
     pub nn_id: usize,
@@ -163,6 +203,9 @@ pub struct InwardNNInputSignal {
     ///
     /// Order of children inputs depends on children's parent_anchor.
     children_input: Array2<f32>,
+
+    /// output of the blob's oscillator at this block, see `cpg.rs`
+    oscillator: f32,
 }
 
 impl Default for InwardNNInputSignal {
@@ -177,6 +220,7 @@ impl Default for InwardNNInputSignal {
             joint_ang_pos: 0.0,
             joint_ang_v: 0.0,
             children_input: Array2::<f32>::zeros((4, CL)),
+            oscillator: 0.0,
         }
     }
 }
@@ -201,6 +245,11 @@ impl InwardNNInputSignal {
         self
     }
 
+    pub fn with_oscillator(mut self, value: f32) -> Self {
+        self.oscillator = value;
+        self
+    }
+
     pub fn push_child_signal(&mut self, signal: Array1<f32>, anchor: usize) {
         // anchor must in 0..=3
         match anchor {
@@ -222,7 +271,7 @@ impl InwardNNInputSignal {
 
         let vect_data = self.collision_vect.iter().cloned();
         // flatten children_data
-        let children_data = self.children_input.rows().into_iter().flatten().map(|&x| x);
+        let children_data = self.children_input.rows().into_iter().flatten().copied();
 
         let all_data = bool_data
             .into_iter()
@@ -232,7 +281,8 @@ impl InwardNNInputSignal {
             .chain(std::iter::once(self.cur_motor_v))
             .chain(std::iter::once(self.joint_ang_pos))
             .chain(std::iter::once(self.joint_ang_v))
-            .chain(children_data);
+            .chain(children_data)
+            .chain(std::iter::once(self.oscillator));
 
         Array1::from_iter(all_data)
     }
@@ -303,6 +353,10 @@ pub struct BrainSignal {
 
     blob_mass_center: [f32; 2],
     blob_speed: [f32; 2],
+    blob_power: f32,
+
+    /// output of the blob's oscillator, see `cpg.rs`
+    oscillator: f32,
 }
 
 impl Default for BrainSignal {
@@ -315,6 +369,8 @@ impl Default for BrainSignal {
             children_input: Array2::<f32>::zeros((4, CL)),
             blob_mass_center: [0.0, 0.0],
             blob_speed: [0.0, 0.0],
+            blob_power: 0.0,
+            oscillator: 0.0,
         }
     }
 }
@@ -330,9 +386,15 @@ impl BrainSignal {
         self
     }
 
-    pub fn with_blob_info(mut self, center: [f32; 2], speed: [f32; 2]) -> Self {
+    pub fn with_blob_info(mut self, center: [f32; 2], speed: [f32; 2], power: f32) -> Self {
         self.blob_mass_center = center;
         self.blob_speed = speed;
+        self.blob_power = power;
+        self
+    }
+
+    pub fn with_oscillator(mut self, value: f32) -> Self {
+        self.oscillator = value;
         self
     }
 
@@ -357,7 +419,7 @@ impl BrainSignal {
 
         let vect_data = self.collision_vect.iter().cloned();
         // flatten children_data
-        let children_data = self.children_input.rows().into_iter().flatten().map(|&x| x);
+        let children_data = self.children_input.rows().into_iter().flatten().copied();
         let mass_center_data = self.blob_mass_center.iter().cloned();
         let speed_data = self.blob_speed.iter().cloned();
 
@@ -367,7 +429,9 @@ impl BrainSignal {
             .chain(std::iter::once(self.collision_mag))
             .chain(children_data)
             .chain(mass_center_data)
-            .chain(speed_data);
+            .chain(speed_data)
+            .chain(std::iter::once(self.blob_power))
+            .chain(std::iter::once(self.oscillator));
 
         Array1::from_iter(all_data)
     }
@@ -0,0 +1,163 @@
+//! command line interface
+//!
+//! subcommands:
+//! - `train`, start a new training, optionally with blobs of checkpoints
+//! - `resume <checkpoint>`, continue training exactly where the checkpoint left off
+//! - `replay <checkpoint>`, simulate a checkpoint without evolution
+//! - `inspect <checkpoint>`, print population stats, geno trees and nn shapes
+//! - `lineage <file>`, print the phylogenetic tree in a lineage file
+//! - `specimen <checkpoint> <output>`, save a single blob of a checkpoint
+//!
+//! Running without subcommand is the same as `train`.
+
+use clap::{Args, Parser, Subcommand};
+use rand::prelude::*;
+
+use crate::config::{ConfigError, EvoConfig, FitnessKind, SelectionMode, TrainingMode};
+use crate::consts::CONFIG_PATH;
+use crate::io::{lineage::LineageFormat, retention::new_run_id};
+
+#[derive(Parser, Debug)]
+#[command(name = "evosim", version, about = "evolving blobs that learn to move")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// flags for `train` if no subcommand is given
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// start a new training with random population,
+    /// or with blobs of checkpoints if `--load` is given
+    Train {
+        /// checkpoints to start with, blobs of several files are merged into one population
+        #[arg(long)]
+        load: Vec<String>,
+        /// only load the best blobs by recorded fitness
+        #[arg(long, requires = "load")]
+        top: Option<usize>,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// continue training from a checkpoint,
+    /// with the config saved in the checkpoint unless `--config` is given
+    Resume {
+        /// exported file or checkpoint to load
+        checkpoint: String,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// simulate a checkpoint without evolution
+    Replay {
+        /// exported file or checkpoint to load
+        checkpoint: String,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// print population stats, geno trees and nn shapes of a checkpoint
+    Inspect {
+        /// exported file or checkpoint to load
+        checkpoint: String,
+    },
+    /// print the phylogenetic tree in a lineage file
+    Lineage {
+        /// `lineage.jsonl` in the export directory
+        file: String,
+        /// output format
+        #[arg(long, value_enum, default_value = "dot")]
+        format: LineageFormat,
+        /// genome id to trace, the best blob of the last iteration if not given
+        #[arg(long)]
+        id: Option<usize>,
+        /// print all genomes instead of the ancestors of one genome
+        #[arg(long)]
+        all: bool,
+    },
+    /// save a single blob of a checkpoint as a specimen file
+    Specimen {
+        /// exported file or checkpoint to load
+        checkpoint: String,
+        /// specimen file to save, encoding is chosen by file extension
+        output: String,
+        /// genome id of the blob, the best blob by recorded fitness if not given
+        #[arg(long)]
+        id: Option<usize>,
+    },
+}
+
+/// flags shared by all subcommands that run the simulation
+#[derive(Args, Debug, Clone)]
+pub struct RunArgs {
+    /// config file, it is an error if the file can not be loaded.
+    /// `./config.json` if not given, default config is used if that does not exist
+    #[arg(long)]
+    pub config: Option<String>,
+    /// random seed
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// iterations to run before exit, only used in headless mode.
+    ///
+    /// counted from the start of the run, including iterations before resuming
+    #[arg(long)]
+    pub iterations: Option<usize>,
+    /// directory to save checkpoints
+    #[arg(long)]
+    pub output: Option<String>,
+    /// training mode
+    #[arg(long, value_enum)]
+    pub mode: Option<TrainingMode>,
+    /// fitness to select blobs with
+    #[arg(long, value_enum)]
+    pub fitness: Option<FitnessKind>,
+    /// how survivers are selected
+    #[arg(long, value_enum)]
+    pub selection: Option<SelectionMode>,
+    /// run without window and renderer
+    #[arg(long)]
+    pub headless: bool,
+}
+
+impl RunArgs {
+    /// load config file and overwrite it with command line flags
+    ///
+    /// `saved` config of a checkpoint is used instead of the default file
+    /// if `--config` is not given
+    pub fn load_config(&self, saved: Option<EvoConfig>) -> Result<EvoConfig, ConfigError> {
+        let mut config = match (&self.config, saved) {
+            (None, Some(saved)) => saved,
+            (Some(path), _) => EvoConfig::load(path)?,
+            (None, None) => EvoConfig::load_or_default(CONFIG_PATH)?,
+        };
+        if let Some(seed) = self.seed {
+            config.train.seed = Some(seed);
+        }
+        if let Some(iterations) = self.iterations {
+            config.train.headless_iterations = iterations;
+        }
+        if let Some(output) = &self.output {
+            // paths are joined by string concatenation
+            config.io.export_path = if output.ends_with('/') {
+                output.clone()
+            } else {
+                format!("{}/", output)
+            };
+        }
+        if let Some(mode) = self.mode {
+            config.train.mode = mode;
+        }
+        if let Some(fitness) = self.fitness {
+            config.train.fitness = Some(fitness);
+        }
+        if let Some(selection) = self.selection {
+            config.train.selection = selection;
+        }
+        // chosen once before the app is built, plugins only read them
+        let seed = *config.train.seed.get_or_insert_with(|| thread_rng().gen());
+        config.io.run_id.get_or_insert_with(|| new_run_id(Some(seed)));
+        Ok(config)
+    }
+}
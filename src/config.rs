@@ -0,0 +1,858 @@
+//! runtime configuration of the simulation
+//!
+//! `EvoConfig` is loaded from a json file and inserted as a bevy resource,
+//! so that experiments can be changed without recompiling.
+//! Missing fields in the file fall back to the default values.
+//!
+//! Constants that are part of the program structure (signal length, keycodes...)
+//! are still in `consts.rs`.
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use bevy::prelude::*;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::brain::nn::Activation;
+use crate::consts::*;
+use crate::io::export::write_atomic;
+
+/// mutation preset used if the config file does not choose one
+#[cfg(feature = "demo")]
+pub const DEFAULT_MUTATE_PRESET: &str = "demo";
+#[cfg(not(feature = "demo"))]
+pub const DEFAULT_MUTATE_PRESET: &str = "move";
+
+/// all the runtime configurations, as a bevy resource
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvoConfig {
+    pub timestep: TimestepConfig,
+    pub world: WorldConfig,
+    pub joint: JointConfig,
+    pub physics: PhysicsConfig,
+    pub energy: EnergyConfig,
+    pub species: SpeciesConfig,
+    pub geno: GenoConfig,
+    pub nn: NNConfig,
+    pub cpg: CpgConfig,
+    /// name of the mutation parameter set, `demo` or `move`
+    pub mutate_preset: String,
+    /// overwrite the preset if set
+    #[serde(default)]
+    pub mutate: Option<MutateConfig>,
+    pub train: TrainConfig,
+    pub io: IOConfig,
+    pub log: LogConfig,
+    pub profile: ProfileConfig,
+}
+
+impl Default for EvoConfig {
+    fn default() -> Self {
+        Self {
+            timestep: TimestepConfig::default(),
+            world: WorldConfig::default(),
+            joint: JointConfig::default(),
+            physics: PhysicsConfig::default(),
+            energy: EnergyConfig::default(),
+            species: SpeciesConfig::default(),
+            geno: GenoConfig::default(),
+            nn: NNConfig::default(),
+            cpg: CpgConfig::default(),
+            mutate_preset: DEFAULT_MUTATE_PRESET.to_string(),
+            mutate: MutateConfig::preset(DEFAULT_MUTATE_PRESET),
+            train: TrainConfig::default(),
+            io: IOConfig::default(),
+            log: LogConfig::default(),
+            profile: ProfileConfig::default(),
+        }
+    }
+}
+
+impl EvoConfig {
+    /// load config from a json file and resolve the mutation preset
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let file_str = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let config: EvoConfig = serde_json::from_str(&file_str).map_err(ConfigError::Parse)?;
+        let config = config.resolved()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// load config from a json file, use default config if the file does not exist
+    pub fn load_or_default(path: &str) -> Result<Self, ConfigError> {
+        if Path::new(path).exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// fill the mutation parameters from `mutate_preset` if they are not given
+    pub fn resolved(mut self) -> Result<Self, ConfigError> {
+        if self.mutate.is_none() {
+            self.mutate = Some(
+                MutateConfig::preset(&self.mutate_preset)
+                    .ok_or_else(|| ConfigError::UnknownPreset(self.mutate_preset.clone()))?,
+            );
+        }
+        Ok(self)
+    }
+
+    /// check values which would break the training
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let train = &self.train;
+        if train.population == 0 {
+            return Err(ConfigError::Invalid(
+                "train.population should be positive".to_string(),
+            ));
+        }
+        if !(train.survival_rate > 0.0 && train.survival_rate <= 1.0) {
+            return Err(ConfigError::Invalid(format!(
+                "train.survival_rate should be in (0, 1], got {}",
+                train.survival_rate
+            )));
+        }
+        if !(0.0..=1.0).contains(&train.hybrid_rate) {
+            return Err(ConfigError::Invalid(format!(
+                "train.hybrid_rate should be in [0, 1], got {}",
+                train.hybrid_rate
+            )));
+        }
+        Ok(())
+    }
+
+    /// mutation parameters, config is always resolved after loading
+    pub fn mutate(&self) -> &MutateConfig {
+        self.mutate
+            .as_ref()
+            .expect("mutate config should be resolved after loading")
+    }
+
+    /// world width and height of the current training mode
+    pub fn world_size(&self) -> [f32; 2] {
+        match self.train.mode {
+            TrainingMode::Swim => self.world.swim,
+            TrainingMode::Walk => self.world.walk,
+        }
+    }
+
+    /// write the resolved config to file, so that a run can be reproduced
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let file_str = serde_json::to_string_pretty(self)?;
+        write_atomic(path, file_str.as_bytes())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    UnknownPreset(String),
+    /// value out of its range
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "can not read config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "can not parse config file: {}", e),
+            ConfigError::UnknownPreset(name) => write!(f, "unknown mutate preset: {}", name),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimestepConfig {
+    pub rapier_dt: f32,
+    pub rapier_substeps: usize,
+}
+
+impl Default for TimestepConfig {
+    fn default() -> Self {
+        Self {
+            rapier_dt: 1.0 / 60.0,
+            rapier_substeps: 1,
+        }
+    }
+}
+
+/// world size `[width, height]` for each training mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldConfig {
+    pub walk: [f32; 2],
+    pub swim: [f32; 2],
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            walk: [100000.0, 2000.0],
+            swim: [10000.0, 10000.0],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JointConfig {
+    pub motor_stiffness: f32,
+    pub motor_damping: f32,
+    pub enable_contacts: bool,
+    pub motor_max_target_v: f32,
+}
+
+impl Default for JointConfig {
+    fn default() -> Self {
+        Self {
+            motor_stiffness: 10.0,
+            motor_damping: 0.0,
+            enable_contacts: false,
+            motor_max_target_v: 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    /// drag coefficient in fluid simulation
+    pub drag_coeff: f32,
+    pub density: f32,
+    pub damping_linear: f32,
+    pub damping_angular: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            drag_coeff: 1.0,
+            density: 1.0,
+            damping_linear: DEFAULT_DAMPING_LINEAR,
+            damping_angular: DEFAULT_DAMPING_ANGULAR,
+        }
+    }
+}
+
+/// energy cost of blobs, see `update_blob_energy`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergyConfig {
+    /// base cost of each block per second
+    pub metabolic_cost: f32,
+    /// fitness is reduced by `energy_penalty * energy`
+    pub energy_penalty: f32,
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        Self {
+            metabolic_cost: 0.0,
+            energy_penalty: 0.0,
+        }
+    }
+}
+
+/// speciation for `species` selection, see `species.rs`
+///
+/// compatibility distance between two blobs is
+/// `ted_weight * tree_edit_distance + nn_weight * mean weight difference`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeciesConfig {
+    pub ted_weight: f32,
+    /// weight difference is averaged over blocks at the same position of both trees
+    pub nn_weight: f32,
+    /// blobs closer than this distance to the representative join the species
+    pub threshold: f32,
+    /// a species extinct if its best fitness does not improve in this many iterations,
+    /// the species of the best blob never extinct
+    pub stagnation_limit: u32,
+}
+
+impl Default for SpeciesConfig {
+    fn default() -> Self {
+        Self {
+            ted_weight: 1.0,
+            nn_weight: 3.0,
+            threshold: 4.0,
+            stagnation_limit: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenoConfig {
+    /// max recursion depth of Geno type
+    pub max_depth: u32,
+    pub default_block_size: [f32; 2],
+    /// probablity of a random node to exist
+    pub rand_node_not_none: f64,
+    pub rand_size_scaler: [f32; 2],
+    /// range of random oscillator frequency, in Hz of simulated time,
+    /// also the bounds of its mutation
+    pub cpg_frequency: [f32; 2],
+    /// range of random oscillator amplitude, also the bounds of its mutation
+    pub cpg_amplitude: [f32; 2],
+}
+
+impl Default for GenoConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            default_block_size: [50.0, 50.0],
+            rand_node_not_none: 0.9,
+            rand_size_scaler: [0.5, 2.0],
+            cpg_frequency: [0.2, 2.0],
+            cpg_amplitude: [0.0, 1.0],
+        }
+    }
+}
+
+/// central pattern generator, see `cpg.rs`.
+///
+/// oscillators are always in the genotype, this decides whether NNs get their output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CpgConfig {
+    /// oscillator output as an input of brains, the input is zero if disabled
+    pub enabled: bool,
+    /// oscillator output as an input of each block as well
+    pub inward: bool,
+    /// blocks oscillate with the phase of their parent plus their phase offset,
+    /// otherwise all blocks oscillate with the phase of the blob
+    pub coupled: bool,
+}
+
+impl Default for CpgConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            inward: true,
+            coupled: true,
+        }
+    }
+}
+
+/// Neural network config.
+///
+/// Input and output width are decided by signals,
+/// only hidden layers can be configured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NNConfig {
+    /// node count of each hidden layer
+    pub hidden_layers: Vec<usize>,
+    /// ReLU will make all output positive
+    pub activation: Activation,
+    /// feed the first hidden layer back to itself in the next frame,
+    /// so that NNs have memory, see `RecurrentLayer`
+    pub recurrent: bool,
+    /// pass each depth layer of blobs as batches of same-shaped NNs.
+    ///
+    /// weights are stacked once and kept until NNs change, e.g. by mutation,
+    /// then each layer is one batched product, see `cargo bench --bench nn`.
+    /// copies of a NN, e.g. unmutated NNs of offspring, share their weights.
+    /// NNs without others of the same topology and node activations are passed alone,
+    /// so the gain shrinks as topology mutation makes the population diverge
+    pub batched: bool,
+}
+
+impl Default for NNConfig {
+    fn default() -> Self {
+        Self {
+            hidden_layers: vec![8],
+            activation: Activation::Sigmoid,
+            recurrent: false,
+            batched: true,
+        }
+    }
+}
+
+impl NNConfig {
+    pub fn inward_shape(&self) -> Vec<usize> {
+        self.shape(INWARD_NN_INPUT_LEN, INWARD_NN_OUTPUT_LEN)
+    }
+
+    pub fn outward_shape(&self) -> Vec<usize> {
+        self.shape(OUTWARD_NN_INPUT_LEN, OUTWARD_NN_OUTPUT_LEN)
+    }
+
+    pub fn brain_shape(&self) -> Vec<usize> {
+        self.shape(BRAIN_NN_INPUT_LEN, BRAIN_NN_OUTPUT_LEN)
+    }
+
+    fn shape(&self, input: usize, output: usize) -> Vec<usize> {
+        let mut shape = vec![input];
+        shape.extend(self.hidden_layers.iter());
+        shape.push(output);
+        shape
+    }
+}
+
+/// mutation parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MutateConfig {
+    /// probablity of having tree structure mutate
+    ///
+    /// if the tree structure is going to mutate, maximumly 1 node will mutate
+    /// since single node blob can't lose a node anymore
+    pub tree_structure_prob: f32,
+    /// probablity for the choosen node to gain a child node,
+    /// otherwise the blob gonna lose a limb
+    pub gain_limb_prob: f32,
+    /// max times to retry to add a new limb if last one cause self-conflict
+    ///
+    /// condition of impossible new limb exist (the parent indicator was dropped)
+    pub gain_limb_max_try: u32,
+    /// probablity of having limb size mutate
+    pub block_size_prob: f32,
+    /// probablity for each signle block to mutate
+    ///
+    /// mutation is not garenteed since it might cause self-confliction
+    pub single_block_size_prob: f32,
+    /// scaler for block mutation
+    pub single_block_size_scaler: [f32; 2],
+    /// clamp between this scaler for `default_block_size`
+    pub single_block_size_clamp_scaler: [f32; 2],
+    /// porbablity of a signle joint limit to mutate
+    pub joint_limit_prob: f32,
+    pub joint_limit_min: f32,
+    pub joint_limit_max: f32,
+    /// porbablity of a single nn to mutate
+    pub nn_prob: f32,
+    /// standard deviation for normal distribution mutation
+    pub nn_std: f32,
+    /// probablity of a single weight to mutate after the `BaseNN` is chosen to be mutate.
+    pub nn_weight_prob: f32,
+    /// probablity of a single bias to mutate after the `BaseNN` is chosen to be mutate.
+    pub nn_bias_prob: f32,
+    /// probablity of a new blob in `reproduce` to be the offspring of two survivers,
+    /// otherwise it is a clone of one surviver
+    #[serde(default)]
+    pub crossover_prob: f32,
+    /// max times to retry subtree swap if last one cause self-conflict
+    #[serde(default = "default_crossover_max_try")]
+    pub crossover_max_try: u32,
+    /// probablity of arithmetic nn crossover, otherwise uniform crossover.
+    ///
+    /// nn crossover only happens between parents with the same tree structure
+    #[serde(default)]
+    pub nn_arithmetic_crossover_prob: f32,
+    /// probablity of the oscillator of a blob to mutate,
+    /// frequency, amplitude, phase and phase offsets of all nodes are perturbed
+    #[serde(default)]
+    pub cpg_prob: f32,
+    /// standard deviation of the perturbation, relative for frequency,
+    /// in radians divided by PI for phases
+    #[serde(default)]
+    pub cpg_std: f32,
+    /// probablity of a hidden node to be added after the `BaseNN` is chosen to be mutate,
+    /// same for the following topology mutations, see `mutate_topology`
+    #[serde(default)]
+    pub nn_add_node_prob: f32,
+    #[serde(default)]
+    pub nn_remove_node_prob: f32,
+    #[serde(default)]
+    pub nn_add_layer_prob: f32,
+    #[serde(default)]
+    pub nn_remove_layer_prob: f32,
+    /// probablity of a random node to switch to a random activation
+    #[serde(default)]
+    pub nn_activation_prob: f32,
+    /// max node count of a hidden layer, layers are not added if they would be wider
+    #[serde(default = "default_nn_max_hidden_nodes")]
+    pub nn_max_hidden_nodes: usize,
+    #[serde(default = "default_nn_max_hidden_layers")]
+    pub nn_max_hidden_layers: usize,
+}
+
+fn default_crossover_max_try() -> u32 {
+    10
+}
+
+fn default_nn_max_hidden_nodes() -> usize {
+    16
+}
+
+fn default_nn_max_hidden_layers() -> usize {
+    3
+}
+
+impl MutateConfig {
+    /// get the named mutation parameter set
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "demo" => Some(Self::demo()),
+            "move" => Some(Self::moving()),
+            _ => None,
+        }
+    }
+
+    /// mutate for demo
+    pub fn demo() -> Self {
+        Self {
+            tree_structure_prob: 0.9,
+            gain_limb_prob: 0.5,
+            gain_limb_max_try: 10,
+            block_size_prob: 1.0,
+            single_block_size_prob: 0.5,
+            single_block_size_scaler: [0.9, 1.1],
+            single_block_size_clamp_scaler: [0.5, 2.0],
+            joint_limit_prob: 0.5,
+            joint_limit_min: -PI * 0.9,
+            joint_limit_max: PI * 0.9,
+            nn_prob: 0.5,
+            nn_std: 0.1,
+            nn_weight_prob: 0.8,
+            nn_bias_prob: 0.8,
+            crossover_prob: 0.0,
+            crossover_max_try: 10,
+            nn_arithmetic_crossover_prob: 0.5,
+            cpg_prob: 0.5,
+            cpg_std: 0.1,
+            nn_add_node_prob: 0.1,
+            nn_remove_node_prob: 0.1,
+            nn_add_layer_prob: 0.05,
+            nn_remove_layer_prob: 0.05,
+            nn_activation_prob: 0.1,
+            nn_max_hidden_nodes: 16,
+            nn_max_hidden_layers: 3,
+        }
+    }
+
+    /// mutate for move training
+    pub fn moving() -> Self {
+        Self {
+            tree_structure_prob: 0.05,
+            gain_limb_prob: 0.5,
+            gain_limb_max_try: 10,
+            block_size_prob: 0.25,
+            single_block_size_prob: 0.5,
+            single_block_size_scaler: [0.7, 1.3],
+            single_block_size_clamp_scaler: [0.3, 2.0],
+            joint_limit_prob: 0.1,
+            joint_limit_min: -PI * 0.9,
+            joint_limit_max: PI * 0.9,
+            nn_prob: 0.25,
+            nn_std: 0.15,
+            nn_weight_prob: 0.8,
+            nn_bias_prob: 0.8,
+            crossover_prob: 0.2,
+            crossover_max_try: 10,
+            nn_arithmetic_crossover_prob: 0.5,
+            cpg_prob: 0.25,
+            cpg_std: 0.1,
+            nn_add_node_prob: 0.05,
+            nn_remove_node_prob: 0.05,
+            nn_add_layer_prob: 0.01,
+            nn_remove_layer_prob: 0.01,
+            nn_activation_prob: 0.05,
+            nn_max_hidden_nodes: 16,
+            nn_max_hidden_layers: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TrainingMode {
+    Swim,
+    Walk,
+}
+
+/// built-in fitness functions, see `fitness.rs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum FitnessKind {
+    /// magnitude of moving distance
+    Distance,
+    /// moving distance on x axis
+    XDistance,
+    /// average speed along the moving path
+    Speed,
+    /// moving distance per unit of mass
+    Efficiency,
+    /// how close the blob ends to `target`
+    Target,
+}
+
+/// how survivers are selected at the end of an iteration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionMode {
+    /// tournament on fitness, mixed with survivers drawn by novelty
+    Hybrid,
+    /// NSGA-II non-dominated sorting over `objectives`
+    Nsga2,
+    /// fitness shared within species, each species survives and reproduces by its quota
+    Species,
+}
+
+/// objectives of multi-objective selection, see `nsga2.rs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective {
+    /// score of `TrainFitness`, maximized
+    Fitness,
+    /// energy spent, minimized
+    Energy,
+    /// morphological novelty, maximized
+    Novelty,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrainConfig {
+    /// choose between swim and walk
+    pub mode: TrainingMode,
+    /// fitness to select blobs with,
+    /// `distance` for swim and `x_distance` for walk if not given
+    pub fitness: Option<FitnessKind>,
+    /// target position for `target` fitness
+    pub target: [f32; 2],
+    /// random seed of the run, a random seed is chosen if not given
+    pub seed: Option<u64>,
+    /// population for each training iteration
+    pub population: usize,
+    /// survival rate in `train_move.rs`
+    pub survival_rate: f32,
+    /// how survivers are selected
+    pub selection: SelectionMode,
+    /// objectives for `nsga2` selection
+    pub objectives: Vec<Objective>,
+    /// tournament selection hybrid
+    pub hybrid_rate: f32,
+    /// how strongly novel blobs are preferred in hybrid selection
+    pub hybrid_bias: f64,
+    /// how long a signle iteration, counted in frame
+    pub iteration_length: usize,
+    /// save checkpoint every `checkpoints_length` iterations
+    pub checkpoints_length: usize,
+    /// how many iterations to train before a headless run exits
+    pub headless_iterations: usize,
+    /// limit for population generation area
+    ///
+    /// 100*100 world size with 0.5 ratio result in 50*50 generation area
+    pub scatter_ratio: [f32; 2],
+    /// min distance between two spawn point
+    pub spawn_point_radius: f32,
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        Self {
+            mode: TrainingMode::Swim,
+            fitness: None,
+            target: [0.0, 0.0],
+            seed: None,
+            population: 30,
+            survival_rate: 0.5,
+            selection: SelectionMode::Hybrid,
+            objectives: vec![Objective::Fitness, Objective::Novelty],
+            hybrid_rate: 0.3,
+            hybrid_bias: 4.0,
+            iteration_length: 1000,
+            checkpoints_length: 100,
+            headless_iterations: 1000,
+            scatter_ratio: [0.8, 0.8],
+            spawn_point_radius: 750.0,
+        }
+    }
+}
+
+impl TrainConfig {
+    /// fitness of the run, fall back to the one matches training mode
+    pub fn fitness_kind(&self) -> FitnessKind {
+        self.fitness.unwrap_or(match self.mode {
+            TrainingMode::Swim => FitnessKind::Distance,
+            TrainingMode::Walk => FitnessKind::XDistance,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IOConfig {
+    pub export_path: String,
+    /// prefix of checkpoint names, generated from start time and seed if not given.
+    ///
+    /// saved in checkpoints, so that a resumed run keeps its names
+    pub run_id: Option<String>,
+    /// keep the newest checkpoints of the run, `0` and `keep_best` `0` keep all,
+    /// see `io/retention.rs`
+    pub keep_last: usize,
+    /// also keep the checkpoints with the best fitness
+    pub keep_best: usize,
+    pub load_folder: String,
+    pub load_fname: String,
+    pub load_newest_file: bool,
+    /// checkpoints merged into the loaded population, e.g. islands of other runs
+    pub merge_fnames: Vec<String>,
+    /// only load the best blobs by recorded fitness
+    pub load_top: Option<usize>,
+    /// specimens spawned next to the population by pressing `SPAWN_SPECIMENS_KEYCODE`
+    pub specimens: Vec<SpecimenSpawn>,
+    /// append lineage of all blobs to `lineage.jsonl` in `export_path`
+    /// at the end of each iteration, see `io/lineage.rs`
+    pub save_lineage: bool,
+    /// encoding of saved checkpoints,
+    /// loading picks the encoding by file extension
+    pub checkpoint_format: CheckpointFormat,
+    /// write a row of training metrics to `<run_id>.metrics.<ext>` in `export_path`
+    /// at the end of each iteration, see `io/metrics.rs`
+    pub save_metrics: bool,
+    pub metrics_format: MetricsFormat,
+}
+
+impl Default for IOConfig {
+    fn default() -> Self {
+        Self {
+            export_path: "./export/".to_string(),
+            run_id: None,
+            keep_last: 0,
+            keep_best: 0,
+            load_folder: "./export/".to_string(),
+            load_fname: "./export/2023-07-25T15-28-56.json".to_string(),
+            load_newest_file: true,
+            merge_fnames: Vec::new(),
+            load_top: None,
+            specimens: Vec::new(),
+            save_lineage: true,
+            checkpoint_format: CheckpointFormat::Json,
+            save_metrics: true,
+            metrics_format: MetricsFormat::Csv,
+        }
+    }
+}
+
+/// logging, see `logger.rs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// lowest level to log, `error`, `warn`, `info`, `debug` or `trace`
+    pub level: String,
+    /// extra `EnvFilter` directives, e.g. `wgpu=error,train=debug`,
+    /// `RUST_LOG` overrides both
+    pub filter: String,
+    /// write the log to `<run_id>.log` in `export_path`
+    pub save_log: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            filter: "wgpu=error,naga=warn".to_string(),
+            save_log: true,
+        }
+    }
+}
+
+/// profiling of the per-frame pipeline, see `profile.rs`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    /// record stage times, saved to `<run_id>.profile.jsonl` in `export_path` each iteration
+    pub enabled: bool,
+    /// write all spans to `<run_id>.trace.json` in `export_path`,
+    /// needs cargo feature `profile`
+    pub chrome_trace: bool,
+}
+
+/// training metrics file format, see `io/metrics.rs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsFormat {
+    /// with a header row, `.csv`
+    Csv,
+    /// a JSON object per line, `.jsonl`
+    Jsonl,
+}
+
+impl MetricsFormat {
+    /// file extension, without the leading dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MetricsFormat::Csv => "csv",
+            MetricsFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// specimen file to spawn and where, see `io/specimen.rs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecimenSpawn {
+    pub path: String,
+    /// center of the spawned blob
+    pub pos: [f32; 2],
+}
+
+/// checkpoint file encoding, see `io/checkpoint.rs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckpointFormat {
+    /// human readable, `.json`
+    Json,
+    /// CBOR, `.cbor`
+    Cbor,
+    /// zstd compressed CBOR, `.cbor.zst`
+    CborZstd,
+}
+
+impl CheckpointFormat {
+    /// file extension, without the leading dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CheckpointFormat::Json => "json",
+            CheckpointFormat::Cbor => "cbor",
+            CheckpointFormat::CborZstd => "cbor.zst",
+        }
+    }
+
+    /// format of a file by its extension, `None` if it is not a checkpoint
+    pub fn from_path(path: &str) -> Option<Self> {
+        // `.cbor.zst` before `.cbor`, longer extension first
+        [
+            CheckpointFormat::CborZstd,
+            CheckpointFormat::Cbor,
+            CheckpointFormat::Json,
+        ]
+        .into_iter()
+        .find(|format| path.ends_with(&format!(".{}", format.extension())))
+    }
+}
+
+#[cfg(test)]
+mod config_test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = EvoConfig::default();
+        config.validate().unwrap();
+
+        config.train.survival_rate = 0.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.train.survival_rate = 1.0;
+        config.validate().unwrap();
+
+        config.train.hybrid_rate = 1.5;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.train.hybrid_rate = 0.0;
+        config.validate().unwrap();
+
+        config.train.population = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+}
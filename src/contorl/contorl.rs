@@ -6,22 +6,32 @@ use bevy::prelude::*;
 use rand::prelude::*;
 
 use crate::{
-    blob::geno_blob_builder::{BlobGeno, GenoBlobBuilder},
+    blob::{
+        geno_blob_builder::{BlobGeno, GenoBlobBuilder},
+        lineage::Genealogy,
+    },
     brain::resource::BevyBlockNeurons,
+    config::EvoConfig,
     consts::*,
     contorl::{
-        resource::{Frames, TED},
-        train_move::{log_train_move_swim, train_move_swim},
-        update::{update_crowding_distance, update_iteration_frames},
+        fitness::TrainFitness,
+        resource::{EvoRng, Frames, TED},
+        train_move::log_train_move,
+        update::{update_iteration_frames, update_novelty},
     },
+    io::import::{overwrite, LoadedCheckpoint},
     logger_info,
+};
+// systems of training, not used by the demo
+#[cfg(feature = "move")]
+use crate::{
+    contorl::{species::Speciation, train_move::train_move},
     mutate::mutate::mutate_and_refresh_after_train,
 };
 
 use super::{
     resource::TrainMutPipe,
-    train_move::{log_train_move_walk, train_move_walk},
-    update::{block_action, update_blob_info, update_joint_info},
+    update::{block_action, update_blob_energy, update_blob_info, update_joint_info},
 };
 
 /// Main entrance of the whole EvoSim system
@@ -46,62 +56,62 @@ use super::{
 /// - `TrainMutPipe`
 /// - `Frames`
 /// - `TED`
+/// - `TrainFitness`
+/// - `Speciation`
+/// - `Genealogy`
 ///
 ///
 /// implement all training style.
-/// choose between different training mode in const
+/// choose between different training mode and fitness in `EvoConfig`,
+/// which should be inserted before adding this plugin
 pub struct BlobContorlPlugin;
 
 impl Plugin for BlobContorlPlugin {
     #[cfg(feature = "demo")]
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, demo_setup)
-            .add_systems(Update, (block_action, update_blob_info, update_joint_info));
+        app.init_resource::<EvoConfig>()
+            .init_resource::<EvoRng>()
+            .add_systems(Startup, demo_setup)
+            .add_systems(
+                Update,
+                (
+                    update_joint_info,
+                    update_blob_info,
+                    block_action,
+                    update_blob_energy,
+                )
+                    .chain(),
+            );
     }
 
     #[cfg(feature = "move")]
     fn build(&self, app: &mut App) {
-        if TRAINING_MODE == "swim" {
-            // train swim
-            app.add_systems(Startup, move_setup)
-                .add_systems(
-                    Update,
-                    (
-                        update_iteration_frames.before(update_blob_info),
-                        block_action,
-                        update_blob_info,
-                        update_joint_info,
-                        update_crowding_distance,
-                        log_train_move_swim.after(block_action),
-                        train_move_swim.after(log_train_move_swim),
-                        mutate_and_refresh_after_train.after(train_move_swim),
-                    ),
+        app.init_resource::<EvoConfig>()
+            .init_resource::<EvoRng>()
+            .init_resource::<TrainFitness>()
+            // a resumed run spawns the population selected before the checkpoint
+            .add_systems(Startup, (move_setup, mutate_and_refresh_after_train).chain())
+            .add_systems(
+                Update,
+                // explicit order, so that runs with the same seed are reproducible
+                (
+                    update_iteration_frames,
+                    update_joint_info,
+                    update_blob_info,
+                    update_novelty,
+                    block_action,
+                    update_blob_energy,
+                    log_train_move,
+                    train_move,
+                    mutate_and_refresh_after_train,
                 )
-                .init_resource::<TrainMutPipe>()
-                .init_resource::<Frames>()
-                .init_resource::<TED>();
-        } else if TRAINING_MODE == "walk" {
-            // train walk
-            app.add_systems(Startup, move_setup)
-                .add_systems(
-                    Update,
-                    (
-                        update_iteration_frames.before(update_blob_info),
-                        block_action,
-                        update_blob_info,
-                        update_joint_info,
-                        update_crowding_distance,
-                        log_train_move_walk.after(block_action),
-                        train_move_walk.after(log_train_move_walk),
-                        mutate_and_refresh_after_train.after(train_move_walk),
-                    ),
-                )
-                .init_resource::<TrainMutPipe>()
-                .init_resource::<Frames>()
-                .init_resource::<TED>();
-        } else {
-            panic!()
-        }
+                    .chain(),
+            )
+            .init_resource::<TrainMutPipe>()
+            .init_resource::<Frames>()
+            .init_resource::<TED>()
+            .init_resource::<Speciation>()
+            .init_resource::<Genealogy>();
     }
 
     fn finish(&self, _app: &mut App) {
@@ -109,59 +119,144 @@ impl Plugin for BlobContorlPlugin {
     }
 }
 
-/// inital setup for demo (mainly for mutation demo)
-pub fn demo_setup(commands: Commands, mut bbns: ResMut<BevyBlockNeurons>) {
-    let mut builder = GenoBlobBuilder::from_commands(commands, &mut bbns.nnvec);
-    // let mut geno = BlobGeno::new_rand();
-    // builder.build(&mut geno, [-500.0, 0.0]);
-    // println!("{:#?}",geno);
+/// Replay blobs loaded from `LoadedCheckpoint` without evolution.
+///
+/// Blobs are updated each frame and the training information is logged,
+/// but there is no training and mutation.
+///
+/// `LoadedCheckpoint` and `EvoConfig` should be inserted before adding this plugin
+pub struct BlobReplayPlugin;
+
+impl Plugin for BlobReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EvoConfig>()
+            .init_resource::<EvoRng>()
+            .init_resource::<TrainFitness>()
+            .add_systems(Startup, move_setup)
+            .add_systems(
+                Update,
+                (
+                    update_iteration_frames,
+                    update_joint_info,
+                    update_blob_info,
+                    update_novelty,
+                    block_action,
+                    update_blob_energy,
+                    log_train_move,
+                )
+                    .chain(),
+            )
+            .init_resource::<Frames>()
+            .init_resource::<TED>()
+            .init_resource::<Genealogy>();
+    }
 
+    fn finish(&self, _app: &mut App) {
+        logger_info!("BlobReplayPlugin started");
+    }
+}
+
+/// inital setup for demo (mainly for mutation demo)
+pub fn demo_setup(
+    commands: Commands,
+    mut bbns: ResMut<BevyBlockNeurons>,
+    config: Res<EvoConfig>,
+    mut rng: ResMut<EvoRng>,
+) {
+    let mut genovec = Vec::<(BlobGeno, [f32; 2])>::new();
     for i in -2..2 {
         for j in -2..2 {
-            builder.build(
-                &mut BlobGeno::new_rand(),
+            genovec.push((
+                BlobGeno::new_rand(&config.geno, &mut rng.0),
                 [1000.0 * i as f32, 1000.0 * j as f32],
-            );
+            ));
         }
     }
+
+    let mut builder =
+        GenoBlobBuilder::from_commands(commands, &mut bbns.nnvec, &config, &mut rng.0);
+    // let mut geno = BlobGeno::new_rand();
+    // builder.build(&mut geno, [-500.0, 0.0]);
+    // println!("{:#?}",geno);
+
+    for (geno, center) in genovec.iter_mut() {
+        builder.build(geno, *center);
+    }
 }
 
 /// inital setup for movement training
-pub fn move_setup(commands: Commands, mut bbns: ResMut<BevyBlockNeurons>) {
-    let mut builder = GenoBlobBuilder::from_commands(commands, &mut bbns.nnvec);
+///
+/// blobs are loaded from `LoadedCheckpoint` if it exists,
+/// otherwise generate random blobs.
+///
+/// nothing is spawned if the selected population of a checkpoint is restored
+/// in `TrainMutPipe`, see `restore_run_state`
+pub fn move_setup(
+    commands: Commands,
+    mut bbns: ResMut<BevyBlockNeurons>,
+    config: Res<EvoConfig>,
+    mut rng: ResMut<EvoRng>,
+    mut genealogy: ResMut<Genealogy>,
+    checkpoint: Option<Res<LoadedCheckpoint>>,
+    pipe: Option<Res<TrainMutPipe>>,
+) {
+    if pipe.is_some_and(|pipe| !pipe.is_empty()) {
+        return;
+    }
 
-    let centers = get_center();
-    for center in centers.iter() {
-        builder.build(&mut BlobGeno::new_rand(), [center.0, center.1]);
+    if let Some(checkpoint) = checkpoint {
+        overwrite(
+            checkpoint.0.clone(),
+            commands,
+            &mut bbns,
+            &config,
+            &mut genealogy,
+            &mut rng.0,
+        );
+        return;
+    }
+
+    let centers = get_center(&config, &mut rng.0);
+    let mut genovec: Vec<BlobGeno> = centers
+        .iter()
+        .map(|_| {
+            let mut geno = BlobGeno::new_rand(&config.geno, &mut rng.0);
+            geno.lineage = genealogy.birth(Vec::new());
+            geno
+        })
+        .collect();
+
+    let mut builder =
+        GenoBlobBuilder::from_commands(commands, &mut bbns.nnvec, &config, &mut rng.0);
+    for (geno, center) in genovec.iter_mut().zip(centers.iter()) {
+        builder.build(geno, [center.0, center.1]);
     }
 }
 
 /// generate a random blob center pos base on target population
 ///
-/// centers generation is contorled by several consts.
+/// centers generation is contorled by `EvoConfig`.
 ///
 /// function will panic if it is not very likely to
 /// fit all blobs into the given field
-pub fn get_center() -> Vec<(f32, f32)> {
-    let mut rng: ThreadRng = thread_rng();
-
-    let mut world_width = WORLD_WIDTH_SWIM as f32;
-    let mut world_height = WORLD_HEIGHT_SWIM as f32;
-    if TRAINING_MODE == "walk" {
-        world_width = WORLD_WIDTH_WALK as f32;
-        world_height = WORLD_HEIGHT_WALK as f32;
-    }
+pub fn get_center(config: &EvoConfig, rng: &mut dyn RngCore) -> Vec<(f32, f32)> {
+    get_centers(config, config.train.population, rng)
+}
+
+/// generate `number` random blob center pos, see `get_center`
+pub fn get_centers(config: &EvoConfig, number: usize, rng: &mut dyn RngCore) -> Vec<(f32, f32)> {
+    let [world_width, world_height] = config.world_size();
+    let [scatter_ratio_x, scatter_ratio_y] = config.train.scatter_ratio;
 
     let x_lim: (f32, f32) = (
-        -world_width * SCATTER_RATIO_X * 0.5,
-        world_width as f32 * SCATTER_RATIO_X * 0.5,
+        -world_width * scatter_ratio_x * 0.5,
+        world_width * scatter_ratio_x * 0.5,
     );
     let y_lim: (f32, f32) = (
-        -world_height as f32 * SCATTER_RATIO_Y * 0.5,
-        world_height as f32 * SCATTER_RATIO_Y * 0.5,
+        -world_height * scatter_ratio_y * 0.5,
+        world_height * scatter_ratio_y * 0.5,
     );
-    let number: usize = POPULATION;
-    let min_distance: f32 = BLOB_SPAWN_POINT_RADIUS;
+    let min_distance: f32 = config.train.spawn_point_radius;
 
     let mut points: Vec<(f32, f32)> = Vec::new();
 
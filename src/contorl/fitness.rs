@@ -0,0 +1,171 @@
+//! fitness functions used by training to rank blobs
+//!
+//! The fitness is chosen by `fitness` in `EvoConfig`.
+//! To train a new task, implement `Fitness` and insert it as `TrainFitness`
+//! before adding `BlobContorlPlugin`.
+
+use bevy::prelude::*;
+
+use crate::{
+    blob::blob::BlobInfo,
+    config::{EvoConfig, FitnessKind},
+};
+
+/// score a blob at the end of an iteration, higher is better
+pub trait Fitness: Send + Sync + 'static {
+    /// name of the fitness, used in logs
+    fn name(&self) -> &'static str;
+
+    /// score base on `BlobInfo`, which cumulated during the iteration
+    fn score(&self, info: &BlobInfo) -> f32;
+}
+
+/// magnitude of moving distance
+pub struct DistanceFitness;
+
+impl Fitness for DistanceFitness {
+    fn name(&self) -> &'static str {
+        "distance"
+    }
+
+    fn score(&self, info: &BlobInfo) -> f32 {
+        magnitude(info.move_distance)
+    }
+}
+
+/// moving distance on x axis
+pub struct XDistanceFitness;
+
+impl Fitness for XDistanceFitness {
+    fn name(&self) -> &'static str {
+        "x_distance"
+    }
+
+    fn score(&self, info: &BlobInfo) -> f32 {
+        info.move_distance[0]
+    }
+}
+
+/// average speed along the moving path
+///
+/// different from distance, moving back and forth also counts
+pub struct SpeedFitness;
+
+impl Fitness for SpeedFitness {
+    fn name(&self) -> &'static str {
+        "speed"
+    }
+
+    fn score(&self, info: &BlobInfo) -> f32 {
+        if info.move_frames == 0 {
+            return 0.0;
+        }
+        info.path_length / info.move_frames as f32
+    }
+}
+
+/// moving distance per unit of mass
+///
+/// prevent large blobs from winning only by their size
+pub struct EfficiencyFitness;
+
+impl Fitness for EfficiencyFitness {
+    fn name(&self) -> &'static str {
+        "efficiency"
+    }
+
+    fn score(&self, info: &BlobInfo) -> f32 {
+        if info.mass <= 0.0 {
+            return 0.0;
+        }
+        magnitude(info.move_distance) / info.mass
+    }
+}
+
+/// negative distance between mass center and target,
+/// so that the blob ends closer to the target has higher score
+pub struct TargetFitness {
+    pub target: [f32; 2],
+}
+
+impl Fitness for TargetFitness {
+    fn name(&self) -> &'static str {
+        "target"
+    }
+
+    fn score(&self, info: &BlobInfo) -> f32 {
+        -magnitude([
+            info.mass_center[0] - self.target[0],
+            info.mass_center[1] - self.target[1],
+        ])
+    }
+}
+
+/// reduce the score of another fitness by the energy spent
+///
+/// see `update_blob_energy`
+pub struct EnergyPenalty {
+    pub fitness: Box<dyn Fitness>,
+    pub penalty: f32,
+}
+
+impl Fitness for EnergyPenalty {
+    fn name(&self) -> &'static str {
+        self.fitness.name()
+    }
+
+    fn score(&self, info: &BlobInfo) -> f32 {
+        self.fitness.score(info) - self.penalty * info.energy
+    }
+}
+
+/// the fitness used by training, as a bevy resource
+///
+/// initialized from `EvoConfig` if it is not inserted
+#[derive(Resource)]
+pub struct TrainFitness(pub Box<dyn Fitness>);
+
+impl TrainFitness {
+    pub fn new(fitness: impl Fitness) -> Self {
+        Self(Box::new(fitness))
+    }
+
+    /// built-in fitness chosen by config,
+    /// with energy penalty if `energy_penalty` is not zero
+    pub fn from_config(config: &EvoConfig) -> Self {
+        let fitness: Box<dyn Fitness> = match config.train.fitness_kind() {
+            FitnessKind::Distance => Box::new(DistanceFitness),
+            FitnessKind::XDistance => Box::new(XDistanceFitness),
+            FitnessKind::Speed => Box::new(SpeedFitness),
+            FitnessKind::Efficiency => Box::new(EfficiencyFitness),
+            FitnessKind::Target => Box::new(TargetFitness {
+                target: config.train.target,
+            }),
+        };
+        let penalty = config.energy.energy_penalty;
+        if penalty == 0.0 {
+            Self(fitness)
+        } else {
+            Self::new(EnergyPenalty { fitness, penalty })
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    pub fn score(&self, info: &BlobInfo) -> f32 {
+        self.0.score(info)
+    }
+}
+
+impl FromWorld for TrainFitness {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.get_resource_or_insert_with(EvoConfig::default);
+        Self::from_config(&config)
+    }
+}
+
+fn magnitude(v: [f32; 2]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1]).sqrt()
+}
@@ -1,6 +1,10 @@
 //! update simulation once each frame, entrance of the entire contorl flow of the project
 
 pub mod update;
+#[allow(clippy::module_inception)]
 pub mod contorl;
+pub mod fitness;
+pub mod nsga2;
+pub mod species;
 pub mod train_move;
 pub mod resource;
\ No newline at end of file
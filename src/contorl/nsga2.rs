@@ -0,0 +1,183 @@
+//! NSGA-II multi-objective selection
+//!
+//! Blobs are sorted into non-dominated fronts over the objectives in `EvoConfig`,
+//! blobs in the same front are ordered by crowding distance,
+//! so that survivers spread over the whole Pareto front.
+//!
+//! All objectives are maximized, minimized objectives are negated in `objective_values`.
+
+use std::cmp::Ordering;
+
+use crate::{blob::blob::BlobInfo, config::Objective, profile_span};
+
+use super::fitness::TrainFitness;
+
+/// objective values of a blob, in the order of `objectives`
+pub fn objective_values(
+    info: &BlobInfo,
+    objectives: &[Objective],
+    fitness: &TrainFitness,
+) -> Vec<f32> {
+    objectives
+        .iter()
+        .map(|objective| match objective {
+            Objective::Fitness => fitness.score(info),
+            Objective::Energy => -info.energy,
+            Objective::Novelty => info.novelty,
+        })
+        .collect()
+}
+
+/// name of objective used in logs
+pub fn objective_name(objective: &Objective, fitness: &TrainFitness) -> &'static str {
+    match objective {
+        Objective::Fitness => fitness.name(),
+        Objective::Energy => "energy",
+        Objective::Novelty => "novelty",
+    }
+}
+
+/// if `a` is no worse than `b` in all objectives and better in at least one
+fn dominates(a: &[f32], b: &[f32]) -> bool {
+    let mut better = false;
+    for (x, y) in a.iter().zip(b.iter()) {
+        if x < y {
+            return false;
+        }
+        if x > y {
+            better = true;
+        }
+    }
+    better
+}
+
+/// fast non-dominated sort
+///
+/// return indices of each front, the first front is the Pareto front
+pub fn non_dominated_sort(values: &[Vec<f32>]) -> Vec<Vec<usize>> {
+    let n = values.len();
+    // how many blobs dominate this blob
+    let mut dominated_count = vec![0usize; n];
+    // blobs dominated by this blob
+    let mut dominating: Vec<Vec<usize>> = vec![Vec::new(); n];
+
+    for i in 0..n {
+        for j in (i + 1)..n {
+            if dominates(&values[i], &values[j]) {
+                dominating[i].push(j);
+                dominated_count[j] += 1;
+            } else if dominates(&values[j], &values[i]) {
+                dominating[j].push(i);
+                dominated_count[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts: Vec<Vec<usize>> = Vec::new();
+    let mut current: Vec<usize> = (0..n).filter(|&i| dominated_count[i] == 0).collect();
+    while !current.is_empty() {
+        let mut next = Vec::new();
+        for &i in current.iter() {
+            for &j in dominating[i].iter() {
+                dominated_count[j] -= 1;
+                if dominated_count[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        next.sort_unstable();
+        fronts.push(current);
+        current = next;
+    }
+    fronts
+}
+
+/// crowding distance of each blob in a front, in the order of `front`
+///
+/// boundary blobs of each objective have infinite distance
+pub fn crowding_distance(front: &[usize], values: &[Vec<f32>]) -> Vec<f32> {
+    let _span = profile_span!("crowding_distance");
+    let mut distance = vec![0.0f32; front.len()];
+    if front.is_empty() {
+        return distance;
+    }
+
+    // values of each objective, in the order of `front`
+    let objective_len = values[front[0]].len();
+    let columns =
+        (0..objective_len).map(|m| front.iter().map(|&idx| values[idx][m]).collect::<Vec<f32>>());
+    for column in columns {
+        // positions in front, sorted by this objective
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| column[a].partial_cmp(&column[b]).unwrap_or(Ordering::Equal));
+
+        let min = column[order[0]];
+        let max = column[order[order.len() - 1]];
+        distance[order[0]] = f32::INFINITY;
+        distance[order[order.len() - 1]] = f32::INFINITY;
+
+        let range = max - min;
+        if range <= 0.0 {
+            continue;
+        }
+        for k in 1..order.len().saturating_sub(1) {
+            distance[order[k]] += (column[order[k + 1]] - column[order[k - 1]]) / range;
+        }
+    }
+    distance
+}
+
+/// order all blobs by front rank, then by crowding distance (larger first)
+///
+/// taking the first `n` indices is NSGA-II selection of `n` survivers
+pub fn nsga2_order(values: &[Vec<f32>]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(values.len());
+    for front in non_dominated_sort(values) {
+        let distance = crowding_distance(&front, values);
+        let mut ranked: Vec<(usize, f32)> = front.into_iter().zip(distance).collect();
+        // stable sort, keeps index order for equal distance
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        order.extend(ranked.into_iter().map(|(idx, _)| idx));
+    }
+    order
+}
+
+#[cfg(test)]
+mod nsga2_test {
+    use super::*;
+
+    #[test]
+    fn test_non_dominated_sort() {
+        let values = vec![
+            vec![1.0, 1.0], // 0, dominated by 1, 2 and 4
+            vec![2.0, 3.0], // 1, front 0
+            vec![3.0, 2.0], // 2, front 0
+            vec![0.0, 0.0], // 3, dominated by all
+            vec![1.0, 2.0], // 4, dominated by 1
+        ];
+        let fronts = non_dominated_sort(&values);
+        assert_eq!(fronts, vec![vec![1, 2], vec![4], vec![0], vec![3]]);
+    }
+
+    #[test]
+    fn test_crowding_distance() {
+        let values = vec![
+            vec![0.0, 4.0],
+            vec![1.0, 3.0],
+            vec![3.0, 1.0],
+            vec![4.0, 0.0],
+        ];
+        let front = vec![0, 1, 2, 3];
+        let distance = crowding_distance(&front, &values);
+        assert!(distance[0].is_infinite());
+        assert!(distance[3].is_infinite());
+        // (3 - 0) / 4 on both objectives
+        assert!((distance[1] - 1.5).abs() < 1e-6);
+        // (4 - 1) / 4 on both objectives
+        assert!((distance[2] - 1.5).abs() < 1e-6);
+
+        // boundary blobs first, front 0 before front 1
+        let values = vec![vec![0.0, 0.0], vec![1.0, 1.0], vec![2.0, 0.0], vec![0.0, 2.0]];
+        assert_eq!(nsga2_order(&values), vec![2, 3, 1, 0]);
+    }
+}
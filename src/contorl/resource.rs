@@ -1,22 +1,69 @@
 //! hepler bevy resources to pass information and signals between bevy systems
 
 use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     blob::{blob::BlobInfo, geno_blob_builder::BlobGeno},
     brain::neuron::GenericNN,
+    config::EvoConfig,
+    logger_info,
 };
 
-/// count how many frames been passed since simulation start
+/// The only random generator of the simulation.
+///
+/// All randomness (geno, nn, mutation, selection, spawn position)
+/// comes from this resource, so that runs with the same seed are reproducible.
+///
+/// Seeded by `seed` in `EvoConfig`, which is chosen by `RunArgs::load_config` if not given.
 #[derive(Resource)]
-pub struct Frames(pub u128);
+pub struct EvoRng(pub ChaCha8Rng);
 
-impl Default for Frames {
-    fn default() -> Self {
-        Self(0)
+impl EvoRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self(ChaCha8Rng::seed_from_u64(seed))
+    }
+
+    /// exact state of the generator, saved in checkpoints
+    pub fn state(&self) -> RngState {
+        RngState {
+            seed: self.0.get_seed(),
+            stream: self.0.get_stream(),
+            word_pos: self.0.get_word_pos(),
+        }
+    }
+
+    pub fn from_state(state: &RngState) -> Self {
+        let mut rng = ChaCha8Rng::from_seed(state.seed);
+        rng.set_stream(state.stream);
+        rng.set_word_pos(state.word_pos);
+        Self(rng)
+    }
+}
+
+/// position of `EvoRng` in its random stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RngState {
+    pub seed: [u8; 32],
+    pub stream: u64,
+    pub word_pos: u128,
+}
+
+impl FromWorld for EvoRng {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.get_resource_or_insert_with(EvoConfig::default);
+        let seed = config.train.seed.unwrap_or_default();
+        logger_info!("random seed {}", seed);
+        Self::from_seed(seed)
     }
 }
 
+/// count how many frames been passed since simulation start
+#[derive(Resource, Default)]
+pub struct Frames(pub u128);
+
 // TODO: TED should be normalized by avg blob depth
 /// The Tree Edit Distance, used as indicator for diversity
 #[derive(Resource)]
@@ -35,23 +82,13 @@ impl Default for TED {
 /// 
 /// When doing cleaning, this Resource stores 
 /// all the blobs and neurons waiting to be spawned
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct TrainMutPipe {
     genovec: Vec<BlobGeno>,
     infovec: Vec<BlobInfo>,
     nnvec: Vec<GenericNN>,
 }
 
-impl Default for TrainMutPipe {
-    fn default() -> Self {
-        Self {
-            genovec: Vec::<BlobGeno>::new(),
-            infovec: Vec::<BlobInfo>::new(),
-            nnvec: Vec::<GenericNN>::new(),
-        }
-    }
-}
-
 impl TrainMutPipe {
     pub fn push(&mut self, genovec: Vec<BlobGeno>, infovec: Vec<BlobInfo>, nnvec: Vec<GenericNN>) {
         assert!(self.genovec.is_empty());
@@ -83,4 +120,9 @@ impl TrainMutPipe {
     pub fn is_empty(&self) -> bool {
         self.genovec.is_empty()
     }
+
+    /// blobs waiting for mutation, without taking them
+    pub fn peek(&self) -> (&[BlobGeno], &[BlobInfo], &[GenericNN]) {
+        (&self.genovec, &self.infovec, &self.nnvec)
+    }
 }
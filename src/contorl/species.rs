@@ -0,0 +1,498 @@
+//! NEAT-style speciation
+//!
+//! Blobs are grouped into species by compatibility distance to the representative of each species,
+//! so that new limbs only compete with similar blobs until their NNs are tuned.
+//!
+//! - fitness is shared within a species, large species don't take over the population
+//! - each species survives and reproduces by its quota, see `Speciation::select`
+//! - species without improvement for `stagnation_limit` iterations extinct
+
+use std::cmp::Ordering;
+
+use bevy::prelude::*;
+use ndarray::{s, Array2, Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blob::geno_blob_builder::{BlobGeno, GenericGenoNode},
+    brain::{neuron::GenericNN, nn::BaseNN},
+    config::{EvoConfig, SpeciesConfig},
+};
+
+/// a group of similar blobs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Species {
+    pub id: usize,
+    /// blob compared with in speciation, nn_id of the geno is the index of `nnvec`
+    representative: BlobGeno,
+    nnvec: Vec<GenericNN>,
+    /// best fitness the species ever reached
+    pub best_fitness: f32,
+    /// iterations since `best_fitness` last improved
+    pub stagnation: u32,
+}
+
+impl Species {
+    fn new(id: usize, geno: &BlobGeno, nnvec: &[GenericNN]) -> Self {
+        let (representative, nnvec) = clone_with_nn(geno, nnvec);
+        Self {
+            id,
+            representative,
+            nnvec,
+            best_fitness: f32::NEG_INFINITY,
+            stagnation: 0,
+        }
+    }
+}
+
+/// survivers and offspring count of a species after selection
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesQuota {
+    pub id: usize,
+    /// indices of survivers in the population, best first
+    pub survivers: Vec<usize>,
+    /// how many new blobs are reproduced from `survivers`
+    pub offspring: usize,
+}
+
+/// all alive species, as a bevy resource.
+///
+/// species persist between iterations,
+/// their ids are recorded in `BlobGeno` and exported with the blobs.
+#[derive(Resource, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Speciation {
+    pub species: Vec<Species>,
+    next_id: usize,
+}
+
+impl Speciation {
+    /// assign a species to every blob and set `species` of the geno.
+    ///
+    /// A blob stays in its species if it is still compatible,
+    /// otherwise it joins the first compatible species or founds a new one.
+    /// Species unknown to the resource (e.g. loaded from a checkpoint) are restored.
+    ///
+    /// return indices of members of each species, in the order of `self.species`
+    pub fn speciate(
+        &mut self,
+        genos: &mut [&mut BlobGeno],
+        nnvec: &[GenericNN],
+        config: &SpeciesConfig,
+    ) -> Vec<Vec<usize>> {
+        // restore species of loaded blobs
+        for geno in genos.iter() {
+            if let Some(id) = geno.species {
+                if self.position(id).is_none() {
+                    self.species.push(Species::new(id, geno, nnvec));
+                }
+                self.next_id = self.next_id.max(id + 1);
+            }
+        }
+
+        let mut members: Vec<Vec<usize>> = vec![Vec::new(); self.species.len()];
+        for (idx, geno) in genos.iter_mut().enumerate() {
+            let compatible = |species: &Species| {
+                compatibility(geno, nnvec, &species.representative, &species.nnvec, config)
+                    < config.threshold
+            };
+
+            let current = geno
+                .species
+                .and_then(|id| self.position(id))
+                .filter(|&pos| compatible(&self.species[pos]));
+            let pos = match current.or_else(|| self.species.iter().position(compatible)) {
+                Some(pos) => pos,
+                None => {
+                    self.species.push(Species::new(self.next_id, geno, nnvec));
+                    self.next_id += 1;
+                    members.push(Vec::new());
+                    self.species.len() - 1
+                }
+            };
+
+            geno.species = Some(self.species[pos].id);
+            members[pos].push(idx);
+        }
+
+        // drop species without members
+        let mut empty = members.iter().map(|m| m.is_empty());
+        self.species.retain(|_| !empty.next().unwrap());
+        members.retain(|m| !m.is_empty());
+        members
+    }
+
+    /// select survivers of each species and decide how many offspring it has.
+    ///
+    /// `members` is the result of `speciate`, `scores` is fitness of each blob,
+    /// the population is shared as `population` in `EvoConfig`.
+    ///
+    /// Fitness is shifted to be positive and divided by species size,
+    /// the population is shared by species in proportion to their total shared fitness.
+    /// Stagnant species extinct before sharing, and the representative of
+    /// each species is updated to its best blob.
+    pub fn select(
+        &mut self,
+        members: &[Vec<usize>],
+        scores: &[f32],
+        genos: &[&BlobGeno],
+        nnvec: &[GenericNN],
+        config: &EvoConfig,
+    ) -> Vec<SpeciesQuota> {
+        assert_eq!(members.len(), self.species.len());
+        let population = config.train.population;
+        let survival_rate = config.train.survival_rate;
+
+        // members of each species, best first
+        let ranked: Vec<Vec<usize>> = members
+            .iter()
+            .map(|m| {
+                let mut m = m.clone();
+                m.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal));
+                m
+            })
+            .collect();
+
+        // stagnation
+        for (species, ranked) in self.species.iter_mut().zip(ranked.iter()) {
+            let best = scores[ranked[0]];
+            if best > species.best_fitness {
+                species.best_fitness = best;
+                species.stagnation = 0;
+            } else {
+                species.stagnation += 1;
+            }
+            let (representative, nnvec) = clone_with_nn(genos[ranked[0]], nnvec);
+            species.representative = representative;
+            species.nnvec = nnvec;
+        }
+
+        // extinction, the species of the best blob is always kept
+        let champion = (0..ranked.len())
+            .max_by(|&a, &b| {
+                scores[ranked[a][0]]
+                    .partial_cmp(&scores[ranked[b][0]])
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap();
+        let alive: Vec<bool> = self
+            .species
+            .iter()
+            .enumerate()
+            .map(|(pos, species)| {
+                pos == champion || species.stagnation < config.species.stagnation_limit
+            })
+            .collect();
+
+        // shared fitness
+        let min_score = scores.iter().cloned().fold(f32::INFINITY, f32::min);
+        let shared: Vec<f32> = ranked
+            .iter()
+            .zip(alive.iter())
+            .map(|(ranked, &alive)| {
+                if !alive {
+                    return 0.0;
+                }
+                let total: f32 = ranked.iter().map(|&idx| scores[idx] - min_score).sum();
+                total / ranked.len() as f32
+            })
+            .collect();
+        // all blobs have the same score, share by species size
+        let shared: Vec<f32> = if shared.iter().sum::<f32>() > 0.0 {
+            shared
+        } else {
+            ranked
+                .iter()
+                .zip(alive.iter())
+                .map(|(ranked, &alive)| if alive { ranked.len() as f32 } else { 0.0 })
+                .collect()
+        };
+
+        let quotas = largest_remainder(&shared, population);
+
+        let mut result = Vec::new();
+        for ((species, ranked), quota) in self.species.iter().zip(ranked.iter()).zip(quotas) {
+            if quota == 0 {
+                continue;
+            }
+            let survivers_len =
+                ((quota as f32 * survival_rate).ceil() as usize).clamp(1, ranked.len());
+            result.push(SpeciesQuota {
+                id: species.id,
+                survivers: ranked[..survivers_len].to_vec(),
+                offspring: quota - survivers_len,
+            });
+        }
+
+        let mut alive = alive.into_iter();
+        self.species.retain(|_| alive.next().unwrap());
+        result
+    }
+
+    fn position(&self, id: usize) -> Option<usize> {
+        self.species.iter().position(|species| species.id == id)
+    }
+}
+
+/// compatibility distance between two blobs, see `SpeciesConfig`
+pub fn compatibility(
+    a: &BlobGeno,
+    nnvec_a: &[GenericNN],
+    b: &BlobGeno,
+    nnvec_b: &[GenericNN],
+    config: &SpeciesConfig,
+) -> f32 {
+    let ted = a.vec_tree.tree_edit_distance(&b.vec_tree) as f32;
+    config.ted_weight * ted + config.nn_weight * nn_distance(a, nnvec_a, b, nnvec_b)
+}
+
+/// mean absolute weight difference of NNs of blocks at the same position of both trees.
+///
+/// the root block holds the brain NN, so brains are compared as well
+fn nn_distance(a: &BlobGeno, nnvec_a: &[GenericNN], b: &BlobGeno, nnvec_b: &[GenericNN]) -> f32 {
+    let mut total = 0.0;
+    let mut count = 0;
+    for (node_a, node_b) in a.vec_tree.nodes.iter().zip(b.vec_tree.nodes.iter()) {
+        if let (Some(GenericGenoNode::Child(node_a)), Some(GenericGenoNode::Child(node_b))) =
+            (node_a, node_b)
+        {
+            total += generic_nn_distance(
+                &nnvec_a[node_a.nn_id.unwrap()],
+                &nnvec_b[node_b.nn_id.unwrap()],
+            );
+            count += 1;
+        }
+    }
+    if count == 0 {
+        0.0
+    } else {
+        total / count as f32
+    }
+}
+
+fn generic_nn_distance(a: &GenericNN, b: &GenericNN) -> f32 {
+    match (a, b) {
+        (GenericNN::BRAINNN(a), GenericNN::BRAINNN(b)) => base_nn_distance(&a.nn, &b.nn),
+        (GenericNN::BLOCKNN(a), GenericNN::BLOCKNN(b)) => {
+            (base_nn_distance(&a.inward_nn.nn, &b.inward_nn.nn)
+                + base_nn_distance(&a.outward_nn.nn, &b.outward_nn.nn))
+                * 0.5
+        }
+        _ => f32::INFINITY,
+    }
+}
+
+/// mean absolute difference of all weights and biases,
+/// weights of nodes which only one of the NNs has are compared with zero.
+///
+/// NNs with different numbers of layers are never compatible
+fn base_nn_distance(a: &BaseNN, b: &BaseNN) -> f32 {
+    if a.layers.len() != b.layers.len() {
+        return f32::INFINITY;
+    }
+    let mut total = 0.0;
+    let mut count = 0;
+    for (layer_a, layer_b) in a.layers.iter().zip(b.layers.iter()) {
+        let (rows_a, cols_a) = layer_a.weights.dim();
+        let (rows_b, cols_b) = layer_b.weights.dim();
+        let dim = (rows_a.max(rows_b), cols_a.max(cols_b));
+        let weights = zero_padded(&layer_a.weights, dim) - zero_padded(&layer_b.weights, dim);
+        let bias_a = layer_a.bias.view().insert_axis(Axis(1)).to_owned();
+        let bias_b = layer_b.bias.view().insert_axis(Axis(1)).to_owned();
+        let bias = zero_padded(&bias_a, (dim.0, 1)) - zero_padded(&bias_b, (dim.0, 1));
+        total += weights.mapv(f32::abs).sum() + bias.mapv(f32::abs).sum();
+        count += weights.len() + bias.len();
+    }
+    if count == 0 {
+        0.0
+    } else {
+        total / count as f32
+    }
+}
+
+/// `array` in the top left corner of zeros of `dim`
+fn zero_padded(array: &Array2<f32>, dim: (usize, usize)) -> Array2<f32> {
+    let mut padded = Array2::<f32>::zeros(dim);
+    padded
+        .slice_mut(s![..array.nrows(), ..array.ncols()])
+        .assign(array);
+    padded
+}
+
+/// clone the geno and its NNs, nn_id of the cloned geno is the index of cloned NNs
+fn clone_with_nn(geno: &BlobGeno, nnvec: &[GenericNN]) -> (BlobGeno, Vec<GenericNN>) {
+    let mut geno = geno.clone();
+    let mut nns = Vec::<GenericNN>::new();
+    for nn_id in geno.all_nn_ids_mut() {
+        nns.push(nnvec[nn_id.unwrap()].clone());
+        *nn_id = Some(nns.len() - 1);
+    }
+    (geno, nns)
+}
+
+/// split `total` in proportion to `weights`,
+/// the rest after rounding down goes to the largest remainders
+fn largest_remainder(weights: &[f32], total: usize) -> Vec<usize> {
+    let sum: f32 = weights.iter().sum();
+    if sum <= 0.0 {
+        return vec![0; weights.len()];
+    }
+    let exact: Vec<f32> = weights.iter().map(|w| w / sum * total as f32).collect();
+    let mut quotas: Vec<usize> = exact.iter().map(|x| x.floor() as usize).collect();
+
+    let mut order: Vec<usize> = (0..weights.len()).collect();
+    // stable sort, earlier species first for equal remainder
+    order.sort_by(|&a, &b| {
+        (exact[b] - exact[b].floor())
+            .partial_cmp(&(exact[a] - exact[a].floor()))
+            .unwrap_or(Ordering::Equal)
+    });
+    let rest = total.saturating_sub(quotas.iter().sum());
+    for &idx in order
+        .iter()
+        .filter(|&&idx| weights[idx] > 0.0)
+        .cycle()
+        .take(rest)
+    {
+        quotas[idx] += 1;
+    }
+    quotas
+}
+
+#[cfg(test)]
+mod species_test {
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::{
+        brain::neuron::{BlockNN, BrainNN},
+        config::EvoConfig,
+    };
+
+    /// random geno with NNs appended to `nnvec`
+    fn rand_geno(
+        config: &EvoConfig,
+        nnvec: &mut Vec<GenericNN>,
+        rng: &mut dyn RngCore,
+    ) -> BlobGeno {
+        let mut geno = BlobGeno::new_rand(&config.geno, rng);
+        for (i, nn_id) in geno.all_nn_ids_mut().into_iter().enumerate() {
+            if i == 0 {
+                nnvec.push(GenericNN::BRAINNN(BrainNN::new(&config.nn, rng)));
+            } else {
+                nnvec.push(GenericNN::BLOCKNN(BlockNN::new(&config.nn, rng)));
+            }
+            *nn_id = Some(nnvec.len() - 1);
+        }
+        geno
+    }
+
+    #[test]
+    fn test_speciate() {
+        let config = EvoConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut nnvec = Vec::<GenericNN>::new();
+
+        let a = rand_geno(&config, &mut nnvec, &mut rng);
+        let b = rand_geno(&config, &mut nnvec, &mut rng);
+        assert_eq!(compatibility(&a, &nnvec, &a, &nnvec, &config.species), 0.0);
+        assert!(compatibility(&a, &nnvec, &b, &nnvec, &config.species) > config.species.threshold);
+
+        // clones of a blob share the species
+        let mut genos = [a.clone(), b.clone(), a.clone(), b.clone()];
+        let mut refs: Vec<&mut BlobGeno> = genos.iter_mut().collect();
+        let mut speciation = Speciation::default();
+        let members = speciation.speciate(&mut refs, &nnvec, &config.species);
+        assert_eq!(members, vec![vec![0, 2], vec![1, 3]]);
+        assert_eq!(genos[0].species, genos[2].species);
+        assert_ne!(genos[0].species, genos[1].species);
+
+        // species ids are kept in next iteration
+        let mut speciation_loaded = Speciation::default();
+        let mut refs: Vec<&mut BlobGeno> = genos.iter_mut().rev().collect();
+        let members = speciation_loaded.speciate(&mut refs, &nnvec, &config.species);
+        assert_eq!(members, vec![vec![0, 2], vec![1, 3]]);
+        assert_eq!(
+            genos.iter().map(|geno| geno.species).collect::<Vec<_>>(),
+            vec![Some(0), Some(1), Some(0), Some(1)]
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let mut config = EvoConfig::default();
+        config.train.population = 8;
+        config.train.survival_rate = 0.5;
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut nnvec = Vec::<GenericNN>::new();
+        let a = rand_geno(&config, &mut nnvec, &mut rng);
+        let b = rand_geno(&config, &mut nnvec, &mut rng);
+
+        let mut genos = [a.clone(), a.clone(), a.clone(), b.clone()];
+        let mut refs: Vec<&mut BlobGeno> = genos.iter_mut().collect();
+        let mut speciation = Speciation::default();
+        let members = speciation.speciate(&mut refs, &nnvec, &config.species);
+
+        // the single blob of b is as good as the average of a,
+        // b gets the same share as a thanks to fitness sharing
+        let scores = [1.0, 3.0, 2.0, 2.0];
+        let refs: Vec<&BlobGeno> = genos.iter().collect();
+        let quotas = speciation.select(&members, &scores, &refs, &nnvec, &config);
+        assert_eq!(
+            quotas,
+            vec![
+                SpeciesQuota {
+                    id: 0,
+                    survivers: vec![1, 2],
+                    offspring: 2
+                },
+                SpeciesQuota {
+                    id: 1,
+                    survivers: vec![3],
+                    offspring: 3
+                },
+            ]
+        );
+
+        // stagnant species extinct, except the species of the best blob
+        config.species.stagnation_limit = 1;
+        let quotas = speciation.select(&members, &scores, &refs, &nnvec, &config);
+        assert_eq!(quotas.len(), 1);
+        assert_eq!(quotas[0].id, 0);
+        assert_eq!(quotas[0].survivers.len() + quotas[0].offspring, 8);
+        assert_eq!(speciation.species.len(), 1);
+    }
+
+    #[test]
+    fn test_nn_distance() {
+        let config = EvoConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let a = BrainNN::new(&config.nn, &mut rng).nn;
+
+        // a new node only adds its own weights to the distance
+        let mut b = a.clone();
+        b.add_node(0, &mut rng);
+        let distance = base_nn_distance(&a, &b);
+        assert!(distance > 0.0 && distance < 1.0);
+        assert_eq!(base_nn_distance(&a, &b), base_nn_distance(&b, &a));
+
+        b.add_layer(1);
+        assert_eq!(base_nn_distance(&a, &b), f32::INFINITY);
+    }
+
+    #[test]
+    fn test_brain_distance() {
+        let config = EvoConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut nnvec = Vec::<GenericNN>::new();
+        let a = rand_geno(&config, &mut nnvec, &mut rng);
+        let brain_id = a.all_usize_nn_ids()[0];
+        assert!(matches!(nnvec[brain_id], GenericNN::BRAINNN(_)));
+
+        // same blocks, only the brain differs
+        let mut other = nnvec.clone();
+        other[brain_id] = GenericNN::BRAINNN(BrainNN::new(&config.nn, &mut rng));
+        assert_eq!(nn_distance(&a, &nnvec, &a, &nnvec), 0.0);
+        assert!(nn_distance(&a, &nnvec, &a, &other) > 0.0);
+    }
+}
@@ -1,6 +1,10 @@
 //! training process. Trainnig to let blobs to learn to move (swim or walk)
+//!
+//! blobs are ranked by `TrainFitness`, see `fitness.rs`,
+//! or by NSGA-II over multiple objectives, see `nsga2.rs`,
+//! or within their species, see `species.rs`
 
-// TODO: Currently the crowing distance only considered the morphyology distance, need to consider the distance of neural network.
+// TODO: Currently the novelty only considered the morphyology distance, need to consider the distance of neural network.
 
 use std::collections::HashSet;
 
@@ -9,34 +13,52 @@ use rand::prelude::*;
 use rand_distr::WeightedIndex;
 
 use crate::{
-    blob::{blob::BlobInfo, block::NeuronId, geno_blob_builder::BlobGeno},
+    blob::{blob::BlobInfo, block::NeuronId, geno_blob_builder::BlobGeno, lineage::Genealogy},
     brain::{neuron::GenericNN, resource::BevyBlockNeurons},
-    consts::{
-        HYBRID_RATE, ITERATION_LENGTH, NEW_ITERATION_KEYCODE, POPULATION, TRAIN_MOVE_SURVIVAL_RATE,
-    },
+    config::{EvoConfig, Objective, SelectionMode},
+    consts::NEW_ITERATION_KEYCODE,
     contorl::contorl::get_center,
     logger_info,
+    mutate::crossover::crossover,
 };
 
-use super::resource::{Frames, TrainMutPipe, TED};
+use super::{
+    fitness::TrainFitness,
+    nsga2::{non_dominated_sort, nsga2_order, objective_name, objective_values},
+    resource::{EvoRng, Frames, TrainMutPipe, TED},
+    species::{Speciation, SpeciesQuota},
+};
 
-/// main training function for blob's swim moving.
+/// main training function for blob's moving (swim or walk).
 /// 
 /// When current iteration ends, the function will be called.
 /// 
-/// Preform tournament selection base on moving distance and crowding distance
+/// Preform tournament selection base on `TrainFitness` and novelty,
+/// or NSGA-II selection if `selection` in `EvoConfig` is `nsga2`,
+/// or selection by species quota if `selection` is `species`
 /// 
-/// `POPULATION == 1` in will make thread panic since it never trains
-pub fn train_move_swim(
+/// `population == 1` in will make thread panic since it never trains
+#[allow(clippy::too_many_arguments)]
+pub fn train_move(
     entity_geno_info_q: Query<(Entity, (&BlobGeno, &BlobInfo))>,
     nn_q: Query<(&Parent, &NeuronId)>,
-    mut bbn: ResMut<BevyBlockNeurons>,
+    bbn: Res<BevyBlockNeurons>,
     mut pipe: ResMut<TrainMutPipe>,
-    input: Res<Input<KeyCode>>,
+    input: Option<Res<Input<KeyCode>>>,
     frames: Res<Frames>,
+    config: Res<EvoConfig>,
+    fitness: Res<TrainFitness>,
+    mut speciation: ResMut<Speciation>,
+    mut genealogy: ResMut<Genealogy>,
+    mut rng: ResMut<EvoRng>,
 ) {
-    if input.just_pressed(NEW_ITERATION_KEYCODE) || iteration_end(frames) {
-        let nnvec = &mut bbn.nnvec;
+    let key_pressed = input.is_some_and(|input| input.just_pressed(NEW_ITERATION_KEYCODE));
+    if key_pressed || iteration_end(&frames, config.train.iteration_length) {
+        // offspring are born at this iteration
+        genealogy.iteration = (frames.0 / config.train.iteration_length as u128) as usize;
+        // NNs in the world are kept until mutation replaces the population,
+        // so that checkpoints saved in this frame still match the blobs
+        let nnvec = &mut bbn.nnvec.clone();
         let mut blob_vec_move: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
         let mut blob_vec_ted: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
         for (e, (geno, info)) in entity_geno_info_q.iter() {
@@ -44,104 +66,109 @@ pub fn train_move_swim(
             blob_vec_ted.push((e, (geno.clone(), info.clone())));
         }
 
-        // move mag
-        blob_vec_move.sort_by(|a, b| {
-            let mag_a =
-                a.1 .1
-                    .move_distance
+        // a loaded population can be larger than the target population,
+        // then the survivers take all slots
+        let split_idx = (blob_vec_move.len() as f32 * config.train.survival_rate).ceil() as usize;
+        let split_idx = split_idx.min(config.train.population);
+        // all survivers reproduce to the target population
+        let whole_population = vec![(
+            (0..split_idx).collect(),
+            config.train.population - split_idx,
+        )];
+
+        let (survivers_move, groups) = match config.train.selection {
+            SelectionMode::Nsga2 => {
+                // front rank and crowding distance
+                let values: Vec<Vec<f32>> = blob_vec_move
                     .iter()
-                    .fold(0.0, |acc, &x| acc + x * x)
-                    .sqrt();
-            let mag_b =
-                b.1 .1
-                    .move_distance
+                    .map(|(_, (_, info))| {
+                        objective_values(info, &config.train.objectives, &fitness)
+                    })
+                    .collect();
+                let order = nsga2_order(&values);
+                blob_vec_move = order
+                    .into_iter()
+                    .map(|idx| blob_vec_move[idx].clone())
+                    .collect();
+
+                let (survivers_move, _outcasts) = blob_vec_move.split_at_mut(split_idx);
+                (survivers_move, whole_population)
+            }
+            SelectionMode::Hybrid => {
+                // fitness
+                blob_vec_move.sort_by(|a, b| {
+                    let mag_a = fitness.score(&a.1 .1);
+                    let mag_b = fitness.score(&b.1 .1);
+                    mag_b
+                        .partial_cmp(&mag_a)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+
+                blob_vec_ted.sort_by(|a, b| {
+                    let mag_a = a.1 .1.novelty;
+                    let mag_b = b.1 .1.novelty;
+                    mag_b
+                        .partial_cmp(&mag_a)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+
+                // tournament selection
+                let (survivers_move, _outcasts) = blob_vec_move.split_at_mut(split_idx);
+                hybrid_selection(
+                    survivers_move,
+                    &blob_vec_ted,
+                    config.train.hybrid_rate,
+                    config.train.hybrid_bias,
+                    &mut rng.0,
+                );
+                (survivers_move, whole_population)
+            }
+            SelectionMode::Species => {
+                let scores: Vec<f32> = blob_vec_move
                     .iter()
-                    .fold(0.0, |acc, &x| acc + x * x)
-                    .sqrt();
-            mag_b
-                .partial_cmp(&mag_a)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
-
-        blob_vec_ted.sort_by(|a, b| {
-            let mag_a = a.1 .1.crowding_distance;
-            let mag_b = b.1 .1.crowding_distance;
-            mag_b
-                .partial_cmp(&mag_a)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
-
-        let split_idx = (blob_vec_move.len() as f32 * TRAIN_MOVE_SURVIVAL_RATE).ceil() as usize;
-
-        // tournament selection
-        let (survivers_move, _outcasts) = blob_vec_move.split_at_mut(split_idx);
-        hybrid_selection(survivers_move, &blob_vec_ted);
-
-        let (mut new_genovec, mut infovec, mut new_nnvec) =
-            clean_outcast(survivers_move, nn_q, nnvec);
-
-        // reproduce
-        reproduce(&mut new_genovec, &mut infovec, &mut new_nnvec);
-
-        // println!("{:#?}",new_genovec);
-        // println!("nnveclen: {:#?}",new_nnvec.len());
-
-        pipe.push(new_genovec, infovec, new_nnvec);
-    }
-}
-
-/// main training function for blob's walk moving.
-/// 
-/// When current iteration ends, the function will be called.
-/// 
-/// Preform tournament selection base on moving distance (only on x axis) and crowding distance
-/// 
-/// `POPULATION == 1` in will make thread panic since it never trains
-pub fn train_move_walk(
-    entity_geno_info_q: Query<(Entity, (&BlobGeno, &BlobInfo))>,
-    nn_q: Query<(&Parent, &NeuronId)>,
-    mut bbn: ResMut<BevyBlockNeurons>,
-    mut pipe: ResMut<TrainMutPipe>,
-    input: Res<Input<KeyCode>>,
-    frames: Res<Frames>,
-) {
-    if input.just_pressed(NEW_ITERATION_KEYCODE) || iteration_end(frames) {
-        let nnvec = &mut bbn.nnvec;
-        let mut blob_vec_move: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
-        let mut blob_vec_ted: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
-        for (e, (geno, info)) in entity_geno_info_q.iter() {
-            blob_vec_move.push((e, (geno.clone(), info.clone())));
-            blob_vec_ted.push((e, (geno.clone(), info.clone())));
-        }
-
-        // x axis move
-        blob_vec_move.sort_by(|a, b| {
-            let mag_a = a.1 .1.move_distance[0];
-            let mag_b = b.1 .1.move_distance[0];
-            mag_b
-                .partial_cmp(&mag_a)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
-
-        blob_vec_ted.sort_by(|a, b| {
-            let mag_a = a.1 .1.crowding_distance;
-            let mag_b = b.1 .1.crowding_distance;
-            mag_b
-                .partial_cmp(&mag_a)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
-
-        let split_idx = (blob_vec_move.len() as f32 * TRAIN_MOVE_SURVIVAL_RATE).ceil() as usize;
-
-        // tournament selection
-        let (survivers_move, _outcasts) = blob_vec_move.split_at_mut(split_idx);
-        hybrid_selection(survivers_move, &blob_vec_ted);
+                    .map(|(_, (_, info))| fitness.score(info))
+                    .collect();
+                let mut genos: Vec<&mut BlobGeno> = blob_vec_move
+                    .iter_mut()
+                    .map(|(_, (geno, _))| geno)
+                    .collect();
+                let members = speciation.speciate(&mut genos, nnvec, &config.species);
+                let genos: Vec<&BlobGeno> =
+                    blob_vec_move.iter().map(|(_, (geno, _))| geno).collect();
+                let quotas = speciation.select(&members, &scores, &genos, nnvec, &config);
+                log_species(&frames, &speciation, &quotas, &config);
+
+                // survivers of each species, in the order of species
+                let mut groups = Vec::new();
+                let mut survivers = Vec::new();
+                for quota in quotas.iter() {
+                    let start = survivers.len();
+                    survivers.extend(
+                        quota
+                            .survivers
+                            .iter()
+                            .map(|&idx| blob_vec_move[idx].clone()),
+                    );
+                    groups.push(((start..survivers.len()).collect(), quota.offspring));
+                }
+                blob_vec_move = survivers;
+                (&mut blob_vec_move[..], groups)
+            }
+        };
 
         let (mut new_genovec, mut infovec, mut new_nnvec) =
             clean_outcast(survivers_move, nn_q, nnvec);
 
         // reproduce
-        reproduce(&mut new_genovec, &mut infovec, &mut new_nnvec);
+        reproduce(
+            &mut new_genovec,
+            &mut infovec,
+            &mut new_nnvec,
+            &groups,
+            &mut genealogy,
+            &config,
+            &mut rng.0,
+        );
 
         // println!("{:#?}",new_genovec);
         // println!("nnveclen: {:#?}",new_nnvec.len());
@@ -156,30 +183,36 @@ pub fn train_move_walk(
 /// aiming to keep diversity
 fn hybrid_selection(
     survivers_move: &mut [(Entity, (BlobGeno, BlobInfo))],
-    blob_vec_ted: &Vec<(Entity, (BlobGeno, BlobInfo))>,
+    blob_vec_ted: &[(Entity, (BlobGeno, BlobInfo))],
+    hybrid_rate: f32,
+    bias_factor: f64,
+    rng: &mut dyn RngCore,
 ) {
-    let mut rng: ThreadRng = thread_rng();
-    let x = (HYBRID_RATE * survivers_move.len() as f32) as usize;
-    let bias_factor = 4.0;
+    let mut chosen_indices = HashSet::new();
+    let survivers_entities: HashSet<_> = survivers_move.iter().map(|(entity, _)| *entity).collect();
+
+    // every chosen blob is an unique outcast, otherwise the loop below never ends
+    let outcasts = blob_vec_ted
+        .iter()
+        .filter(|(entity, _)| !survivers_entities.contains(entity))
+        .count();
+    let x = ((hybrid_rate * survivers_move.len() as f32) as usize).min(outcasts);
 
     // Generate the weighted distribution
     let weights: Vec<f64> = (0..blob_vec_ted.len())
         .map(|i| ((blob_vec_ted.len() - i) as f64).powf(bias_factor))
         .collect();
 
-    let mut chosen_indices = HashSet::new();
-    let survivers_entities: HashSet<_> = survivers_move.iter().map(|(entity, _)| *entity).collect();
-
     for _ in 0..x {
         let rand_surviver_idx = rng.gen_range(0..survivers_move.len());
 
-        let mut blobvec_idx = WeightedIndex::new(&weights).unwrap().sample(&mut rng);
+        let mut blobvec_idx = WeightedIndex::new(&weights).unwrap().sample(rng);
 
         // Ensure the selected index is unique and its Entity is not already in survivers_move
         while chosen_indices.contains(&blobvec_idx)
             || survivers_entities.contains(&blob_vec_ted[blobvec_idx].0)
         {
-            blobvec_idx = WeightedIndex::new(&weights).unwrap().sample(&mut rng);
+            blobvec_idx = WeightedIndex::new(&weights).unwrap().sample(rng);
         }
 
         chosen_indices.insert(blobvec_idx);
@@ -255,36 +288,83 @@ fn clean_outcast(
 
 /// reproduce the blob to the target population
 ///
+/// each group is the indices of survivers and how many offspring they have,
+/// parents of an offspring are chosen in the same group.
+/// offspring get new genome ids from `genealogy`.
+///
 /// this function will reset spawn position of all blobs,
 /// the position won't inherit
 ///
 /// new NN will be append to nnvec
-fn reproduce(genovec: &mut Vec<BlobGeno>, infovec: &mut Vec<BlobInfo>, nnvec: &mut Vec<GenericNN>) {
+fn reproduce(
+    genovec: &mut Vec<BlobGeno>,
+    infovec: &mut Vec<BlobInfo>,
+    nnvec: &mut Vec<GenericNN>,
+    groups: &[(Vec<usize>, usize)],
+    genealogy: &mut Genealogy,
+    config: &EvoConfig,
+    rng: &mut dyn RngCore,
+) {
+    let population = config.train.population;
     assert_eq!(genovec.len(), infovec.len());
-    assert!(genovec.len() < POPULATION);
-
-    let mut rng: ThreadRng = thread_rng();
+    assert_eq!(
+        genovec.len() + groups.iter().map(|(_, offspring)| offspring).sum::<usize>(),
+        population
+    );
 
     let mut new_genovec: Vec<BlobGeno> = Vec::new();
     let mut new_infovec: Vec<BlobInfo> = Vec::new();
     let mut new_nnvec: Vec<GenericNN> = Vec::new();
 
-    loop {
-        let chosen_idx: usize = rng.gen_range(0..genovec.len());
-        let mut new_geno = genovec.get(chosen_idx).unwrap().clone();
-        let new_info = infovec.get(chosen_idx).unwrap().clone();
-        for nn_id in new_geno.all_nn_ids_mut() {
-            let copied_id = nn_id.unwrap();
-            let new_nn = nnvec.get(copied_id).unwrap().clone();
-            new_nnvec.push(new_nn);
-            // modify nn_id
-            *nn_id = Some(new_nnvec.len() + nnvec.len() - 1)
-        }
-        new_genovec.push(new_geno);
-        new_infovec.push(new_info);
-
-        if new_genovec.len() + genovec.len() == POPULATION {
-            break;
+    for (parents, offspring_len) in groups {
+        for _ in 0..*offspring_len {
+            let chosen_pos: usize = rng.gen_range(0..parents.len());
+            let chosen_idx = parents[chosen_pos];
+
+            let new_info = infovec.get(chosen_idx).unwrap().clone();
+
+            // sexual reproduction with another random surviver,
+            // clone the chosen surviver if crossover fails
+            let offspring =
+                if parents.len() >= 2 && rng.gen_bool(config.mutate().crossover_prob as f64) {
+                    let other_idx =
+                        parents[(chosen_pos + rng.gen_range(1..parents.len())) % parents.len()];
+                    crossover(
+                        &genovec[chosen_idx],
+                        &genovec[other_idx],
+                        nnvec,
+                        config.mutate(),
+                        rng,
+                    )
+                    .map(|offspring| (offspring, other_idx))
+                } else {
+                    None
+                };
+
+            if let Some(((mut new_geno, nns), other_idx)) = offspring {
+                // nn_id in offspring is the index of `nns`
+                for nn_id in new_geno.all_nn_ids_mut() {
+                    *nn_id = Some(nn_id.unwrap() + new_nnvec.len() + nnvec.len());
+                }
+                new_nnvec.extend(nns);
+                new_geno.lineage = genealogy.birth(vec![
+                    genovec[chosen_idx].lineage.id,
+                    genovec[other_idx].lineage.id,
+                ]);
+                new_genovec.push(new_geno);
+            } else {
+                let mut new_geno = genovec.get(chosen_idx).unwrap().clone();
+                for nn_id in new_geno.all_nn_ids_mut() {
+                    let copied_id = nn_id.unwrap();
+                    let new_nn = nnvec.get(copied_id).unwrap().clone();
+                    new_nnvec.push(new_nn);
+                    // modify nn_id
+                    *nn_id = Some(new_nnvec.len() + nnvec.len() - 1)
+                }
+                new_geno.lineage = genealogy.birth(vec![genovec[chosen_idx].lineage.id]);
+                new_genovec.push(new_geno);
+            }
+            new_infovec.push(new_info);
         }
     }
 
@@ -292,7 +372,7 @@ fn reproduce(genovec: &mut Vec<BlobGeno>, infovec: &mut Vec<BlobInfo>, nnvec: &m
     infovec.append(&mut new_infovec);
     nnvec.append(&mut new_nnvec);
 
-    let rand_centers = get_center();
+    let rand_centers = get_center(config, rng);
     assert_eq!(infovec.len(), rand_centers.len());
     for (center, info) in rand_centers.iter().zip(infovec.iter_mut()) {
         info.center_block_pos = Vec2::from_array([center.0, center.1])
@@ -300,100 +380,141 @@ fn reproduce(genovec: &mut Vec<BlobGeno>, infovec: &mut Vec<BlobInfo>, nnvec: &m
 }
 
 /// determin if iteration ends
-fn iteration_end(frames: Res<Frames>) -> bool {
-    let cur_gen_frame_cnt = frames.0 % ITERATION_LENGTH as u128;
-    if cur_gen_frame_cnt == 0 && frames.0 != 0 {
-        true
-    } else {
-        false
-    }
+pub fn iteration_end(frames: &Frames, iteration_length: usize) -> bool {
+    let cur_gen_frame_cnt = frames.0 % iteration_length as u128;
+    cur_gen_frame_cnt == 0 && frames.0 != 0
 }
 
-/// logger function for swim training
-pub fn log_train_move_swim(frames: Res<Frames>, info_q: Query<&BlobInfo>, ted: Res<TED>) {
-    let cur_gen_frame_cnt = frames.0 % ITERATION_LENGTH as u128;
+/// logger function for move training
+pub fn log_train_move(
+    frames: Res<Frames>,
+    info_q: Query<&BlobInfo>,
+    ted: Res<TED>,
+    config: Res<EvoConfig>,
+    fitness: Res<TrainFitness>,
+) {
+    let cur_gen_frame_cnt = frames.0 % config.train.iteration_length as u128;
     if cur_gen_frame_cnt != 0 || frames.0 == 0 {
         return;
     }
 
-    let mut infovec = Vec::from_iter(info_q.iter());
-
-    infovec.sort_by(|a, b| {
-        let a_distance_mag = a
-            .move_distance
-            .iter()
-            .fold(0.0, |acc, &x| acc + x * x)
-            .sqrt();
-        let b_distance_mag = b
-            .move_distance
-            .iter()
-            .fold(0.0, |acc, &x| acc + x * x)
-            .sqrt();
-        b_distance_mag.partial_cmp(&a_distance_mag).unwrap()
+    let mut scores: Vec<f32> = info_q.iter().map(|info| fitness.score(info)).collect();
+    if scores.is_empty() {
+        return;
+    }
+
+    scores.sort_by(|a, b| {
+        b.partial_cmp(a)
+            .unwrap_or(std::cmp::Ordering::Equal)
     });
 
-    let top_distance = infovec[0]
-        .move_distance
-        .iter()
-        .fold(0.0, |acc, &x| acc + x * x)
-        .sqrt();
+    let top_score = scores[0];
 
-    let total_distances: f32 = infovec
-        .iter()
-        .map(|info| {
-            info.move_distance
-                .iter()
-                .fold(0.0, |acc, &x| acc + x * x)
-                .sqrt()
-        })
-        .sum();
+    let total_scores: f32 = scores.iter().sum();
 
-    let mean_distance = total_distances / infovec.len() as f32;
+    let mean_score = total_scores / scores.len() as f32;
 
     logger_info!(
-        "iteration {}, top_distance {:.5}, mean_distance {:.5}, ted {:.5}",
-        frames.0 / ITERATION_LENGTH as u128,
-        top_distance,
-        mean_distance,
+        "iteration {}, top_{} {:.5}, mean_{} {:.5}, ted {:.5}",
+        frames.0 / config.train.iteration_length as u128,
+        fitness.name(),
+        top_score,
+        fitness.name(),
+        mean_score,
         ted.0
     );
-}
 
-/// logger function for walk training
-pub fn log_train_move_walk(frames: Res<Frames>, info_q: Query<&BlobInfo>, ted: Res<TED>) {
-    let cur_gen_frame_cnt = frames.0 % ITERATION_LENGTH as u128;
-    if cur_gen_frame_cnt != 0 || frames.0 == 0 {
-        return;
+    if config.train.selection == SelectionMode::Nsga2 {
+        log_pareto_front(&frames, &info_q, &config, &fitness);
     }
+}
 
-    let mut infovec = Vec::from_iter(info_q.iter());
-
-    // x axis move
-    infovec.sort_by(|a, b| {
-        let mag_a = a.move_distance[0];
-        let mag_b = b.move_distance[0];
-        mag_b
-            .partial_cmp(&mag_a)
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
-
-    let top_x_distance = infovec[0]
-        .move_distance[0];
+/// log size of each species after selection
+fn log_species(
+    frames: &Frames,
+    speciation: &Speciation,
+    quotas: &[SpeciesQuota],
+    config: &EvoConfig,
+) {
+    let sizes: Vec<String> = quotas
+        .iter()
+        .map(|quota| format!("[{} {}]", quota.id, quota.survivers.len() + quota.offspring))
+        .collect();
+    logger_info!(
+        "iteration {}, species {}, next sizes {}",
+        frames.0 / config.train.iteration_length as u128,
+        speciation.species.len(),
+        sizes.join(" ")
+    );
+}
 
-    let total_x_distances: f32 = infovec
-    .iter()
-    .map(|info| {
-        info.move_distance[0]
-    })
-    .sum();
+/// log objective values of all blobs in the Pareto front
+fn log_pareto_front(
+    frames: &Frames,
+    info_q: &Query<&BlobInfo>,
+    config: &EvoConfig,
+    fitness: &TrainFitness,
+) {
+    let objectives = &config.train.objectives;
+    let values: Vec<Vec<f32>> = info_q
+        .iter()
+        .map(|info| objective_values(info, objectives, fitness))
+        .collect();
+    let fronts = non_dominated_sort(&values);
+    let Some(pareto_front) = fronts.first() else {
+        return;
+    };
 
-    let mean_x_distance = total_x_distances / infovec.len() as f32;
+    let blobs: Vec<String> = pareto_front
+        .iter()
+        .map(|&idx| {
+            let pairs: Vec<String> = objectives
+                .iter()
+                .zip(values[idx].iter())
+                .map(|(objective, value)| {
+                    // minimized objectives are negated in `objective_values`
+                    let value = if *objective == Objective::Energy {
+                        -value
+                    } else {
+                        *value
+                    };
+                    format!("{} {:.5}", objective_name(objective, fitness), value)
+                })
+                .collect();
+            format!("[{}]", pairs.join(", "))
+        })
+        .collect();
 
     logger_info!(
-        "iteration {}, top_x_distance {:.5}, mean_x_distance {:.5}, ted {:.5}",
-        frames.0 / ITERATION_LENGTH as u128,
-        top_x_distance,
-        mean_x_distance,
-        ted.0
+        "iteration {}, pareto front {}",
+        frames.0 / config.train.iteration_length as u128,
+        blobs.join(" ")
     );
 }
+
+#[cfg(test)]
+mod train_move_test {
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_hybrid_selection_cap() {
+        let blobs: Vec<(Entity, (BlobGeno, BlobInfo))> = (0..4)
+            .map(|i| (Entity::from_raw(i), (BlobGeno::default(), BlobInfo::default())))
+            .collect();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        // 3 survivers but only 1 outcast to choose from
+        let mut survivers = blobs[..3].to_vec();
+        hybrid_selection(&mut survivers, &blobs, 1.0, 1.0, &mut rng);
+        let entities: HashSet<Entity> = survivers.iter().map(|(e, _)| *e).collect();
+        assert!(entities.contains(&blobs[3].0));
+
+        // all blobs survive, nothing to choose from
+        let mut survivers = blobs.clone();
+        hybrid_selection(&mut survivers, &blobs, 1.0, 1.0, &mut rng);
+        let entities: Vec<Entity> = survivers.iter().map(|(e, _)| *e).collect();
+        assert_eq!(entities, blobs.iter().map(|(e, _)| *e).collect::<Vec<_>>());
+    }
+}
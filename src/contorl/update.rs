@@ -1,7 +1,7 @@
 //! all implementation of framely updates relate to blobs (sensors and activators behavior)
 
+use std::collections::HashMap;
 use std::f32::consts::PI;
-use std::time::Instant;
 
 use bevy::prelude::*;
 use bevy_rapier2d::{
@@ -16,11 +16,13 @@ use crate::{
         geno_blob_builder::BlobGeno,
     },
     brain::{
+        cpg::oscillator_outputs,
         resource::BevyBlockNeurons,
         signal::{BrainSignal, InwardNNInputSignal, SignalHandler},
     },
     componet::{BlobEntityIndex, ColliderFlag},
-    consts::*,
+    config::EvoConfig,
+    profile_span,
 };
 
 use super::resource::{Frames, TED};
@@ -34,6 +36,7 @@ use super::resource::{Frames, TED};
 ///
 /// Can not use `EventReader` multiple times each frame.
 /// Events been read will be marked as read.
+#[allow(clippy::too_many_arguments)]
 pub fn block_action(
     mut brain_q: Query<(&Parent, Entity), With<CenterBlockFlag>>,
     mut block_q: Query<(Entity, &Parent, &mut ImpulseJoint)>,
@@ -43,11 +46,12 @@ pub fn block_action(
     collider_q: Query<&ColliderFlag>,
     joint_info_q: Query<&JointInfo>,
     depth_q: Query<&BlockDepth>,
-    blob_q: Query<&BlobInfo>,
+    blob_q: Query<(&BlobInfo, &BlobGeno)>,
     p_anchor_q: Query<&ParentAnchor>,
+    config: Res<EvoConfig>,
     // mut joint_q: Query<&mut ImpulseJoint>
 ) {
-    let start_time = Instant::now();
+    let _span = profile_span!("block_action");
 
     if block_q.is_empty() {
         assert!(brain_q.is_empty());
@@ -59,8 +63,24 @@ pub fn block_action(
         return;
     }
 
+    let signal_span = profile_span!("signal_collection");
     let mut signal_handler = SignalHandler::default();
-    let mut cf_events_vec = Vec::from_iter(cf_events.into_iter().cloned());
+    let cf_map = contact_map(cf_events.iter());
+
+    // oscillator output of each block, indexed by nn_id, zero if cpg is disabled
+    let mut oscillators = vec![0.0; bbn.nnvec.len()];
+    if config.cpg.enabled {
+        for (blobinfo, geno) in blob_q.iter() {
+            oscillator_outputs(geno, blobinfo.age, &config, &mut oscillators);
+        }
+    }
+    let inward_oscillator = |nn_id: usize| {
+        if config.cpg.inward {
+            oscillators[nn_id]
+        } else {
+            0.0
+        }
+    };
 
     // push inward
     for (child, parent, joint) in block_q.iter_mut() {
@@ -76,7 +96,7 @@ pub fn block_action(
         });
 
         // init signal
-        let cf_singal = get_cf_signal(entity_id, &mut cf_events_vec, &collider_q);
+        let cf_singal = get_cf_signal(entity_id, &cf_map, &collider_q);
         let joint_motor = joint.data.motor(JointAxis::AngX).unwrap();
         let joint_info = joint_info_q.get(entity_id).unwrap();
         let joint_signal = (
@@ -87,7 +107,8 @@ pub fn block_action(
         );
         let inward_signal = InwardNNInputSignal::default()
             .with_cf_signal(cf_singal)
-            .with_joint_singal(joint_signal);
+            .with_joint_singal(joint_signal)
+            .with_oscillator(inward_oscillator(*nn_id));
 
         // push inward signals to signal handler
         // unwarp parent_id, since all inward signal should have parent
@@ -109,36 +130,50 @@ pub fn block_action(
         // should have id so unwrap
         let nn_id = nn_id_q.get(entity_id).unwrap().id;
         // cf_signal
-        let cf_signal = get_cf_signal(entity_id, &mut cf_events_vec, &collider_q);
+        let cf_signal = get_cf_signal(entity_id, &cf_map, &collider_q);
         // blob_signal
         // should in blobinfo so unwrap
-        let blobinfo = blob_q.get(parent.get()).unwrap();
+        let (blobinfo, _) = blob_q.get(parent.get()).unwrap();
 
         signal_handler.push_brain(
             BrainSignal::default()
                 .with_cf_signal(cf_signal)
-                .with_blob_info(blobinfo.mass_center, blobinfo.velocity),
+                .with_blob_info(blobinfo.mass_center, blobinfo.velocity, blobinfo.power)
+                .with_oscillator(oscillators[nn_id]),
             nn_id,
         );
     }
 
+    drop(signal_span);
+
+    // NNs stacked for the batched pass are kept, until NNs are changed by other systems,
+    // changes of this system in the last frame are not counted
+    if bbn.is_changed() {
+        bbn.forget_batches();
+    }
+
     // run neuron
-    let output: Vec<(Entity, f32, f32)> = bbn.get_outputs(signal_handler);
+    let output: Vec<(Entity, f32, f32)> = {
+        let _span = profile_span!("nn_forward");
+        bbn.get_outputs(signal_handler, config.nn.batched)
+    };
 
     // println!("{}",output[1].1);
     // update joints base on nn's output
+    let stiffness = config.joint.motor_stiffness;
+    let damping = config.joint.motor_damping;
     for (entity_id, target_pos, target_vel) in output {
         // println!("{},{}",target_pos,target_vel);
         let (_, _, mut joint) = block_q.get_mut(entity_id).unwrap();
         joint
             .data
-            .set_motor_position(JointAxis::AngX, target_pos, MOTOR_STIFFNESS, MOTOR_DAMPING);
+            .set_motor_position(JointAxis::AngX, target_pos, stiffness, damping);
         joint
             .data
-            .set_motor_velocity(JointAxis::AngX, target_vel, MOTOR_DAMPING);
+            .set_motor_velocity(JointAxis::AngX, target_vel, damping);
     }
 
-    // let output = bbn.get_rand_outputs(signal_handler);
+    // let output = bbn.get_rand_outputs(signal_handler, config.joint.motor_max_target_v);
     // // TODO: make sure the element order in output vec matches the iterator so that they can be zipped together
     // // update physical world
     // for (signal, (_, mut joint)) in output.iter().zip(block_q.iter_mut()) {
@@ -149,27 +184,21 @@ pub fn block_action(
     //         .data
     //         .set_motor_velocity(JointAxis::AngX, signal[1], MOTOR_DAMPING);
     // }
-    let duration = Instant::now() - start_time;
-    if PRINT_FUNCTION_TIME && duration >= MIN_PRINT_DURATION {
-        println!("block_action: {:?}", duration);
-    }
 }
 
-// TODO: test preformance and change to `get_bulk_cf_events()` if necessary
-// loop over all entities over all events might be slow
-// TODO: Takes input of mut ref, returns a clone, considering return the reference
-// need to dealing with lifetime
-//
-/// get contact force event for an entity,
-/// return the first if multiple event shappen at the same time
-fn get_cf_event(
-    entity_id: Entity,
-    cf_events: &mut Vec<ContactForceEvent>,
-) -> Option<ContactForceEvent> {
-    cf_events
-        .iter()
-        .find(|&event| event.collider1 == entity_id || event.collider2 == entity_id)
-        .and_then(|event| Some(event.clone()))
+/// contact force event of each collider in this frame,
+/// the first if multiple events happen at the same time.
+///
+/// built once per frame, so blocks look up their event instead of scanning all events
+pub fn contact_map<'a>(
+    cf_events: impl IntoIterator<Item = &'a ContactForceEvent>,
+) -> HashMap<Entity, &'a ContactForceEvent> {
+    let mut cf_map = HashMap::new();
+    for event in cf_events {
+        cf_map.entry(event.collider1).or_insert(event);
+        cf_map.entry(event.collider2).or_insert(event);
+    }
+    cf_map
 }
 
 /// Not a bevy system.
@@ -181,11 +210,11 @@ fn get_cf_event(
 /// contact blob and contact wall have different signal
 fn get_cf_signal(
     entity_id: Entity,
-    cf_events_vec: &mut Vec<ContactForceEvent>,
+    cf_map: &HashMap<Entity, &ContactForceEvent>,
     blob_flag_q: &Query<&ColliderFlag>,
 ) -> Option<(bool, bool, [f32; 2], f32)> {
     // if contact
-    if let Some(event) = get_cf_event(entity_id, cf_events_vec) {
+    if let Some(event) = cf_map.get(&entity_id) {
         let other = if entity_id == event.collider1 {
             event.collider2
         } else {
@@ -222,7 +251,7 @@ pub fn update_joint_info(
     trans_q: Query<&Transform>,
     veloc_q: Query<&Velocity>,
 ) {
-    let start_time = Instant::now();
+    let _span = profile_span!("update_joint_info");
     for (parent, joint) in parent_joint_q.iter() {
         let parent_id = parent.get();
         let child_id = joint.parent;
@@ -246,10 +275,6 @@ pub fn update_joint_info(
             panic!("update joint info failed!")
         }
     }
-    let duration = Instant::now() - start_time;
-    if PRINT_FUNCTION_TIME && duration >= MIN_PRINT_DURATION {
-        println!("update_joint_info: {:?}", duration);
-    }
 }
 
 /// Calculates the relative rotation between two transforms.
@@ -300,8 +325,9 @@ pub fn get_relative_angular_velocity(v1: &Velocity, v2: &Velocity) -> f32 {
 /// 
 /// it updates:
 /// - mass_center
+/// - mass
 /// - velocity
-/// - cumulated move distance (for move training usage)
+/// - cumulated move distance and path length (for move training usage)
 /// 
 /// # Panics
 ///
@@ -311,8 +337,9 @@ pub fn update_blob_info(
     tc_q: Query<(&Transform, &Collider)>,
     mut blob_q: Query<(&mut BlobInfo, &Children)>,
     frames: Res<Frames>,
+    config: Res<EvoConfig>,
 ) {
-    let start_time = Instant::now();
+    let _span = profile_span!("update_blob_info");
     for (mut blob, children) in blob_q.iter_mut() {
         let mut mass_vec = Vec::<[f32; 3]>::new();
         for child in children {
@@ -324,6 +351,7 @@ pub fn update_blob_info(
                 collider.scale().x * collider.scale().y,
             ])
         }
+        blob.mass = mass_vec.iter().map(|point| point[2]).sum();
         // unwrap since all blob should have at least one block
         let new_mass_center = get_mass_center(mass_vec).unwrap();
         blob.velocity = [
@@ -332,17 +360,59 @@ pub fn update_blob_info(
         ];
 
         // update move_distance
-        if frames.0 % ITERATION_LENGTH as u128 != 1 {
+        if frames.0 % config.train.iteration_length as u128 != 1 {
             blob.move_distance[0] += blob.velocity[0];
             blob.move_distance[1] += blob.velocity[1];
+            blob.path_length += (blob.velocity[0].powi(2) + blob.velocity[1].powi(2)).sqrt();
+            blob.move_frames += 1;
         }
 
         // update mass_center
         blob.mass_center = new_mass_center;
+        blob.age += 1;
+    }
+}
+
+/// **a bevy function**
+///
+/// Updates the energy spent by each blob in this frame.
+///
+/// Each joint motor spends `|torque * angular velocity|`,
+/// the torque is estimated from the motor target the same way as rapier's motor model:
+/// `stiffness * (target_pos - pos) + damping * (target_vel - vel)`.
+/// Each block also spends `metabolic_cost` per second.
+///
+/// it updates:
+/// - power (energy per second of this frame)
+/// - cumulated energy (for training usage)
+pub fn update_blob_energy(
+    joint_q: Query<(&Parent, &ImpulseJoint)>,
+    block_q: Query<(&Parent, &JointInfo)>,
+    mut blob_q: Query<(Entity, &mut BlobInfo, &Children)>,
+    config: Res<EvoConfig>,
+) {
+    let stiffness = config.joint.motor_stiffness;
+    let damping = config.joint.motor_damping;
+
+    let mut blob_power: HashMap<Entity, f32> = HashMap::new();
+    for (parent, joint) in joint_q.iter() {
+        // joint without motor or joint info does not cost energy
+        let (Ok((blob, joint_info)), Some(motor)) =
+            (block_q.get(parent.get()), joint.data.motor(JointAxis::AngX))
+        else {
+            continue;
+        };
+        // joint info is in degree
+        let pos = joint_info.ang_pos.to_radians();
+        let vel = joint_info.ang_velocity.to_radians();
+        let torque = stiffness * (motor.target_pos - pos) + damping * (motor.target_vel - vel);
+        *blob_power.entry(blob.get()).or_insert(0.0) += (torque * vel).abs();
     }
-    let duration = Instant::now() - start_time;
-    if PRINT_FUNCTION_TIME && duration >= MIN_PRINT_DURATION {
-        println!("update_blob_info: {:?}", duration);
+
+    for (entity, mut blob, children) in blob_q.iter_mut() {
+        let metabolic = config.energy.metabolic_cost * children.len() as f32;
+        blob.power = blob_power.get(&entity).copied().unwrap_or(0.0) + metabolic;
+        blob.energy += blob.power * config.timestep.rapier_dt;
     }
 }
 
@@ -388,8 +458,8 @@ pub fn update_iteration_frames(mut frames: ResMut<Frames>) {
     frames.0 += 1;
 }
 
-/// update TED resource
-pub fn update_crowding_distance(
+/// update novelty of all blobs and TED resource
+pub fn update_novelty(
     mut blob_q: Query<(&BlobGeno, &mut BlobInfo)>,
     mut ted: ResMut<TED>,
 ) {
@@ -400,26 +470,24 @@ pub fn update_crowding_distance(
         infovec.push(info.clone());
     }
 
-    // calculate crowding distance
+    // calculate novelty
     for i in 0..genovec.len() {
         let &this_geno = genovec.get(i).unwrap();
         let this_info = infovec.get_mut(i).unwrap();
-        let mut sum_crowding_distance: usize = 0;
+        let mut sum_ted: usize = 0;
         for j in 0..genovec.len() {
             let &other_geno = genovec.get(j).unwrap();
-            sum_crowding_distance +=
-                this_geno.vec_tree.tree_edit_distance(&other_geno.vec_tree) as usize;
+            sum_ted += this_geno.vec_tree.tree_edit_distance(&other_geno.vec_tree);
         }
-        let avg_corwding_distance = sum_crowding_distance as f32 / genovec.len() as f32;
-        this_info.crowding_distance = avg_corwding_distance;
+        this_info.novelty = sum_ted as f32 / genovec.len() as f32;
     }
 
-    let mut cd: f32 = 0.0;
-    // update crowding distance
+    let mut total_novelty: f32 = 0.0;
+    // update novelty
     for (i, (_, mut info)) in blob_q.iter_mut().enumerate() {
         *info = infovec.get(i).unwrap().clone();
-        cd += infovec.get(i).unwrap().crowding_distance;
+        total_novelty += infovec.get(i).unwrap().novelty;
     }
 
-    ted.0 = cd / blob_q.iter().len() as f32;
+    ted.0 = total_novelty / blob_q.iter().len() as f32;
 }
@@ -2,11 +2,11 @@
 
 use bevy::{prelude::*, window::PresentMode};
 use bevy_pancam::{PanCam, PanCamPlugin};
-use bevy_rapier2d::prelude::{RapierConfiguration, TimestepMode};
+use bevy_rapier2d::prelude::RapierDebugRenderPlugin;
 
 // use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 
-use crate::consts::{AUTO_NO_VSYNC_KEYCODE, RAPIER_DT, RAPIER_SUBSTEPS};
+use crate::consts::AUTO_NO_VSYNC_KEYCODE;
 
 #[derive(Component)]
 pub struct MainCamera;
@@ -16,7 +16,7 @@ pub struct MainCamera;
 /// includes
 /// - camera & camera contorl
 /// - vsgnc & novsync
-/// - time step contorl
+/// - physics debug render
 pub struct EvoGraphicsPlugin;
 
 impl Plugin for EvoGraphicsPlugin {
@@ -24,19 +24,12 @@ impl Plugin for EvoGraphicsPlugin {
         app.add_systems(Startup, setup_graphics)
             .add_plugins((
                 PanCamPlugin::default(),
+                RapierDebugRenderPlugin::default(),
                 // // log frame rate
                 // LogDiagnosticsPlugin::default(),
                 // FrameTimeDiagnosticsPlugin::default(),
             ))
-            .add_systems(Update, toggle_vsync)
-            // using Fixed timestep so that the simulation can speed up
-            .insert_resource(RapierConfiguration {
-                timestep_mode: TimestepMode::Fixed {
-                    dt: RAPIER_DT,
-                    substeps: RAPIER_SUBSTEPS,
-                },
-                ..default()
-            });
+            .add_systems(Update, toggle_vsync);
     }
 
     
@@ -0,0 +1,122 @@
+//! Headless mode, run the simulation without window, renderer and keyboard
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    blob::{blob::BlobInfo, block::NeuronId, geno_blob_builder::BlobGeno, lineage::Genealogy},
+    brain::resource::BevyBlockNeurons,
+    config::EvoConfig,
+    contorl::{
+        fitness::TrainFitness,
+        resource::{EvoRng, Frames, TrainMutPipe, TED},
+        species::Speciation,
+        train_move::{log_train_move, train_move},
+    },
+    io::{
+        checkpoint::{CheckpointHeader, RunState},
+        export::{collect_export_file, is_checkpoints},
+    },
+    logger_info, logger_warn,
+    mutate::mutate::mutate_and_refresh_after_train,
+};
+
+/// all implementations relate to headless training
+///
+/// include
+/// - iteration limit
+/// - final checkpoint before exit, not in replay
+///
+/// Notice: this plugin do not add any window, renderer or input plugin,
+/// it should be used with `MinimalPlugins`.
+/// Checkpoints during the run are still saved by `EvoIOPlugin`.
+pub struct EvoHeadlessPlugin {
+    /// how many iterations to train before exit
+    pub iterations: usize,
+    /// replay a checkpoint, there is no training state to save
+    pub replay: bool,
+}
+
+impl Plugin for EvoHeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(IterationLimit(self.iterations));
+        if self.replay {
+            app.add_systems(
+                Update,
+                exit_after_iterations
+                    .after(log_train_move)
+                    .run_if(resource_exists::<Frames>()),
+            );
+        } else {
+            app.add_systems(
+                Update,
+                // the population will be replaced after mutation,
+                // so the final checkpoint should be saved before that,
+                // but after selection, so that the run can be resumed
+                stop_after_iterations
+                    .after(train_move)
+                    .before(mutate_and_refresh_after_train)
+                    .run_if(resource_exists::<Frames>()),
+            );
+        }
+    }
+}
+
+/// max iteration count of a headless run
+#[derive(Resource)]
+pub struct IterationLimit(pub usize);
+
+impl IterationLimit {
+    pub fn reached(&self, frames: &Frames, config: &EvoConfig) -> bool {
+        frames.0 >= (self.0 * config.train.iteration_length) as u128
+    }
+}
+
+/// exit the app when the iteration limit reached, without saving a checkpoint
+pub fn exit_after_iterations(
+    frames: Res<Frames>,
+    limit: Res<IterationLimit>,
+    config: Res<EvoConfig>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if limit.reached(&frames, &config) {
+        logger_info!("headless replay finished after {} iterations", limit.0);
+        exit.send(AppExit);
+    }
+}
+
+/// save the final checkpoint and exit the app when the iteration limit reached
+#[allow(clippy::too_many_arguments)]
+pub fn stop_after_iterations(
+    frames: Res<Frames>,
+    limit: Res<IterationLimit>,
+    blob_q: Query<(Entity, (&BlobGeno, &BlobInfo))>,
+    nn_q: Query<(&Parent, &NeuronId)>,
+    bbn: Res<BevyBlockNeurons>,
+    config: Res<EvoConfig>,
+    rng: Res<EvoRng>,
+    ted: Res<TED>,
+    fitness: Res<TrainFitness>,
+    speciation: Res<Speciation>,
+    genealogy: Res<Genealogy>,
+    pipe: Res<TrainMutPipe>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !limit.reached(&frames, &config) {
+        return;
+    }
+
+    // checkpoint of this frame has already been saved by `export`
+    if !is_checkpoints(&frames, &config) && !blob_q.is_empty() {
+        let infos: Vec<&BlobInfo> = blob_q.iter().map(|(_, (_, info))| info).collect();
+        let ef = collect_export_file(&blob_q, &nn_q, &bbn.nnvec)
+            .with_header(CheckpointHeader::new(&config, &frames, &rng, &ted, &fitness, &infos))
+            .with_fitness(infos.iter().map(|info| fitness.score(info)).collect())
+            .with_run_state(RunState::new(&speciation, &genealogy, &pipe));
+        if let Err(e) = ef.save(&config) {
+            logger_warn!("failed to save final checkpoint: {}", e);
+        }
+    }
+
+    logger_info!("headless run finished after {} iterations", limit.0);
+    exit.send(AppExit);
+}
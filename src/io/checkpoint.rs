@@ -0,0 +1,538 @@
+//! Versioned checkpoint format
+//!
+//! Every `ExportFile` starts with a `CheckpointHeader`, which records the format version,
+//! the config and the state of the run when the file is saved.
+//!
+//! Checkpoints saved by training also hold a `RunState`,
+//! so that the run can be resumed exactly where it left off,
+//! and the fitness of each blob, so that the best blobs can be picked when loading.
+//!
+//! Files saved before the header exists are version 0,
+//! they are migrated to `CHECKPOINT_VERSION` when loading.
+//!
+//! A checkpoint is encoded as JSON, CBOR or zstd compressed CBOR,
+//! chosen by the file extension, see `CheckpointFormat`.
+//! CBOR keeps field names like JSON does,
+//! so fields added with `serde(default)` load from older files of the same version.
+
+use std::fmt;
+
+use ndarray::{s, Array2};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    blob::{blob::BlobInfo, geno_blob_builder::BlobGeno, lineage::Genealogy},
+    brain::{neuron::GenericNN, nn::BaseNN},
+    config::{CheckpointFormat, EvoConfig},
+    consts::{BRAIN_NN_INPUT_LEN, INWARD_NN_INPUT_LEN},
+    contorl::{
+        fitness::TrainFitness,
+        resource::{EvoRng, Frames, RngState, TrainMutPipe, TED},
+        species::Speciation,
+    },
+};
+
+use super::export::ExportFile;
+
+/// version of the checkpoint format written by this build
+///
+/// version 1 has the header, run state, fitness of blobs,
+/// recurrent layers, oscillators and node activations
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// compression level of `.cbor.zst` checkpoints
+const ZSTD_LEVEL: i32 = 3;
+
+/// metadata of a checkpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointHeader {
+    pub format_version: u32,
+    /// version of evosim which saved the file
+    pub crate_version: String,
+    /// config of the run, `None` for migrated files
+    pub config: Option<EvoConfig>,
+    pub frames: u128,
+    pub iteration: usize,
+    pub seed: Option<u64>,
+    pub rng: Option<RngState>,
+    /// Tree Edit Distance of the population
+    pub ted: Option<f32>,
+    pub fitness: Option<FitnessStats>,
+}
+
+impl Default for CheckpointHeader {
+    fn default() -> Self {
+        Self {
+            format_version: CHECKPOINT_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            config: None,
+            frames: 0,
+            iteration: 0,
+            seed: None,
+            rng: None,
+            ted: None,
+            fitness: None,
+        }
+    }
+}
+
+impl CheckpointHeader {
+    /// header of the current state of a run
+    pub fn new(
+        config: &EvoConfig,
+        frames: &Frames,
+        rng: &EvoRng,
+        ted: &TED,
+        fitness: &TrainFitness,
+        infos: &[&BlobInfo],
+    ) -> Self {
+        Self {
+            config: Some(config.clone()),
+            frames: frames.0,
+            iteration: (frames.0 / config.train.iteration_length as u128) as usize,
+            seed: config.train.seed,
+            rng: Some(rng.state()),
+            ted: Some(ted.0),
+            fitness: FitnessStats::new(fitness, infos),
+            ..Default::default()
+        }
+    }
+}
+
+/// training state that is not in the header, saved after selection
+///
+/// `Frames`, `TED` and `EvoRng` are restored from the header
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RunState {
+    pub speciation: Speciation,
+    pub genealogy: Genealogy,
+    /// population selected at the end of the iteration, waiting for mutation.
+    ///
+    /// empty if the checkpoint is saved during an iteration
+    pub next_genovec: Vec<BlobGeno>,
+    /// spawn positions of `next_genovec`
+    pub next_posvec: Vec<[f32; 2]>,
+    /// indexed by nn ids of `next_genovec`
+    pub next_nnvec: Vec<GenericNN>,
+}
+
+impl RunState {
+    pub fn new(speciation: &Speciation, genealogy: &Genealogy, pipe: &TrainMutPipe) -> Self {
+        let (genovec, infovec, nnvec) = pipe.peek();
+        Self {
+            speciation: speciation.clone(),
+            genealogy: genealogy.clone(),
+            next_genovec: genovec.to_vec(),
+            next_posvec: infovec
+                .iter()
+                .map(|info| info.center_block_pos.to_array())
+                .collect(),
+            next_nnvec: nnvec.to_vec(),
+        }
+    }
+
+    /// the checkpoint is saved at the end of an iteration
+    pub fn has_next(&self) -> bool {
+        !self.next_genovec.is_empty()
+    }
+}
+
+/// fitness of the population when the checkpoint is saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FitnessStats {
+    /// name of the fitness function
+    pub name: String,
+    pub best: f32,
+    pub mean: f32,
+    pub worst: f32,
+}
+
+impl FitnessStats {
+    /// `None` if there is no blob
+    pub fn new(fitness: &TrainFitness, infos: &[&BlobInfo]) -> Option<Self> {
+        if infos.is_empty() {
+            return None;
+        }
+        let scores: Vec<f32> = infos.iter().map(|info| fitness.score(info)).collect();
+        Some(Self {
+            name: fitness.name().to_string(),
+            best: scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max),
+            mean: scores.iter().sum::<f32>() / scores.len() as f32,
+            worst: scores.iter().cloned().fold(f32::INFINITY, f32::min),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Encode(ciborium::ser::Error<std::io::Error>),
+    Decode(ciborium::de::Error<std::io::Error>),
+    /// saved by a newer build
+    UnsupportedVersion(u32),
+    /// `genovec`, `nnvec`, `posvec` and `fitnessvec` are not paired
+    LengthMismatch {
+        genos: usize,
+        nns: usize,
+        positions: usize,
+        scores: usize,
+    },
+    /// nn ids of the file should be `0..n`
+    NNIdNotContiguous(usize),
+    /// geno of the blob uses a nn that is not saved with it
+    MissingNN { blob: usize, nn_id: usize },
+    /// no blob with the genome id
+    MissingBlob(usize),
+    /// error of a file when loading several ones
+    InFile {
+        path: String,
+        source: Box<CheckpointError>,
+    },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "can not read checkpoint: {}", e),
+            CheckpointError::Parse(e) => write!(f, "can not parse checkpoint: {}", e),
+            CheckpointError::Encode(e) => write!(f, "can not encode checkpoint: {}", e),
+            CheckpointError::Decode(e) => write!(f, "can not decode checkpoint: {}", e),
+            CheckpointError::UnsupportedVersion(version) => write!(
+                f,
+                "checkpoint version {} is newer than supported version {}",
+                version, CHECKPOINT_VERSION
+            ),
+            CheckpointError::LengthMismatch {
+                genos,
+                nns,
+                positions,
+                scores,
+            } => write!(
+                f,
+                "{} genos, {} nn lists, {} positions and {} scores are not paired",
+                genos, nns, positions, scores
+            ),
+            CheckpointError::NNIdNotContiguous(nn_id) => {
+                write!(f, "nn ids are not contiguous at {}", nn_id)
+            }
+            CheckpointError::MissingNN { blob, nn_id } => {
+                write!(f, "nn {} of blob {} is not saved", nn_id, blob)
+            }
+            CheckpointError::MissingBlob(id) => write!(f, "no blob with genome id {}", id),
+            CheckpointError::InFile { path, source } => write!(f, "{}: {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+/// encode a checkpoint in `format`
+pub fn encode(ef: &ExportFile, format: CheckpointFormat) -> Result<Vec<u8>, CheckpointError> {
+    match format {
+        CheckpointFormat::Json => serde_json::to_vec(ef).map_err(CheckpointError::Parse),
+        CheckpointFormat::Cbor => encode_cbor(ef),
+        CheckpointFormat::CborZstd => {
+            let bytes = encode_cbor(ef)?;
+            zstd::encode_all(bytes.as_slice(), ZSTD_LEVEL).map_err(CheckpointError::Io)
+        }
+    }
+}
+
+/// decode a checkpoint of any supported version in `format`,
+/// and migrate it to the current version
+pub fn decode(bytes: &[u8], format: CheckpointFormat) -> Result<ExportFile, CheckpointError> {
+    match format {
+        CheckpointFormat::Json => {
+            parse_value(serde_json::from_slice(bytes).map_err(CheckpointError::Parse)?)
+        }
+        CheckpointFormat::Cbor => decode_cbor(bytes),
+        CheckpointFormat::CborZstd => {
+            decode_cbor(&zstd::decode_all(bytes).map_err(CheckpointError::Io)?)
+        }
+    }
+}
+
+/// parse a JSON checkpoint of any supported version, and migrate it to the current version
+pub fn parse(file_str: &str) -> Result<ExportFile, CheckpointError> {
+    parse_value(serde_json::from_str(file_str).map_err(CheckpointError::Parse)?)
+}
+
+fn parse_value(value: Value) -> Result<ExportFile, CheckpointError> {
+    let version = format_version(&value);
+    let ef = match version {
+        0 => migrate_v0(serde_json::from_value(value).map_err(CheckpointError::Parse)?),
+        CHECKPOINT_VERSION => serde_json::from_value(value).map_err(CheckpointError::Parse)?,
+        _ => return Err(CheckpointError::UnsupportedVersion(version)),
+    };
+    ef.check()?;
+    Ok(ef)
+}
+
+fn encode_cbor(ef: &ExportFile) -> Result<Vec<u8>, CheckpointError> {
+    let mut bytes = Vec::new();
+    ciborium::ser::into_writer(ef, &mut bytes).map_err(CheckpointError::Encode)?;
+    Ok(bytes)
+}
+
+/// only the header of a file, to check the version before decoding the rest
+#[derive(Deserialize)]
+struct VersionProbe {
+    header: VersionProbeHeader,
+}
+
+#[derive(Deserialize)]
+struct VersionProbeHeader {
+    format_version: u32,
+}
+
+/// there is no binary file before version 1
+fn decode_cbor(bytes: &[u8]) -> Result<ExportFile, CheckpointError> {
+    let probe: VersionProbe = ciborium::de::from_reader(bytes).map_err(CheckpointError::Decode)?;
+    let version = probe.header.format_version;
+    if version != CHECKPOINT_VERSION {
+        return Err(CheckpointError::UnsupportedVersion(version));
+    }
+    let ef: ExportFile = ciborium::de::from_reader(bytes).map_err(CheckpointError::Decode)?;
+    ef.check()?;
+    Ok(ef)
+}
+
+/// files without header are version 0
+fn format_version(value: &Value) -> u32 {
+    value
+        .get("header")
+        .and_then(|header| header.get("format_version"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32
+}
+
+/// file saved before the header exists
+#[derive(Serialize, Deserialize)]
+struct ExportFileV0 {
+    genovec: Vec<BlobGeno>,
+    nnvec: Vec<Vec<(GenericNN, usize)>>,
+    posvec: Vec<[f32; 2]>,
+}
+
+/// nn ids of version 0 are indices of the world which saved the file,
+/// brain and inward NNs do not have the power and oscillator inputs,
+/// genos get the default oscillator and no phase offsets
+fn migrate_v0(v0: ExportFileV0) -> ExportFile {
+    let mut ef = ExportFile {
+        header: CheckpointHeader::default(),
+        fitnessvec: vec![None; v0.genovec.len()],
+        genovec: v0.genovec,
+        nnvec: v0.nnvec,
+        posvec: v0.posvec,
+        run_state: None,
+    };
+    for (nn, _) in ef.nnvec.iter_mut().flatten() {
+        match nn {
+            GenericNN::BRAINNN(brain) => pad_input(&mut brain.nn, BRAIN_NN_INPUT_LEN),
+            GenericNN::BLOCKNN(block) => pad_input(&mut block.inward_nn.nn, INWARD_NN_INPUT_LEN),
+        }
+    }
+    ef.renumber_nn();
+    ef
+}
+
+/// append zero weights for the missing inputs of the first layer,
+/// so that the NN behaves the same as before
+fn pad_input(nn: &mut BaseNN, input_len: usize) {
+    let Some(layer) = nn.layers.first_mut() else {
+        return;
+    };
+    let (rows, cols) = layer.weights.dim();
+    if cols >= input_len {
+        return;
+    }
+    let mut weights = Array2::<f32>::zeros((rows, input_len));
+    weights.slice_mut(s![.., ..cols]).assign(&layer.weights);
+    layer.weights = weights;
+}
+
+#[cfg(test)]
+mod checkpoint_test {
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::brain::neuron::{BlockNN, BrainNN};
+
+    /// version 0 file of a blob, brain and inward NNs without
+    /// power and oscillator inputs, nn ids start from `offset`
+    fn v0_file(offset: usize) -> String {
+        let config = EvoConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut geno = BlobGeno::new_rand(&config.geno, &mut rng);
+        let mut nns = Vec::<(GenericNN, usize)>::new();
+        for (i, nn_id) in geno.all_nn_ids_mut().into_iter().enumerate() {
+            let nn = if i == 0 {
+                let mut shape = config.nn.brain_shape();
+                shape[0] = BRAIN_NN_INPUT_LEN - 2;
+                GenericNN::BRAINNN(BrainNN {
+                    nn: BaseNN::new_rand(shape, config.nn.activation.clone(), &mut rng),
+                })
+            } else {
+                let mut block = BlockNN::new(&config.nn, &mut rng);
+                let weights = &mut block.inward_nn.nn.layers[0].weights;
+                *weights = weights.slice(s![.., ..INWARD_NN_INPUT_LEN - 1]).to_owned();
+                GenericNN::BLOCKNN(block)
+            };
+            *nn_id = Some(offset + i);
+            nns.push((nn, offset + i));
+        }
+        serde_json::to_string(&ExportFileV0 {
+            genovec: vec![geno],
+            nnvec: vec![nns],
+            posvec: vec![[0.0, 0.0]],
+        })
+        .unwrap()
+    }
+
+    /// first layer weights of the NN, which takes the blob inputs
+    fn input_weights(nn: &GenericNN) -> &Array2<f32> {
+        match nn {
+            GenericNN::BRAINNN(brain) => &brain.nn.layers[0].weights,
+            GenericNN::BLOCKNN(block) => &block.inward_nn.nn.layers[0].weights,
+        }
+    }
+
+    #[test]
+    fn test_migrate_v0() {
+        let v0 = v0_file(10);
+        let ef = parse(&v0).unwrap();
+        assert_eq!(ef.header().format_version, CHECKPOINT_VERSION);
+        assert!(ef.run_state().is_none());
+        assert_eq!(ef.fitnessvec, vec![None]);
+
+        let (geno, _, nns) = ef.iter().next().unwrap();
+        let ids: Vec<usize> = nns.iter().map(|(_, id)| *id).collect();
+        assert_eq!(ids, (0..nns.len()).collect::<Vec<usize>>());
+        assert_eq!(geno.all_usize_nn_ids(), ids);
+        assert!(matches!(nns[0].0, GenericNN::BRAINNN(_)));
+
+        // missing inputs are padded with zero weights
+        let old: ExportFileV0 = serde_json::from_str(&v0).unwrap();
+        for ((nn, _), (old_nn, _)) in nns.iter().zip(old.nnvec[0].iter()) {
+            let (weights, old_weights) = (input_weights(nn), input_weights(old_nn));
+            let input_len = match nn {
+                GenericNN::BRAINNN(_) => BRAIN_NN_INPUT_LEN,
+                GenericNN::BLOCKNN(_) => INWARD_NN_INPUT_LEN,
+            };
+            assert_eq!(weights.ncols(), input_len);
+            let cols = old_weights.ncols();
+            assert_eq!(weights.slice(s![.., ..cols]), old_weights);
+            assert!(weights.slice(s![.., cols..]).iter().all(|w| *w == 0.0));
+        }
+
+        // saved again as the current version
+        let saved = serde_json::to_string(&ef).unwrap();
+        let loaded = parse(&saved).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), saved);
+    }
+
+    #[test]
+    fn test_checkpoint_error() {
+        let mut value: Value = serde_json::from_str(&v0_file(0)).unwrap();
+        value["posvec"] = serde_json::json!([]);
+        assert!(matches!(
+            parse(&value.to_string()),
+            Err(CheckpointError::LengthMismatch { .. })
+        ));
+
+        let ef = parse(&v0_file(0)).unwrap();
+        let mut value = serde_json::to_value(&ef).unwrap();
+        value["header"]["format_version"] = serde_json::json!(CHECKPOINT_VERSION + 1);
+        assert!(matches!(
+            parse(&value.to_string()),
+            Err(CheckpointError::UnsupportedVersion(_))
+        ));
+
+        assert!(matches!(parse("{"), Err(CheckpointError::Parse(_))));
+    }
+
+    #[test]
+    fn test_merge_top() {
+        let mut a = parse(&v0_file(0)).unwrap();
+        a.fitnessvec = vec![Some(1.0)];
+        let b = parse(&v0_file(0)).unwrap();
+        let mut c = parse(&v0_file(0)).unwrap();
+        c.fitnessvec = vec![Some(2.0)];
+        let nn_len = a.nnvec[0].len();
+
+        let merged = ExportFile::merge(vec![a, b, c]);
+        assert_eq!(merged.len(), 3);
+        merged.check().unwrap();
+        assert_eq!(merged.genovec[2].all_usize_nn_ids()[0], 2 * nn_len);
+
+        // unknown fitness comes last
+        let top = merged.clone().top(2);
+        top.check().unwrap();
+        assert_eq!(top.fitnessvec, vec![Some(2.0), Some(1.0)]);
+        assert_eq!(top.genovec[0].all_usize_nn_ids()[0], 0);
+        assert_eq!(merged.top(5).fitnessvec, vec![Some(2.0), Some(1.0), None]);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let ef = parse(&v0_file(0)).unwrap();
+        let json = encode(&ef, CheckpointFormat::Json).unwrap();
+        for format in [CheckpointFormat::Cbor, CheckpointFormat::CborZstd] {
+            let bytes = encode(&ef, format).unwrap();
+            assert!(bytes.len() < json.len());
+            let decoded = decode(&bytes, format).unwrap();
+            assert_eq!(encode(&decoded, CheckpointFormat::Json).unwrap(), json);
+        }
+
+        assert_eq!(
+            CheckpointFormat::from_path("export/a.cbor.zst"),
+            Some(CheckpointFormat::CborZstd)
+        );
+        assert_eq!(
+            CheckpointFormat::from_path("export/a.cbor"),
+            Some(CheckpointFormat::Cbor)
+        );
+        assert_eq!(
+            CheckpointFormat::from_path("export/a.json"),
+            Some(CheckpointFormat::Json)
+        );
+        assert_eq!(CheckpointFormat::from_path("export/a.txt"), None);
+    }
+
+    #[test]
+    fn test_binary_default_field() {
+        let ef = parse(&v0_file(0)).unwrap();
+        let saved = serde_json::to_value(&ef).unwrap();
+
+        // saved before the defaulted fields of genos exist
+        let mut value = saved.clone();
+        let geno = value["genovec"][0].as_object_mut().unwrap();
+        for field in ["species", "lineage", "cpg"] {
+            geno.remove(field).unwrap();
+        }
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&value, &mut bytes).unwrap();
+        let decoded = decode(&bytes, CheckpointFormat::Cbor).unwrap();
+        assert_eq!(decoded.genovec[0].species, None);
+        assert_eq!(decoded.len(), ef.len());
+
+        // the rest of the file is kept
+        let mut restored = serde_json::to_value(&decoded).unwrap();
+        for field in ["species", "lineage", "cpg"] {
+            restored["genovec"][0][field] = saved["genovec"][0][field].clone();
+        }
+        assert_eq!(restored, saved);
+
+        // newer files are not decoded
+        value["header"]["format_version"] = serde_json::json!(CHECKPOINT_VERSION + 1);
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&value, &mut bytes).unwrap();
+        assert!(matches!(
+            decode(&bytes, CheckpointFormat::Cbor),
+            Err(CheckpointError::UnsupportedVersion(_))
+        ));
+    }
+}
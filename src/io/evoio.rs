@@ -2,9 +2,25 @@
 
 use bevy::prelude::*;
 
-use crate::contorl::update::block_action;
+use crate::{
+    blob::lineage::Genealogy,
+    contorl::{
+        fitness::TrainFitness,
+        resource::EvoRng,
+        train_move::{log_train_move, train_move},
+        update::block_action,
+    },
+    mutate::mutate::{mutate_and_refresh, mutate_and_refresh_after_train},
+    profile::{export_profile, Profiler},
+};
 
-use super::{export::export, import::{load_blobs, clean}};
+use super::{
+    export::export,
+    import::{load_blobs, clean},
+    lineage::export_lineage,
+    metrics::{export_metrics, IterationClock},
+    specimen::{export_specimen, load_specimens, spawn_specimens, SpawnSpecimen},
+};
 
 /// all implementations relate to import and export (save and load)
 /// 
@@ -13,16 +29,40 @@ use super::{export::export, import::{load_blobs, clean}};
 /// - save to file
 /// - clean field
 /// - automatic checkpoint save
+/// - lineage of blobs in each iteration
+/// - training metrics of each iteration
+/// - stage times of each iteration if profiling
+/// - save and spawn single-blob specimens
 pub struct EvoIOPlugin;
 
 impl Plugin for EvoIOPlugin {
     fn build(&self, app: &mut App) {
         app
+        .init_resource::<EvoRng>()
+        .init_resource::<Genealogy>()
+        .init_resource::<IterationClock>()
+        .add_event::<SpawnSpecimen>()
+        // checkpoints are saved after selection, so that the run can be resumed
+        .add_systems(Update, export.after(train_move).before(mutate_and_refresh_after_train))
+        // lineage is saved before the population is replaced
+        .add_systems(Update, (export_lineage, export_metrics)
+            .after(log_train_move)
+            .before(train_move)
+            .run_if(resource_exists::<TrainFitness>()))
+        .add_systems(Update, export_profile
+            .after(log_train_move)
+            .before(train_move)
+            .run_if(resource_exists::<Profiler>()))
+        // specimens are added to the population, not replaced by the next one
+        .add_systems(Update, spawn_specimens.after(mutate_and_refresh_after_train))
+        // load and clean are keyboard contorl only,
+        // skip them if there is no keyboard (headless mode)
         .add_systems(Update, (
-            export,
             clean.after(block_action),
-            load_blobs.after(clean),
-        ))
+            load_blobs.after(clean).after(mutate_and_refresh),
+            export_specimen.run_if(resource_exists::<TrainFitness>()),
+            load_specimens.before(spawn_specimens),
+        ).run_if(resource_exists::<Input<KeyCode>>()))
         ;
     }
 }
\ No newline at end of file
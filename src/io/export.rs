@@ -1,61 +1,142 @@
 //! Serialize and export the simulation stage
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
-use std::{fs, path::Path};
+use std::fs;
 
 use bevy::prelude::*;
 use serde::{Serialize, Deserialize};
 use chrono::{Local, NaiveDateTime, Datelike, Timelike};
 
 use crate::blob::blob::BlobInfo;
-use crate::consts::{SAVE_ALL_BLOBS_TO_JSON, ITERATION_LENGTH, CHECKPOINTS_LENGTH};
-use crate::contorl::resource::Frames;
-use crate::logger_info;
+use crate::config::{CheckpointFormat, EvoConfig};
+use crate::consts::SAVE_ALL_BLOBS_TO_JSON;
+use crate::blob::lineage::Genealogy;
+use crate::contorl::{
+    fitness::TrainFitness,
+    resource::{EvoRng, Frames, TrainMutPipe, TED},
+    species::Speciation,
+};
+use crate::{logger_info, logger_warn};
 use crate::{
     blob::{block::NeuronId, geno_blob_builder::BlobGeno},
     brain::{resource::BevyBlockNeurons, neuron::GenericNN},
-    consts::EXPORT_PATH,
 };
 
+use super::checkpoint::{self, CheckpointError, CheckpointHeader, RunState};
+use super::retention::{self, CheckpointEntry};
+
+/// suffix of the config file saved next to each export
+pub const CONFIG_FILE_SUFFIX: &str = ".config.json";
+
 /// struct for file to save & load
 /// 
-/// `genovec`, `nnvec`, `posvec` are paired
-#[derive(Serialize,Deserialize)]
+/// `genovec`, `nnvec`, `posvec`, `fitnessvec` are paired,
+/// nn ids are `0..n` in the order of blobs, see `checkpoint.rs` for the format
+#[derive(Serialize,Deserialize,Clone)]
 pub struct ExportFile{
-    genovec: Vec<BlobGeno>,
+    pub(super) header: CheckpointHeader,
+    pub(super) genovec: Vec<BlobGeno>,
     /// nested vec, outer relate to blob, inner relate to block (blob's limb)
-    nnvec: Vec<Vec<(GenericNN,usize)>>,
-    posvec: Vec<[f32;2]>
+    pub(super) nnvec: Vec<Vec<(GenericNN,usize)>>,
+    pub(super) posvec: Vec<[f32;2]>,
+    /// fitness of each blob when saved, `None` if unknown
+    pub(super) fitnessvec: Vec<Option<f32>>,
+    /// `None` if the file is not saved by training
+    pub(super) run_state: Option<RunState>,
 }
 
 impl ExportFile {
     fn new() -> Self {
         Self{
+            header: CheckpointHeader::default(),
             genovec: Vec::<BlobGeno>::new(),
             nnvec: Vec::<Vec<(GenericNN,usize)>>::new(),
-            posvec: Vec::<[f32;2]>::new()
+            posvec: Vec::<[f32;2]>::new(),
+            fitnessvec: Vec::<Option<f32>>::new(),
+            run_state: None,
         }
     }
 
+    /// load an exported file or checkpoint, older versions are migrated.
+    ///
+    /// encoding is chosen by file extension, unknown extensions are read as JSON
+    pub fn load(path: &str) -> Result<Self, CheckpointError> {
+        let bytes = fs::read(path).map_err(CheckpointError::Io)?;
+        let format = CheckpointFormat::from_path(path).unwrap_or(CheckpointFormat::Json);
+        checkpoint::decode(&bytes, format)
+    }
+
+    pub fn header(&self) -> &CheckpointHeader {
+        &self.header
+    }
+
+    pub fn with_header(mut self, header: CheckpointHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn run_state(&self) -> Option<&RunState> {
+        self.run_state.as_ref()
+    }
+
+    pub fn with_run_state(mut self, run_state: RunState) -> Self {
+        self.run_state = Some(run_state);
+        self
+    }
+
+    /// fitness of blobs in the order of `genovec`
+    pub fn with_fitness(mut self, scores: Vec<f32>) -> Self {
+        assert_eq!(scores.len(), self.genovec.len());
+        self.fitnessvec = scores.into_iter().map(Some).collect();
+        self
+    }
+
     pub fn push_blob(&mut self, blob: (&BlobGeno,&BlobInfo)){
         self.genovec.push(blob.0.clone());
         self.posvec.push(blob.1.center_block_pos.into());
+        self.fitnessvec.push(None);
     }
 
     pub fn push_nn(&mut self, nnvec: Vec<(GenericNN,usize)>){
         self.nnvec.push(nnvec)
     }
 
-    pub fn save(&self){
-        assert_eq!(self.genovec.len(),self.nnvec.len());
-        assert_eq!(self.genovec.len(),self.posvec.len());
-        let file_str = serde_json::to_string(&self).unwrap();
-        let fname = format!("{}{}",EXPORT_PATH,current_time_filename());
-        let mut file = File::create(&fname).expect("Unable to create file");
-        file.write_all(file_str.as_bytes()).expect("Unable to write data");
-        info!("MODEL SAVED {}", &fname);
+    /// save to `export_path` in `checkpoint_format`, the resolved config is saved next to it
+    /// as `<name>.config.json` so that the run can be reproduced.
+    ///
+    /// named by run id and iteration, old checkpoints of the run are removed
+    /// by the retention policy, see `retention.rs`. return the saved file
+    pub fn save(&self, config: &EvoConfig) -> Result<String, CheckpointError> {
+        let export_path = &config.io.export_path;
+        create_if_not_exist(export_path).map_err(CheckpointError::Io)?;
+        let name = retention::checkpoint_name(config, &self.header);
+        let file = format!("{}.{}", name, config.io.checkpoint_format.extension());
+        let config_file = format!("{}{}", name, CONFIG_FILE_SUFFIX);
+        self.write(&format!("{}{}", export_path, file))?;
+        config
+            .save(&format!("{}{}", export_path, config_file))
+            .map_err(CheckpointError::Io)?;
+
+        let fname = format!("{}{}", export_path, file);
+        retention::record(config, CheckpointEntry {
+            file,
+            config_file,
+            frames: self.header.frames,
+            fitness: self.header.fitness.as_ref().map(|fitness| fitness.best),
+        })
+        .map_err(CheckpointError::Io)?;
         logger_info!("MODEL SAVED {}", &fname);
+        Ok(fname)
+    }
+
+    /// write to `fname`, encoding is chosen by file extension like `load`
+    pub fn write(&self, fname: &str) -> Result<(), CheckpointError> {
+        self.check()?;
+        let format = CheckpointFormat::from_path(fname).unwrap_or(CheckpointFormat::Json);
+        let bytes = checkpoint::encode(self, format)?;
+        write_atomic(fname, &bytes).map_err(CheckpointError::Io)
     }
 
     pub fn len(&self) -> usize{
@@ -63,7 +144,11 @@ impl ExportFile {
         self.genovec.len()
     }
 
-    pub fn iter(&self) -> ExportFileIter {
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> ExportFileIter<'_> {
         ExportFileIter {
             geno_iter: self.genovec.iter(),
             nn_iter: self.nnvec.iter(),
@@ -71,7 +156,7 @@ impl ExportFile {
         }
     }
 
-    pub fn iter_mut(&mut self) -> ExportFileIterMut {
+    pub fn iter_mut(&mut self) -> ExportFileIterMut<'_> {
         ExportFileIterMut { 
             geno_iter: self.genovec.iter_mut(), 
             nn_iter: self.nnvec.iter_mut(), 
@@ -79,9 +164,115 @@ impl ExportFile {
         }
     }
 
-    pub fn check(&self) {
-        assert_eq!(self.genovec.len(),self.nnvec.len());
-        assert_eq!(self.genovec.len(),self.posvec.len());
+    /// blobs are paired, nn ids are `0..n`, and every nn of a geno is saved with it
+    pub fn check(&self) -> Result<(), CheckpointError> {
+        if self.genovec.len() != self.nnvec.len()
+            || self.genovec.len() != self.posvec.len()
+            || self.genovec.len() != self.fitnessvec.len()
+        {
+            return Err(CheckpointError::LengthMismatch {
+                genos: self.genovec.len(),
+                nns: self.nnvec.len(),
+                positions: self.posvec.len(),
+                scores: self.fitnessvec.len(),
+            });
+        }
+
+        let mut ids: Vec<usize> = self.nnvec.iter().flatten().map(|(_, id)| *id).collect();
+        ids.sort_unstable();
+        if let Some((_, &id)) = ids.iter().enumerate().find(|(i, &id)| *i != id) {
+            return Err(CheckpointError::NNIdNotContiguous(id));
+        }
+
+        for (blob, (geno, blob_nn)) in self.genovec.iter().zip(self.nnvec.iter()).enumerate() {
+            for nn_id in geno.all_usize_nn_ids() {
+                if !blob_nn.iter().any(|(_, id)| *id == nn_id) {
+                    return Err(CheckpointError::MissingNN { blob, nn_id });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// replace nn ids of the world with `0..n` in the order of blobs
+    pub fn renumber_nn(&mut self) {
+        let mut new_ids = HashMap::<usize, usize>::new();
+        for (_, id) in self.nnvec.iter_mut().flatten() {
+            let new_id = new_ids.len();
+            *id = *new_ids.entry(*id).or_insert(new_id);
+        }
+        for geno in self.genovec.iter_mut() {
+            for nn_id in geno.all_nn_ids_mut() {
+                *nn_id = nn_id.and_then(|id| new_ids.get(&id).copied());
+            }
+        }
+    }
+
+    /// merge populations of several files into one, e.g. islands of different runs.
+    ///
+    /// nn ids of each file are shifted so that they stay `0..n`.
+    /// a single file is returned as is,
+    /// otherwise the header is reset and the run state is dropped
+    pub fn merge(mut files: Vec<ExportFile>) -> Self {
+        if files.len() == 1 {
+            return files.pop().unwrap();
+        }
+
+        let mut ef = ExportFile::new();
+        for mut file in files {
+            let offset = ef.nnvec.iter().map(Vec::len).sum::<usize>();
+            for (_, id) in file.nnvec.iter_mut().flatten() {
+                *id += offset;
+            }
+            for geno in file.genovec.iter_mut() {
+                for nn_id in geno.all_nn_ids_mut() {
+                    *nn_id = nn_id.map(|id| id + offset);
+                }
+            }
+            ef.genovec.append(&mut file.genovec);
+            ef.nnvec.append(&mut file.nnvec);
+            ef.posvec.append(&mut file.posvec);
+            ef.fitnessvec.append(&mut file.fitnessvec);
+        }
+        ef
+    }
+
+    /// keep the `k` best blobs by recorded fitness, best first.
+    ///
+    /// blobs without fitness come after the others in the saved order,
+    /// the run state is dropped since the population changes
+    pub fn top(self, k: usize) -> Self {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.fitnessvec[b]
+                .partial_cmp(&self.fitnessvec[a])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        order.truncate(k);
+        self.pick(&order)
+    }
+
+    /// blobs at `indices` in the given order, with their own NNs re-indexed from 0.
+    ///
+    /// the header is kept, the run state is dropped
+    pub fn pick(&self, indices: &[usize]) -> Self {
+        let mut ef = ExportFile::new().with_header(self.header.clone());
+        for &idx in indices {
+            ef.genovec.push(self.genovec[idx].clone());
+            ef.nnvec.push(self.nnvec[idx].clone());
+            ef.posvec.push(self.posvec[idx]);
+            ef.fitnessvec.push(self.fitnessvec[idx]);
+        }
+        ef.renumber_nn();
+        ef
+    }
+
+    /// move blobs to new spawn positions, e.g. after merging files
+    pub fn respawn(&mut self, centers: &[(f32, f32)]) {
+        assert_eq!(centers.len(), self.posvec.len());
+        for (pos, center) in self.posvec.iter_mut().zip(centers.iter()) {
+            *pos = [center.0, center.1];
+        }
     }
 
     /// Flattening and sorting by usize index, return cloned nnvec
@@ -126,65 +317,89 @@ impl<'a> Iterator for ExportFileIterMut<'a> {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn export(
-    input: Res<Input<KeyCode>>,
+    input: Option<Res<Input<KeyCode>>>,
     blob_q: Query<(Entity, (&BlobGeno, &BlobInfo))>,
     nn_q: Query<(&Parent, &NeuronId)>,
     bbn: Res<BevyBlockNeurons>,
-    frames: Res<Frames>
+    frames: Res<Frames>,
+    config: Res<EvoConfig>,
+    rng: Res<EvoRng>,
+    ted: Res<TED>,
+    fitness: Res<TrainFitness>,
+    speciation: Res<Speciation>,
+    genealogy: Res<Genealogy>,
+    pipe: Res<TrainMutPipe>,
 ) {
     if blob_q.is_empty() || nn_q.is_empty() {
         return;
     }
 
-    if input.just_pressed(SAVE_ALL_BLOBS_TO_JSON) || is_checkpoints(frames){
-        create_if_not_exist();
-        let mut ef = ExportFile::new();
-        let nnvec = &bbn.nnvec;
-
-        for (blob_id, blob) in blob_q.iter(){
-            ef.push_blob(blob);
-            let mut blob_nn = Vec::<(GenericNN,usize)>::new();
-            for (parent_id, neuron) in nn_q.iter(){
-                if parent_id.get() != blob_id {
-                    continue;
-                }
-                // unwrap since neuron must in nnvec
-                blob_nn.push((nnvec.get(neuron.id).unwrap().clone(), neuron.id))
-            }
-            ef.push_nn(blob_nn);
+    let key_pressed = input.is_some_and(|input| input.just_pressed(SAVE_ALL_BLOBS_TO_JSON));
+    if key_pressed || is_checkpoints(&frames, &config){
+        let infos: Vec<&BlobInfo> = blob_q.iter().map(|(_, (_, info))| info).collect();
+        let ef = collect_export_file(&blob_q, &nn_q, &bbn.nnvec)
+            .with_header(CheckpointHeader::new(&config, &frames, &rng, &ted, &fitness, &infos))
+            .with_fitness(infos.iter().map(|info| fitness.score(info)).collect())
+            .with_run_state(RunState::new(&speciation, &genealogy, &pipe));
+        if let Err(e) = ef.save(&config) {
+            logger_warn!("failed to save checkpoint: {}", e);
         }
-        ef.save();
     }
 }
 
-fn create_if_not_exist() {
-    let path = EXPORT_PATH;
+/// collect all blobs and their neurons in the world into an `ExportFile`,
+/// with default header
+pub fn collect_export_file(
+    blob_q: &Query<(Entity, (&BlobGeno, &BlobInfo))>,
+    nn_q: &Query<(&Parent, &NeuronId)>,
+    nnvec: &[GenericNN],
+) -> ExportFile {
+    let mut ef = ExportFile::new();
 
-    // Check if the path exists
-    if !Path::new(path).exists() {
-        // Create the directory if it doesn't exist
-        match fs::create_dir_all(path) {
-            Ok(_) => println!("Directory created {}.", path),
-            Err(e) => eprintln!("Error creating directory: {}", e),
+    for (blob_id, blob) in blob_q.iter(){
+        ef.push_blob(blob);
+        let mut blob_nn = Vec::<(GenericNN,usize)>::new();
+        for (parent_id, neuron) in nn_q.iter(){
+            if parent_id.get() != blob_id {
+                continue;
+            }
+            // unwrap since neuron must in nnvec
+            blob_nn.push((nnvec.get(neuron.id).unwrap().clone(), neuron.id))
         }
+        ef.push_nn(blob_nn);
     }
+    ef.renumber_nn();
+    ef
+}
+
+/// create the directory and its parents if it does not exist
+pub fn create_if_not_exist(path: &str) -> std::io::Result<()> {
+    fs::create_dir_all(path)
 }
 
-fn current_time_filename() -> String {
+/// write to a temp file next to `path` and rename it,
+/// so that an interrupted write never leaves a partial file
+pub fn write_atomic(path: &str, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp = format!("{}.tmp", path);
+    let mut file = File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+pub fn current_time_filename() -> String {
     let now: NaiveDateTime = Local::now().naive_local();
-    format!("{:04}-{:02}-{:02}T{:02}-{:02}-{:02}.json",
+    format!("{:04}-{:02}-{:02}T{:02}-{:02}-{:02}",
             now.year(), now.month(), now.day(),
             now.hour(), now.minute(), now.second())
 }
 
-fn is_checkpoints(frames: Res<Frames>) -> bool {
-    let cur_frame = frames.0 % ITERATION_LENGTH as u128;
-    let iterations = frames.0 / ITERATION_LENGTH as u128;
-    let cur_cp_iter_num = iterations % CHECKPOINTS_LENGTH as u128;
-    if cur_cp_iter_num == 0 && iterations != 0 && cur_frame == 0{
-        true
-    } else {
-        false
-    }
+pub fn is_checkpoints(frames: &Frames, config: &EvoConfig) -> bool {
+    let iteration_length = config.train.iteration_length as u128;
+    let cur_frame = frames.0 % iteration_length;
+    let iterations = frames.0 / iteration_length;
+    let cur_cp_iter_num = iterations % config.train.checkpoints_length as u128;
+    cur_cp_iter_num == 0 && iterations != 0 && cur_frame == 0
 }
\ No newline at end of file
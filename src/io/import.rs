@@ -1,63 +1,158 @@
 //! Import and deserialize the checkpoint or exported files
 
-use std::fs::{File, self};
-use std::io::Read;
+use std::fs;
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::ImpulseJoint;
-use serde_json;
-
-use crate::blob::blob::Blob;
+use rand::RngCore;
+use crate::blob::blob::{Blob, BlobInfo};
+use crate::blob::lineage::Genealogy;
 use crate::blob::geno_blob_builder::GenoBlobBuilder;
 use crate::brain::resource::BevyBlockNeurons;
 use crate::componet::ColliderFlag;
+use crate::config::{CheckpointFormat, EvoConfig};
+use crate::contorl::contorl::get_centers;
+use crate::contorl::resource::{EvoRng, Frames, TrainMutPipe, TED};
 use crate::consts::*;
+use crate::logger_info;
 use crate::physics::world::Wall;
 
-use super::export::ExportFile;
+use super::checkpoint::CheckpointError;
+use super::export::{ExportFile, CONFIG_FILE_SUFFIX};
+use super::retention::latest_file_name;
+
+/// checkpoint to start with, replace the random population in setup
+#[derive(Resource)]
+pub struct LoadedCheckpoint(pub ExportFile);
+
+/// restore `Frames`, `TED`, `EvoRng`, `Speciation` and `Genealogy` saved in the checkpoint,
+/// files without run state are not restored.
+///
+/// If the checkpoint is saved at the end of an iteration and `with_next` is set,
+/// the selected population is put into `TrainMutPipe`,
+/// it will be mutated and spawned by `mutate_and_refresh_after_train`,
+/// so that the run continues exactly where it left off.
+/// Otherwise `Frames` goes back to the start of the iteration,
+/// and the saved population runs the iteration again.
+///
+/// return if the selected population is restored
+pub fn restore_run_state(world: &mut World, ef: &ExportFile, with_next: bool) -> bool {
+    let Some(state) = ef.run_state() else {
+        return false;
+    };
+    let header = ef.header();
+    let iteration_length = world
+        .get_resource_or_insert_with(EvoConfig::default)
+        .train
+        .iteration_length as u128;
+    let with_next = with_next && state.has_next();
+
+    let frames = if with_next {
+        header.frames
+    } else {
+        header.frames - header.frames % iteration_length
+    };
+    world.insert_resource(Frames(frames));
+    world.insert_resource(TED(header.ted.unwrap_or_default()));
+    if let Some(rng) = &header.rng {
+        world.insert_resource(EvoRng::from_state(rng));
+    }
+    world.insert_resource(state.speciation.clone());
+    world.insert_resource(state.genealogy.clone());
+
+    let mut pipe = TrainMutPipe::default();
+    if with_next {
+        let infovec = state
+            .next_posvec
+            .iter()
+            .map(|pos| BlobInfo {
+                center_block_pos: Vec2::from_array(*pos),
+                ..default()
+            })
+            .collect();
+        pipe.push(state.next_genovec.clone(), infovec, state.next_nnvec.clone());
+    }
+    world.insert_resource(pipe);
+
+    logger_info!(
+        "resumed at iteration {}, frame {}",
+        frames / iteration_length,
+        frames
+    );
+    with_next
+}
+
+/// load checkpoints as one population.
+///
+/// blobs of several files are merged and spawned at new positions,
+/// only the `top` blobs by recorded fitness are kept if given.
+/// merged blobs more than the population of `config` are cut by fitness as well
+pub fn load_population(
+    paths: &[String],
+    top: Option<usize>,
+    config: &EvoConfig,
+    rng: &mut dyn RngCore,
+) -> Result<ExportFile, CheckpointError> {
+    let mut files = Vec::<ExportFile>::new();
+    for path in paths {
+        let ef = ExportFile::load(path).map_err(|e| CheckpointError::InFile {
+            path: path.clone(),
+            source: Box::new(e),
+        })?;
+        files.push(ef);
+    }
+
+    let merged = files.len() > 1;
+    let mut ef = ExportFile::merge(files);
+    if let Some(k) = top {
+        ef = ef.top(k);
+    }
+    if merged && ef.len() > config.train.population {
+        ef = ef.top(config.train.population);
+    }
+    if merged {
+        ef.respawn(&get_centers(config, ef.len(), rng));
+    }
+    Ok(ef)
+}
 
 /// load blobs from an exported file or checkpoints file
+///
+/// `load_fname`, or the newest file in `load_folder` if `load_newest_file` is set,
+/// is merged with `merge_fnames`, see `load_population`.
+/// A single checkpoint runs the iteration again, see `restore_run_state`
 pub fn load_blobs(
-    commands: Commands,
+    mut commands: Commands,
     mut bbn: ResMut<BevyBlockNeurons>,
     input: Res<Input<KeyCode>>,
+    config: Res<EvoConfig>,
+    mut genealogy: ResMut<Genealogy>,
+    mut rng: ResMut<EvoRng>,
 ) {
-    let mut load_fname = LOAD_FNAME.to_string();
-    if LOAD_NEWEST_FILE {
-        let path = newest_file_name_in_directory(LOAD_FOLDER);
-        if let Some(path) = path {
-            load_fname = LOAD_FOLDER.to_string() + &path;
-        } else {
-            panic!("empty load folder")
-        }
+    if !input.just_pressed(LOAD_ALL_BLOBS_FROM_JSON) {
+        return;
+    }
+
+    let mut load_fname = config.io.load_fname.clone();
+    if config.io.load_newest_file {
+        let Some(path) = newest_file_name_in_directory(&config.io.load_folder) else {
+            warn!("No checkpoint in {}", config.io.load_folder);
+            return;
+        };
+        load_fname = config.io.load_folder.clone() + &path;
     }
-    
-    if input.just_pressed(LOAD_ALL_BLOBS_FROM_JSON) {
-        match File::open(&load_fname) {
-            Ok(mut file) => {
-                let mut file_str = String::new();
-
-                // Handle read_to_string error
-                if let Err(e) = file.read_to_string(&mut file_str) {
-                    warn!("Failed to read from file {}: {:?}", load_fname, e);
-                    return;
-                }
-
-                // Handle serde_json parsing error
-                match serde_json::from_str::<ExportFile>(&file_str) {
-                    Ok(ef) => {
-                        ef.check();
-                        overwrite(ef, commands, &mut bbn);
-                    }
-                    Err(e) => {
-                        warn!("Failed to parse the file content as `ExportFile`: {:?}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("Failed to open file {}: {:?}", LOAD_FNAME, e);
-            }
+
+    let mut paths = vec![load_fname];
+    paths.extend(config.io.merge_fnames.iter().cloned());
+    match load_population(&paths, config.io.load_top, &config, &mut rng.0) {
+        Ok(ef) => {
+            let restored = ef.clone();
+            commands.add(move |world: &mut World| {
+                restore_run_state(world, &restored, false);
+            });
+            overwrite(ef, commands, &mut bbn, &config, &mut genealogy, &mut rng.0);
         }
+        Err(e) => warn!("Failed to load: {}", e),
     }
 }
 
@@ -82,8 +177,19 @@ pub fn clean(
 }
 
 /// ignore and overwrite all blobs and NNs that exist
-fn overwrite(mut ef: ExportFile, commands: Commands, bbn: &mut BevyBlockNeurons) {
-    let mut builder = GenoBlobBuilder::from_commands(commands, &mut bbn.nnvec);
+///
+/// loaded blobs keep their lineage, see `Genealogy::register`
+pub fn overwrite(
+    mut ef: ExportFile,
+    commands: Commands,
+    bbn: &mut BevyBlockNeurons,
+    config: &EvoConfig,
+    genealogy: &mut Genealogy,
+    rng: &mut dyn RngCore,
+) {
+    genealogy.register(ef.iter_mut().map(|(geno, _, _)| geno));
+
+    let mut builder = GenoBlobBuilder::from_commands(commands, &mut bbn.nnvec, config, rng);
 
     // build loaded blobs
     for (geno, pos, _nnvec) in ef.iter_mut() {
@@ -92,21 +198,26 @@ fn overwrite(mut ef: ExportFile, commands: Commands, bbn: &mut BevyBlockNeurons)
         // println!("\n{:#?}",geno);
     }
 
-    // set resource
+    // set resource, recurrent NNs start without memory
     bbn.nnvec = ef.flatten_nnvec();
+    for nn in bbn.nnvec.iter_mut() {
+        nn.reset_state();
+    }
 }
 
 /// take folder path as input, return fname
+///
+/// the file in `LATEST_FILE` if exists, otherwise the last file by name.
+/// checkpoints in all formats are recognized,
+/// config files saved next to exports are ignored
 fn newest_file_name_in_directory(dir: &str) -> Option<String> {
+    if let Some(fname) = latest_file_name(dir) {
+        return Some(fname);
+    }
     fs::read_dir(dir)
         .ok()?
-        .filter_map(|entry| {
-            let entry = entry.ok()?;
-            if entry.path().extension()? == "json" {
-                entry.path().file_name()?.to_str().map(String::from)
-            } else {
-                None
-            }
-        })
+        .filter_map(|entry| entry.ok()?.file_name().to_str().map(String::from))
+        .filter(|fname| CheckpointFormat::from_path(fname).is_some())
+        .filter(|fname| !fname.ends_with(CONFIG_FILE_SUFFIX))
         .max()
 }
\ No newline at end of file
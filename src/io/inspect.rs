@@ -0,0 +1,74 @@
+//! Print the content of an exported file or checkpoint
+
+use crate::brain::neuron::GenericNN;
+
+use super::{checkpoint::CheckpointError, export::ExportFile};
+
+/// print header, population stats, geno trees and nn shapes of an exported file
+pub fn inspect(path: &str) -> Result<(), CheckpointError> {
+    let ef = ExportFile::load(path)?;
+    let header = ef.header();
+
+    let block_counts: Vec<usize> = ef.iter().map(|(_, _, nnvec)| nnvec.len()).collect();
+    let total_blocks: usize = block_counts.iter().sum();
+
+    println!("checkpoint: {}", path);
+    println!(
+        "format version {}, saved by evosim {}",
+        header.format_version, header.crate_version
+    );
+    println!("iteration {}, frame {}", header.iteration, header.frames);
+    if let Some(seed) = header.seed {
+        println!("seed: {}", seed);
+    }
+    if let Some(ted) = header.ted {
+        println!("TED: {:.3}", ted);
+    }
+    if let Some(fitness) = &header.fitness {
+        println!(
+            "{}: best {:.3}, mean {:.3}, worst {:.3}",
+            fitness.name, fitness.best, fitness.mean, fitness.worst
+        );
+    }
+    println!("population: {}", ef.len());
+    if !block_counts.is_empty() {
+        println!(
+            "blocks per blob: min {}, mean {:.2}, max {}",
+            block_counts.iter().min().unwrap(),
+            total_blocks as f32 / block_counts.len() as f32,
+            block_counts.iter().max().unwrap()
+        );
+    }
+    println!("neurons: {}", total_blocks);
+
+    for (idx, (geno, pos, nnvec)) in ef.iter().enumerate() {
+        println!();
+        println!("blob {} at [{:.2}, {:.2}]", idx, pos[0], pos[1]);
+        println!(
+            "  genome {}, parents {:?}, born at iteration {}, {} mutations",
+            geno.lineage.id,
+            geno.lineage.parents,
+            geno.lineage.birth,
+            geno.lineage.mutations.len()
+        );
+        println!(
+            "  oscillator: frequency {:.3}, amplitude {:.3}, phase {:.3}",
+            geno.cpg.frequency, geno.cpg.amplitude, geno.cpg.phase
+        );
+        print!("{:?}", geno.vec_tree);
+        for (nn, nn_id) in nnvec.iter() {
+            match nn {
+                GenericNN::BRAINNN(nn) => {
+                    println!("  nn {}: brain {:?}", nn_id, nn.nn.shape())
+                }
+                GenericNN::BLOCKNN(nn) => println!(
+                    "  nn {}: block inward {:?}, outward {:?}",
+                    nn_id,
+                    nn.inward_nn.nn.shape(),
+                    nn.outward_nn.nn.shape()
+                ),
+            }
+        }
+    }
+    Ok(())
+}
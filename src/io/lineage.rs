@@ -0,0 +1,282 @@
+//! Save lineage of blobs during training, and dump the phylogenetic tree
+//!
+//! At the end of each iteration, the lineage and fitness of every blob
+//! is appended to `lineage.jsonl` in `export_path`, one `LineageRecord` per line.
+//! `dump_lineage` reads the file and prints the tree as Graphviz DOT or JSON.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use bevy::prelude::*;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blob::{
+        blob::BlobInfo,
+        geno_blob_builder::BlobGeno,
+        lineage::{Lineage, Mutation},
+    },
+    config::EvoConfig,
+    contorl::{fitness::TrainFitness, resource::Frames, train_move::iteration_end},
+};
+
+use super::export::create_if_not_exist;
+
+/// file name of the lineage file in `export_path`
+pub const LINEAGE_FILE: &str = "lineage.jsonl";
+
+/// lineage and fitness of a blob at the end of an iteration,
+/// a line in the lineage file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineageRecord {
+    pub iteration: usize,
+    pub fitness: f32,
+    pub lineage: Lineage,
+}
+
+/// append lineage of all blobs to the lineage file at the end of each iteration
+pub fn export_lineage(
+    frames: Res<Frames>,
+    geno_info_q: Query<(&BlobGeno, &BlobInfo)>,
+    config: Res<EvoConfig>,
+    fitness: Res<TrainFitness>,
+) {
+    if !config.io.save_lineage || !iteration_end(&frames, config.train.iteration_length) {
+        return;
+    }
+
+    let iteration = (frames.0 / config.train.iteration_length as u128) as usize;
+    let mut lines = String::new();
+    for (geno, info) in geno_info_q.iter() {
+        let record = LineageRecord {
+            iteration,
+            fitness: fitness.score(info),
+            lineage: geno.lineage.clone(),
+        };
+        lines.push_str(&serde_json::to_string(&record).unwrap());
+        lines.push('\n');
+    }
+
+    if let Err(e) = append_lines(&config, &lines) {
+        warn!("Failed to write lineage: {}", e);
+    }
+}
+
+fn append_lines(config: &EvoConfig, lines: &str) -> std::io::Result<()> {
+    create_if_not_exist(&config.io.export_path)?;
+    let fname = format!("{}{}", config.io.export_path, LINEAGE_FILE);
+    let mut file = OpenOptions::new().create(true).append(true).open(&fname)?;
+    file.write_all(lines.as_bytes())
+}
+
+/// output format of `dump_lineage`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LineageFormat {
+    /// Graphviz DOT
+    Dot,
+    /// JSON array of genomes
+    Json,
+}
+
+/// a genome in the phylogenetic tree
+#[derive(Debug, Clone, Serialize)]
+pub struct Genome {
+    #[serde(flatten)]
+    pub lineage: Lineage,
+    /// best fitness in all recorded iterations
+    pub best_fitness: f32,
+    /// last iteration the genome was alive
+    pub last_seen: usize,
+}
+
+/// print the phylogenetic tree in the lineage file.
+///
+/// only the ancestors of genome `id` are printed,
+/// the best blob of the last iteration is used if `id` is not given,
+/// all genomes are printed if `all` is set.
+pub fn dump_lineage(
+    path: &str,
+    format: LineageFormat,
+    id: Option<usize>,
+    all: bool,
+) -> std::io::Result<()> {
+    let file_str = fs::read_to_string(path)?;
+    let mut records = Vec::<LineageRecord>::new();
+    for line in file_str.lines().filter(|line| !line.trim().is_empty()) {
+        records.push(serde_json::from_str(line)?);
+    }
+
+    let genomes = collect_genomes(&records);
+    let genomes: Vec<&Genome> = if all {
+        genomes.values().collect()
+    } else {
+        let Some(id) = id.or_else(|| winner(&records)) else {
+            return Ok(());
+        };
+        ancestors(&genomes, id)
+            .iter()
+            .filter_map(|id| genomes.get(id))
+            .collect()
+    };
+
+    match format {
+        LineageFormat::Dot => print!("{}", to_dot(&genomes)),
+        LineageFormat::Json => println!("{}", serde_json::to_string_pretty(&genomes)?),
+    }
+    Ok(())
+}
+
+/// merge records of the same genome, the latest lineage is kept
+pub fn collect_genomes(records: &[LineageRecord]) -> BTreeMap<usize, Genome> {
+    let mut genomes = BTreeMap::<usize, Genome>::new();
+    for record in records {
+        let genome = genomes.entry(record.lineage.id).or_insert_with(|| Genome {
+            lineage: record.lineage.clone(),
+            best_fitness: record.fitness,
+            last_seen: record.iteration,
+        });
+        if record.iteration >= genome.last_seen {
+            genome.lineage = record.lineage.clone();
+            genome.last_seen = record.iteration;
+        }
+        genome.best_fitness = genome.best_fitness.max(record.fitness);
+    }
+    genomes
+}
+
+/// genome id of the best blob in the last iteration
+fn winner(records: &[LineageRecord]) -> Option<usize> {
+    let last = records.iter().map(|record| record.iteration).max()?;
+    records
+        .iter()
+        .filter(|record| record.iteration == last)
+        .max_by(|a, b| {
+            a.fitness
+                .partial_cmp(&b.fitness)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|record| record.lineage.id)
+}
+
+/// `id` and all its ancestors
+pub fn ancestors(genomes: &BTreeMap<usize, Genome>, id: usize) -> BTreeSet<usize> {
+    let mut result = BTreeSet::new();
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+        if !result.insert(id) {
+            continue;
+        }
+        if let Some(genome) = genomes.get(&id) {
+            stack.extend(genome.lineage.parents.iter());
+        }
+    }
+    result
+}
+
+/// Graphviz DOT of genomes, edges point from parent to child.
+///
+/// limb changes are listed in the label, other mutations are counted
+pub fn to_dot(genomes: &[&Genome]) -> String {
+    let mut dot = String::from("digraph lineage {\n    node [shape=box];\n");
+    for genome in genomes {
+        let lineage = &genome.lineage;
+        let mut label = format!(
+            "{}\\nborn {}, fitness {:.3}",
+            lineage.id, lineage.birth, genome.best_fitness
+        );
+
+        let mut counts = BTreeMap::<&str, usize>::new();
+        for mutation in lineage.mutations.iter() {
+            match mutation {
+                Mutation::GainLimb(_) | Mutation::LoseLimb(_) => {
+                    label.push_str(&format!("\\n{}", mutation))
+                }
+                Mutation::BlockSize(_) => *counts.entry("block_size").or_default() += 1,
+                Mutation::JointLimit(_) => *counts.entry("joint_limit").or_default() += 1,
+                Mutation::Nn(_) => *counts.entry("nn").or_default() += 1,
+                Mutation::Cpg => *counts.entry("cpg").or_default() += 1,
+            }
+        }
+        if !counts.is_empty() {
+            let counts: Vec<String> = counts
+                .iter()
+                .map(|(name, count)| format!("{} x{}", name, count))
+                .collect();
+            label.push_str(&format!("\\n{}", counts.join(", ")));
+        }
+
+        dot.push_str(&format!("    {} [label=\"{}\"];\n", lineage.id, label));
+        for parent in lineage.parents.iter() {
+            dot.push_str(&format!("    {} -> {};\n", parent, lineage.id));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod lineage_test {
+    use super::*;
+
+    fn record(iteration: usize, fitness: f32, id: usize, parents: Vec<usize>) -> LineageRecord {
+        LineageRecord {
+            iteration,
+            fitness,
+            lineage: Lineage {
+                id,
+                parents,
+                birth: iteration,
+                mutations: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let mut records = vec![
+            record(0, 1.0, 0, vec![]),
+            record(0, 2.0, 1, vec![]),
+            record(0, 0.5, 2, vec![]),
+            record(1, 3.0, 3, vec![0, 1]),
+            record(1, 1.0, 4, vec![2]),
+            record(2, 4.0, 5, vec![3]),
+        ];
+        let mut mutated = record(2, 1.5, 1, vec![]);
+        mutated.lineage.birth = 0;
+        mutated.lineage.mutations.push(Mutation::GainLimb(3));
+        records.push(mutated);
+
+        let genomes = collect_genomes(&records);
+        assert_eq!(genomes.len(), 6);
+        assert_eq!(genomes[&1].best_fitness, 2.0);
+        assert_eq!(genomes[&1].last_seen, 2);
+        assert_eq!(genomes[&1].lineage.mutations, vec![Mutation::GainLimb(3)]);
+
+        assert_eq!(winner(&records), Some(5));
+        let ids = ancestors(&genomes, 5);
+        assert_eq!(ids.into_iter().collect::<Vec<_>>(), vec![0, 1, 3, 5]);
+
+        let dot = to_dot(&genomes.values().collect::<Vec<_>>());
+        assert!(dot.contains("0 -> 3;"));
+        assert!(dot.contains("1 -> 3;"));
+        assert!(dot.contains("gain_limb 3"));
+    }
+
+    #[test]
+    fn test_append_lines() {
+        let dir = std::env::temp_dir().join(format!("evosim_lineage_{}", std::process::id()));
+        let mut config = EvoConfig::default();
+        config.io.export_path = format!("{}/", dir.display());
+        append_lines(&config, "a\n").unwrap();
+        append_lines(&config, "b\n").unwrap();
+        let fname = dir.join(LINEAGE_FILE);
+        assert_eq!(fs::read_to_string(&fname).unwrap(), "a\nb\n");
+
+        // a file in the way of the export path is an error, not a panic
+        config.io.export_path = format!("{}/", fname.display());
+        assert!(append_lines(&config, "c\n").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
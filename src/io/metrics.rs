@@ -0,0 +1,241 @@
+//! Machine readable training metrics
+//!
+//! At the end of each iteration, a `MetricsRow` is appended to
+//! `<run_id>.metrics.csv` or `<run_id>.metrics.jsonl` in `export_path`,
+//! so that learning curves can be plotted without parsing the log.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::Instant;
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    blob::{blob::BlobInfo, geno_blob_builder::BlobGeno},
+    config::{EvoConfig, MetricsFormat},
+    contorl::{
+        fitness::TrainFitness,
+        resource::{Frames, TED},
+        species::Speciation,
+        train_move::iteration_end,
+    },
+};
+
+use super::{export::create_if_not_exist, retention::run_id};
+
+/// wall time since the iteration started, as a bevy resource
+#[derive(Resource)]
+pub struct IterationClock(pub Instant);
+
+impl Default for IterationClock {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+/// metrics of an iteration, a row in the metrics file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsRow {
+    pub iteration: usize,
+    pub best_fitness: f32,
+    pub mean_fitness: f32,
+    pub median_fitness: f32,
+    pub std_fitness: f32,
+    pub mean_blocks: f32,
+    pub mean_depth: f32,
+    /// Tree Edit Distance of the population
+    pub ted: f32,
+    pub species: usize,
+    /// seconds
+    pub wall_time: f32,
+    pub steps_per_second: f32,
+}
+
+impl MetricsRow {
+    /// column names of the CSV header
+    const COLUMNS: [&'static str; 11] = [
+        "iteration",
+        "best_fitness",
+        "mean_fitness",
+        "median_fitness",
+        "std_fitness",
+        "mean_blocks",
+        "mean_depth",
+        "ted",
+        "species",
+        "wall_time",
+        "steps_per_second",
+    ];
+
+    /// `None` if there is no blob
+    pub fn new(
+        iteration: usize,
+        scores: &[f32],
+        genos: &[&BlobGeno],
+        ted: f32,
+        species: usize,
+        wall_time: f32,
+        steps: usize,
+    ) -> Option<Self> {
+        if scores.is_empty() {
+            return None;
+        }
+        let mut sorted = scores.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let len = sorted.len();
+        let median = if len % 2 == 1 {
+            sorted[len / 2]
+        } else {
+            (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0
+        };
+        let mean = sorted.iter().sum::<f32>() / len as f32;
+        let variance = sorted.iter().map(|score| (score - mean).powi(2)).sum::<f32>() / len as f32;
+
+        let blobs = genos.len().max(1) as f32;
+        Some(Self {
+            iteration,
+            best_fitness: sorted[len - 1],
+            mean_fitness: mean,
+            median_fitness: median,
+            std_fitness: variance.sqrt(),
+            mean_blocks: genos
+                .iter()
+                .map(|geno| geno.all_nn_ids_indices().len())
+                .sum::<usize>() as f32
+                / blobs,
+            mean_depth: genos.iter().map(|geno| geno.tree_depth()).sum::<usize>() as f32 / blobs,
+            ted,
+            species,
+            wall_time,
+            steps_per_second: if wall_time > 0.0 {
+                steps as f32 / wall_time
+            } else {
+                0.0
+            },
+        })
+    }
+
+    pub fn csv_header() -> String {
+        Self::COLUMNS.join(",")
+    }
+
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            self.iteration,
+            self.best_fitness,
+            self.mean_fitness,
+            self.median_fitness,
+            self.std_fitness,
+            self.mean_blocks,
+            self.mean_depth,
+            self.ted,
+            self.species,
+            self.wall_time,
+            self.steps_per_second
+        )
+    }
+}
+
+/// append metrics of the population to the metrics file at the end of each iteration
+pub fn export_metrics(
+    frames: Res<Frames>,
+    geno_info_q: Query<(&BlobGeno, &BlobInfo)>,
+    config: Res<EvoConfig>,
+    fitness: Res<TrainFitness>,
+    ted: Res<TED>,
+    speciation: Res<Speciation>,
+    mut clock: ResMut<IterationClock>,
+) {
+    if !iteration_end(&frames, config.train.iteration_length) {
+        return;
+    }
+    let wall_time = clock.0.elapsed().as_secs_f32();
+    clock.0 = Instant::now();
+    if !config.io.save_metrics {
+        return;
+    }
+
+    let scores: Vec<f32> = geno_info_q
+        .iter()
+        .map(|(_, info)| fitness.score(info))
+        .collect();
+    let genos: Vec<&BlobGeno> = geno_info_q.iter().map(|(geno, _)| geno).collect();
+    let Some(row) = MetricsRow::new(
+        (frames.0 / config.train.iteration_length as u128) as usize,
+        &scores,
+        &genos,
+        ted.0,
+        speciation.species.len(),
+        wall_time,
+        config.train.iteration_length,
+    ) else {
+        return;
+    };
+
+    if let Err(e) = append_row(&config, &row) {
+        warn!("Failed to write metrics: {}", e);
+    }
+}
+
+/// the CSV header is written to a new file
+fn append_row(config: &EvoConfig, row: &MetricsRow) -> std::io::Result<()> {
+    create_if_not_exist(&config.io.export_path)?;
+    let format = config.io.metrics_format;
+    let fname = format!(
+        "{}{}.metrics.{}",
+        config.io.export_path,
+        run_id(config),
+        format.extension()
+    );
+    let mut file = OpenOptions::new().create(true).append(true).open(&fname)?;
+
+    let mut lines = String::new();
+    match format {
+        MetricsFormat::Csv => {
+            if file.metadata()?.len() == 0 {
+                lines.push_str(&MetricsRow::csv_header());
+                lines.push('\n');
+            }
+            lines.push_str(&row.to_csv());
+        }
+        MetricsFormat::Jsonl => lines.push_str(&serde_json::to_string(row)?),
+    }
+    lines.push('\n');
+    file.write_all(lines.as_bytes())
+}
+
+#[cfg(test)]
+mod metrics_test {
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_metrics_row() {
+        let config = EvoConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let geno = BlobGeno::new_rand(&config.geno, &mut rng);
+        let genos = vec![&geno; 4];
+
+        let row = MetricsRow::new(3, &[4.0, 1.0, 3.0, 2.0], &genos, 1.5, 2, 2.0, 100).unwrap();
+        assert_eq!(row.best_fitness, 4.0);
+        assert_eq!(row.mean_fitness, 2.5);
+        assert_eq!(row.median_fitness, 2.5);
+        assert!((row.std_fitness - 1.25f32.sqrt()).abs() < 1e-6);
+        assert_eq!(row.mean_blocks, geno.all_nn_ids_indices().len() as f32);
+        assert_eq!(row.mean_depth, geno.tree_depth() as f32);
+        assert_eq!(row.steps_per_second, 50.0);
+
+        let csv = row.to_csv();
+        assert_eq!(
+            csv.split(',').count(),
+            MetricsRow::csv_header().split(',').count()
+        );
+        assert!(csv.starts_with("3,4,2.5,2.5,"));
+
+        assert!(MetricsRow::new(0, &[], &[], 0.0, 0, 0.0, 0).is_none());
+    }
+}
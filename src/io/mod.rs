@@ -1,5 +1,11 @@
 //! Import and Export the simulation, generate checkpoints
 
+pub mod checkpoint;
 pub mod export;
 pub mod import;
+pub mod inspect;
+pub mod lineage;
+pub mod metrics;
+pub mod retention;
+pub mod specimen;
 pub mod evoio;
\ No newline at end of file
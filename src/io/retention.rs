@@ -0,0 +1,218 @@
+//! Naming and rolling retention of checkpoints
+//!
+//! Checkpoints are named `<run_id>-it<iteration>`, with `-f<frames>` appended
+//! for checkpoints saved during an iteration.
+//!
+//! Every checkpoint saved by a run is recorded in `<run_id>.checkpoints.jsonl` in `export_path`.
+//! After each save, the newest `keep_last` and the best `keep_best` checkpoints by fitness
+//! are kept, the others are removed with their config files.
+//! `LATEST_FILE` holds the file name of the newest checkpoint in `export_path`.
+
+use std::fs;
+use std::io::ErrorKind;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::EvoConfig;
+
+use super::checkpoint::CheckpointHeader;
+use super::export::{current_time_filename, write_atomic};
+
+/// file in `export_path` holding the file name of the newest checkpoint
+pub const LATEST_FILE: &str = "latest";
+
+/// suffix of the file recording checkpoints of a run
+pub const INDEX_FILE_SUFFIX: &str = ".checkpoints.jsonl";
+
+/// run id used if the config does not have one
+const DEFAULT_RUN_ID: &str = "run";
+
+/// a saved checkpoint, a line in the index file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointEntry {
+    /// file name in `export_path`
+    pub file: String,
+    /// config file saved next to the checkpoint
+    pub config_file: String,
+    pub frames: u128,
+    /// best fitness of the population, `None` if unknown
+    pub fitness: Option<f32>,
+}
+
+/// start time and seed of the run
+pub fn new_run_id(seed: Option<u64>) -> String {
+    match seed {
+        Some(seed) => format!("{}-{}", current_time_filename(), seed),
+        None => current_time_filename(),
+    }
+}
+
+/// run id in config, or the default one
+pub fn run_id(config: &EvoConfig) -> &str {
+    config.io.run_id.as_deref().unwrap_or(DEFAULT_RUN_ID)
+}
+
+/// file name of a checkpoint without extension
+pub fn checkpoint_name(config: &EvoConfig, header: &CheckpointHeader) -> String {
+    let mut name = format!("{}-it{:06}", run_id(config), header.iteration);
+    let cur_frame = header.frames % config.train.iteration_length as u128;
+    if cur_frame != 0 {
+        name.push_str(&format!("-f{}", header.frames));
+    }
+    name
+}
+
+/// record a saved checkpoint of the run, point `LATEST_FILE` to it,
+/// and remove old checkpoints by the retention policy
+pub fn record(config: &EvoConfig, entry: CheckpointEntry) -> std::io::Result<()> {
+    let export_path = &config.io.export_path;
+    let index_path = format!("{}{}{}", export_path, run_id(config), INDEX_FILE_SUFFIX);
+
+    let mut entries = read_index(&index_path)?;
+    // a checkpoint saved again with the same name replaces the old one
+    entries.retain(|old| old.file != entry.file);
+    let latest = entry.file.clone();
+    entries.push(entry);
+
+    let (kept, removed) = retain(entries, config.io.keep_last, config.io.keep_best);
+    for entry in removed.iter() {
+        remove_if_exist(&format!("{}{}", export_path, entry.file))?;
+        remove_if_exist(&format!("{}{}", export_path, entry.config_file))?;
+    }
+
+    let mut lines = String::new();
+    for entry in kept.iter() {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+    write_atomic(&index_path, lines.as_bytes())?;
+    write_atomic(&format!("{}{}", export_path, LATEST_FILE), latest.as_bytes())
+}
+
+/// file name of the newest checkpoint in `dir`, `None` if there is no `LATEST_FILE`
+/// or the checkpoint is removed
+pub fn latest_file_name(dir: &str) -> Option<String> {
+    let fname = fs::read_to_string(format!("{}{}", dir, LATEST_FILE)).ok()?;
+    let fname = fname.trim().to_string();
+    fs::metadata(format!("{}{}", dir, fname)).ok()?;
+    Some(fname)
+}
+
+/// split entries in the saved order into kept and removed ones.
+///
+/// the newest `keep_last` and the best `keep_best` entries are kept,
+/// the newest entry is always kept, all entries are kept if both are `0`
+pub fn retain(
+    entries: Vec<CheckpointEntry>,
+    keep_last: usize,
+    keep_best: usize,
+) -> (Vec<CheckpointEntry>, Vec<CheckpointEntry>) {
+    if keep_last == 0 && keep_best == 0 {
+        return (entries, Vec::new());
+    }
+
+    let len = entries.len();
+    let mut keep = vec![false; len];
+    for flag in keep.iter_mut().skip(len.saturating_sub(keep_last.max(1))) {
+        *flag = true;
+    }
+
+    let mut order: Vec<usize> = (0..len).collect();
+    order.sort_by(|&a, &b| {
+        entries[b]
+            .fitness
+            .partial_cmp(&entries[a].fitness)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    for &idx in order.iter().take(keep_best) {
+        keep[idx] = true;
+    }
+
+    let mut kept = Vec::new();
+    let mut removed = Vec::new();
+    for (entry, keep) in entries.into_iter().zip(keep) {
+        if keep {
+            kept.push(entry);
+        } else {
+            removed.push(entry);
+        }
+    }
+    (kept, removed)
+}
+
+/// entries of the index file, empty if the file does not exist
+fn read_index(path: &str) -> std::io::Result<Vec<CheckpointEntry>> {
+    let file_str = match fs::read_to_string(path) {
+        Ok(file_str) => file_str,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for line in file_str.lines().filter(|line| !line.trim().is_empty()) {
+        entries.push(serde_json::from_str(line)?);
+    }
+    Ok(entries)
+}
+
+fn remove_if_exist(path: &str) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod retention_test {
+    use super::*;
+
+    fn entry(iteration: usize, fitness: Option<f32>) -> CheckpointEntry {
+        CheckpointEntry {
+            file: format!("run-it{:06}.json", iteration),
+            config_file: format!("run-it{:06}.config.json", iteration),
+            frames: iteration as u128 * 10,
+            fitness,
+        }
+    }
+
+    fn iterations(entries: &[CheckpointEntry]) -> Vec<u128> {
+        entries.iter().map(|entry| entry.frames / 10).collect()
+    }
+
+    #[test]
+    fn test_retain() {
+        let entries = vec![
+            entry(1, Some(3.0)),
+            entry(2, Some(1.0)),
+            entry(3, None),
+            entry(4, Some(2.0)),
+            entry(5, Some(0.5)),
+        ];
+
+        let (kept, removed) = retain(entries.clone(), 0, 0);
+        assert_eq!(iterations(&kept), vec![1, 2, 3, 4, 5]);
+        assert!(removed.is_empty());
+
+        let (kept, removed) = retain(entries.clone(), 2, 0);
+        assert_eq!(iterations(&kept), vec![4, 5]);
+        assert_eq!(iterations(&removed), vec![1, 2, 3]);
+
+        let (kept, _) = retain(entries.clone(), 2, 2);
+        assert_eq!(iterations(&kept), vec![1, 4, 5]);
+
+        // newest is always kept, unknown fitness is the worst
+        let (kept, _) = retain(entries, 0, 1);
+        assert_eq!(iterations(&kept), vec![1, 5]);
+
+        let mut config = EvoConfig::default();
+        config.train.iteration_length = 10;
+        config.io.run_id = Some("a".to_string());
+        let mut header = CheckpointHeader {
+            frames: 20,
+            iteration: 2,
+            ..Default::default()
+        };
+        assert_eq!(checkpoint_name(&config, &header), "a-it000002");
+        header.frames = 25;
+        assert_eq!(checkpoint_name(&config, &header), "a-it000002-f25");
+    }
+}
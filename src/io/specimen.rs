@@ -0,0 +1,175 @@
+//! Single-blob "specimen" files
+//!
+//! A specimen is an `ExportFile` with a single blob, whose NNs are re-indexed from 0,
+//! so that `inspect`, `replay` and `train --load` work on it as well.
+//!
+//! Specimens are saved from a checkpoint by the `specimen` subcommand,
+//! or from the running world by pressing `SAVE_BEST_BLOB_KEYCODE`.
+//! `SpawnSpecimen` adds a specimen to the running world,
+//! next to the population rather than overwriting it.
+
+use bevy::prelude::*;
+
+use crate::{
+    blob::{
+        blob::BlobInfo,
+        block::NeuronId,
+        geno_blob_builder::{BlobGeno, GenoBlobBuilder},
+        lineage::Genealogy,
+    },
+    brain::resource::BevyBlockNeurons,
+    config::EvoConfig,
+    consts::{SAVE_BEST_BLOB_KEYCODE, SPAWN_SPECIMENS_KEYCODE},
+    contorl::{fitness::TrainFitness, resource::EvoRng},
+    logger_info,
+};
+
+use super::{
+    checkpoint::CheckpointError,
+    export::{collect_export_file, create_if_not_exist, ExportFile},
+};
+
+/// folder of specimens saved from the running world, in `export_path`
+pub const SPECIMEN_FOLDER: &str = "specimens/";
+
+/// spawn the blob of a specimen file with its center at `pos`
+#[derive(Event)]
+pub struct SpawnSpecimen {
+    pub specimen: ExportFile,
+    pub pos: [f32; 2],
+}
+
+/// save the blob with genome `id` of a checkpoint as a specimen,
+/// the best blob by recorded fitness if `id` is not given
+pub fn extract_specimen(
+    checkpoint: &str,
+    id: Option<usize>,
+    output: &str,
+) -> Result<(), CheckpointError> {
+    let ef = ExportFile::load(checkpoint)?;
+    let specimen = match id {
+        Some(id) => {
+            let idx = ef
+                .genovec
+                .iter()
+                .position(|geno| geno.lineage.id == id)
+                .ok_or(CheckpointError::MissingBlob(id))?;
+            ef.pick(&[idx])
+        }
+        None => ef.top(1),
+    };
+    specimen.write(output)?;
+    println!("specimen saved {}", output);
+    Ok(())
+}
+
+/// save the best blob of the world by fitness to `SPECIMEN_FOLDER`,
+/// named by its genome id
+pub fn export_specimen(
+    input: Res<Input<KeyCode>>,
+    blob_q: Query<(Entity, (&BlobGeno, &BlobInfo))>,
+    nn_q: Query<(&Parent, &NeuronId)>,
+    bbn: Res<BevyBlockNeurons>,
+    config: Res<EvoConfig>,
+    fitness: Res<TrainFitness>,
+) {
+    if !input.just_pressed(SAVE_BEST_BLOB_KEYCODE) || blob_q.is_empty() {
+        return;
+    }
+
+    let scores = blob_q
+        .iter()
+        .map(|(_, (_, info))| fitness.score(info))
+        .collect();
+    let specimen = collect_export_file(&blob_q, &nn_q, &bbn.nnvec)
+        .with_fitness(scores)
+        .top(1);
+
+    let folder = format!("{}{}", config.io.export_path, SPECIMEN_FOLDER);
+    if let Err(e) = create_if_not_exist(&folder) {
+        warn!("Failed to create {}: {}", folder, e);
+        return;
+    }
+    let fname = format!(
+        "{}{}.{}",
+        folder,
+        specimen.genovec[0].lineage.id,
+        config.io.checkpoint_format.extension()
+    );
+    match specimen.write(&fname) {
+        Ok(()) => logger_info!("SPECIMEN SAVED {}", fname),
+        Err(e) => warn!("Failed to save specimen {}: {}", fname, e),
+    }
+}
+
+/// load `specimens` in config and spawn them
+pub fn load_specimens(
+    input: Res<Input<KeyCode>>,
+    config: Res<EvoConfig>,
+    mut events: EventWriter<SpawnSpecimen>,
+) {
+    if !input.just_pressed(SPAWN_SPECIMENS_KEYCODE) {
+        return;
+    }
+
+    for spawn in config.io.specimens.iter() {
+        match ExportFile::load(&spawn.path) {
+            Ok(specimen) => events.send(SpawnSpecimen {
+                specimen,
+                pos: spawn.pos,
+            }),
+            Err(e) => warn!("Failed to load specimen {}: {}", spawn.path, e),
+        }
+    }
+}
+
+/// add specimens to the world, NNs are appended to `BevyBlockNeurons`.
+///
+/// a specimen starts a new lineage in the run, since its genome id comes from another run.
+/// blobs of a file with more than one blob keep their positions relative to the first
+pub fn spawn_specimens(
+    mut events: EventReader<SpawnSpecimen>,
+    commands: Commands,
+    mut bbn: ResMut<BevyBlockNeurons>,
+    config: Res<EvoConfig>,
+    mut genealogy: ResMut<Genealogy>,
+    mut rng: ResMut<EvoRng>,
+) {
+    let mut blobs = Vec::<(BlobGeno, [f32; 2])>::new();
+    for event in events.iter() {
+        let mut specimen = event.specimen.clone();
+        let offset = bbn.nnvec.len();
+        let Some(first) = specimen.posvec.first().copied() else {
+            continue;
+        };
+        for (geno, pos, _) in specimen.iter_mut() {
+            for nn_id in geno.all_nn_ids_mut() {
+                *nn_id = nn_id.map(|id| id + offset);
+            }
+            geno.lineage = genealogy.birth(Vec::new());
+            let pos = [
+                pos[0] - first[0] + event.pos[0],
+                pos[1] - first[1] + event.pos[1],
+            ];
+            blobs.push((geno.clone(), pos));
+        }
+        // recurrent NNs start without memory
+        let mut nnvec = specimen.flatten_nnvec();
+        for nn in nnvec.iter_mut() {
+            nn.reset_state();
+        }
+        bbn.nnvec.extend(nnvec);
+    }
+    if blobs.is_empty() {
+        return;
+    }
+
+    // the builder pushes random NNs for every block, which are not used
+    // since the genos have nn ids, keep them out of `BevyBlockNeurons`
+    let mut unused_nnvec = Vec::new();
+    let mut builder =
+        GenoBlobBuilder::from_commands(commands, &mut unused_nnvec, &config, &mut rng.0);
+    for (geno, pos) in blobs.iter_mut() {
+        builder.build(geno, *pos);
+    }
+}
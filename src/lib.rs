@@ -0,0 +1,21 @@
+//! EvoSim, blobs of blocks evolving to move in a 2D physical world.
+//!
+//! The simulation is built from the modules here,
+//! `main.rs` builds the bevy app and runs the subcommands of `cli.rs`.
+
+pub mod blob;
+pub mod brain;
+pub mod cli;
+pub mod componet;
+pub mod config;
+pub mod consts;
+pub mod contorl;
+pub mod graphics;
+pub mod headless;
+pub mod io;
+pub mod mutate;
+pub mod physics;
+pub mod profile;
+
+#[macro_use]
+pub mod logger;
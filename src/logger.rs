@@ -1,52 +1,199 @@
-//! logger script, 
+//! logger script,
 //! provide macros to log informations (basically training process) into logfile.
+//!
+//! `EvoLogPlugin` replaces bevy's `LogPlugin`, so that `info!` of bevy and
+//! the training logs of the macros (target `train`) end up in one place,
+//! stderr and `<run_id>.log` in `export_path`.
+//! Levels and filters come from `LogConfig`, `RUST_LOG` overrides them.
+//!
+//! The log file is buffered by `LogFile`, and flushed at the end of each frame.
+//!
+//! The layers of profiling are added to the same subscriber, see `profile.rs`.
 
-use chrono::Local;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
 
-use crate::consts::LOG_PATH;
+use bevy::prelude::*;
+use bevy::utils::tracing::subscriber;
+use tracing_log::LogTracer;
+use tracing_subscriber::{filter::filter_fn, fmt, prelude::*, registry::Registry, EnvFilter};
 
-pub fn log_to_file(level: &str, message: &str) {
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(LOG_PATH)
-        .unwrap();
+use crate::config::EvoConfig;
+use crate::io::{export::create_if_not_exist, retention::run_id};
+use crate::profile::{Profiler, PROFILE_TARGET};
 
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+/// target of the training logs, e.g. `train=debug` in `LogConfig::filter`
+pub const TRAIN_TARGET: &str = "train";
 
-    writeln!(file, "[{} - {}] {}", timestamp, level, message).unwrap();
+/// buffered log file shared with the tracing subscriber, as a bevy resource
+#[derive(Resource, Clone)]
+pub struct LogFile(Arc<Mutex<BufWriter<File>>>);
+
+impl LogFile {
+    /// append to the file
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self(Arc::new(Mutex::new(BufWriter::new(file)))))
+    }
+}
+
+// a thread panicked while logging does not stop others from logging
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+/// set the global tracing subscriber of the process
+///
+/// the log file is named by the run id in `EvoConfig`,
+/// which is chosen by `RunArgs::load_config` if not given
+pub struct EvoLogPlugin;
+
+impl Plugin for EvoLogPlugin {
+    fn build(&self, app: &mut App) {
+        let config = app.world.get_resource_or_insert_with(EvoConfig::default).clone();
+
+        let directives = format!("{},{}", config.log.level, config.log.filter);
+        let mut filter = EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(&directives))
+            .unwrap_or_else(|e| {
+                eprintln!("invalid log filter {}: {}", directives, e);
+                EnvFilter::new("info")
+            });
+        // stage spans are disabled unless profiling
+        if config.profile.enabled {
+            filter = filter.add_directive(format!("{}=trace", PROFILE_TARGET).parse().unwrap());
+        }
+        let profiler = config.profile.enabled.then(Profiler::default);
+
+        let log_file = if config.log.save_log {
+            open_log_file(&config)
+                .map_err(|e| eprintln!("can not open log file: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        // spans are for profiling, log lines are not prefixed by them
+        let file_layer = log_file.clone().map(|file| {
+            fmt::layer()
+                .with_ansi(false)
+                .with_writer(move || file.clone())
+                .with_filter(filter_fn(|metadata| metadata.is_event()))
+        });
+        let stderr_layer = fmt::layer()
+            .with_writer(io::stderr)
+            .with_filter(filter_fn(|metadata| metadata.is_event()));
+
+        let subscriber = Registry::default()
+            .with(filter)
+            .with(stderr_layer)
+            .with(file_layer)
+            .with(profiler.as_ref().map(Profiler::layer));
+        #[cfg(feature = "profile")]
+        let (subscriber, chrome_guard) = {
+            let chrome = if config.profile.chrome_trace {
+                crate::profile::chrome_layer(&config)
+                    .map_err(|e| eprintln!("can not open chrome trace: {}", e))
+                    .ok()
+            } else {
+                None
+            };
+            let (chrome_layer, chrome_guard) = chrome.unzip();
+            (subscriber.with(chrome_layer), chrome_guard)
+        };
+        #[cfg(not(feature = "profile"))]
+        if config.profile.chrome_trace {
+            eprintln!("chrome trace needs cargo feature `profile`");
+        }
+        // there is only one subscriber in a process, e.g. apps of tests share the first one
+        let _ = LogTracer::init();
+        if subscriber::set_global_default(subscriber).is_err() {
+            warn!("Could not set global logger, logs go to the existing one");
+            return;
+        }
+
+        if let Some(file) = log_file {
+            info!("logging to {}{}.log", config.io.export_path, run_id(&config));
+            app.insert_resource(file).add_systems(Last, flush_log);
+        }
+        if let Some(profiler) = profiler {
+            info!("profiling to {}{}.profile.jsonl", config.io.export_path, run_id(&config));
+            app.insert_resource(profiler);
+        }
+        // the trace is written until the app is dropped
+        #[cfg(feature = "profile")]
+        if let Some(chrome_guard) = chrome_guard {
+            app.insert_non_send_resource(chrome_guard);
+        }
+    }
+}
+
+fn open_log_file(config: &EvoConfig) -> io::Result<LogFile> {
+    create_if_not_exist(&config.io.export_path)?;
+    LogFile::open(&format!(
+        "{}{}.log",
+        config.io.export_path,
+        run_id(config)
+    ))
+}
+
+/// write buffered logs at the end of each frame
+fn flush_log(mut file: ResMut<LogFile>) {
+    if let Err(e) = file.flush() {
+        eprintln!("can not write log file: {}", e);
+    }
 }
 
 #[macro_export]
 macro_rules! logger_info {
     ($($arg:tt)*) => {
-        crate::logger::log_to_file("INFO", &format!($($arg)*));
+        bevy::log::info!(target: $crate::logger::TRAIN_TARGET, $($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! logger_warn {
     ($($arg:tt)*) => {
-        crate::logger::log_to_file("WARN", &format!($($arg)*));
+        bevy::log::warn!(target: $crate::logger::TRAIN_TARGET, $($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! logger_error {
     ($($arg:tt)*) => {
-        crate::logger::log_to_file("ERROR", &format!($($arg)*));
+        bevy::log::error!(target: $crate::logger::TRAIN_TARGET, $($arg)*)
     };
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn test_logger() {
         logger_info!("This is an info message.");
         logger_warn!("This is a warning with number: {}", 404);
         logger_error!("An error occurred!");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_log_file() {
+        let path = std::env::temp_dir().join(format!("evosim_log_{}.log", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut file = LogFile::open(path).unwrap();
+        let mut shared = file.clone();
+        writeln!(shared, "first").unwrap();
+        writeln!(file, "second").unwrap();
+        // buffered until flushed
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "");
+        file.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "first\nsecond\n");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,56 +1,406 @@
-#![allow(dead_code)]
-
-mod blob;
-mod brain;
-mod componet;
-mod consts;
-mod contorl;
-mod graphics;
-mod io;
-mod mutate;
-mod physics;
-
-#[macro_use]
-mod logger;
-
-use bevy::prelude::*;
-
-use brain::resource::BevyBlockNeurons;
-use contorl::contorl::BlobContorlPlugin;
-use graphics::*;
-use io::evoio::EvoIOPlugin;
-use mutate::mutate::MutatePlugin;
-use physics::physical_world::PhysiWorldPlugin;
-
-// TODO: Headless mode causing panic
-// TODO: Not all cores are fully tuilized
+use bevy::{core::TaskPoolOptions, log::LogPlugin, prelude::*};
+use clap::Parser;
+
+use evosim::{
+    brain::resource::BevyBlockNeurons,
+    cli::{Cli, Command, RunArgs},
+    config::EvoConfig,
+    consts::THREAD_COUNT,
+    contorl::{
+        contorl::{BlobContorlPlugin, BlobReplayPlugin},
+        resource::EvoRng,
+    },
+    graphics::*,
+    headless::EvoHeadlessPlugin,
+    io::{
+        evoio::EvoIOPlugin,
+        export::ExportFile,
+        import::{load_population, restore_run_state, LoadedCheckpoint},
+        inspect::inspect,
+        lineage::dump_lineage,
+        specimen::extract_specimen,
+    },
+    logger::EvoLogPlugin,
+    mutate::mutate::MutatePlugin,
+    physics::physical_world::PhysiWorldPlugin,
+};
+
 /// Main function to start the simulation (which is a bevy app)
+///
+/// see `cli.rs` for subcommands and flags.
+/// errors of loading and saving files are printed, and exit with code 1
 fn main() {
-    App::new()
-        .add_plugins((
-            // defualt
-            DefaultPlugins,
-            // // set thread count
-            // DefaultPlugins.set(
-            //     TaskPoolPlugin{
-            //         task_pool_options: TaskPoolOptions::with_num_threads(THREAD_COUNT)
-            //     }
-            // ),
-            // // no renderer
-            // DefaultPlugins.set(RenderPlugin {
-            //     wgpu_settings: WgpuSettings {
-            //         backends: None,
-            //         ..default()
-            //     }
-            // }),
-
-            // custom
-            PhysiWorldPlugin,  // init physical world
-            EvoGraphicsPlugin, // vsync and camera
-            EvoIOPlugin,       // import and export
+    let cli = Cli::parse();
+    let train = Command::Train {
+        load: Vec::new(),
+        top: None,
+        run: cli.run,
+    };
+    let result = match cli.command.unwrap_or(train) {
+        Command::Train { load, top, run } => {
+            if load.is_empty() {
+                run_app(&run, None, false)
+            } else {
+                train_from(&run, &load, top)
+            }
+        }
+        Command::Resume { checkpoint, run } => {
+            load_checkpoint(&checkpoint).and_then(|ef| run_app(&run, Some(ef), false))
+        }
+        Command::Replay { checkpoint, run } => {
+            load_checkpoint(&checkpoint).and_then(|ef| run_app(&run, Some(ef), true))
+        }
+        Command::Inspect { checkpoint } => {
+            inspect(&checkpoint).map_err(|e| format!("failed to inspect {}: {}", checkpoint, e))
+        }
+        Command::Lineage {
+            file,
+            format,
+            id,
+            all,
+        } => dump_lineage(&file, format, id, all)
+            .map_err(|e| format!("failed to read lineage {}: {}", file, e)),
+        Command::Specimen {
+            checkpoint,
+            output,
+            id,
+        } => extract_specimen(&checkpoint, id, &output)
+            .map_err(|e| format!("failed to save specimen of {}: {}", checkpoint, e)),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn load_checkpoint(path: &str) -> Result<ExportFile, String> {
+    ExportFile::load(path).map_err(|e| format!("failed to load {}: {}", path, e))
+}
+
+fn load_config(run: &RunArgs, saved: Option<EvoConfig>) -> Result<EvoConfig, String> {
+    run.load_config(saved)
+        .map_err(|e| format!("failed to load config: {}", e))
+}
+
+/// build and run the app
+///
+/// blobs are loaded from `checkpoint` if given, otherwise random blobs are generated.
+/// `replay` runs the simulation without evolution,
+/// otherwise the training is resumed from the checkpoint.
+fn run_app(run: &RunArgs, checkpoint: Option<ExportFile>, replay: bool) -> Result<(), String> {
+    let saved = checkpoint
+        .as_ref()
+        .and_then(|ef| ef.header().config.clone());
+    let mut app = build_app(run, load_config(run, saved)?, replay);
+    if let Some(ef) = checkpoint {
+        insert_checkpoint(&mut app, ef, replay);
+    }
+    app.run();
+    Ok(())
+}
+
+/// start a new training with blobs of checkpoints, see `load_population`
+///
+/// the run state of the checkpoints is not restored
+fn train_from(run: &RunArgs, paths: &[String], top: Option<usize>) -> Result<(), String> {
+    let mut app = build_app(run, load_config(run, None)?, false);
+    // merged blobs are spawned with the seeded rng of the run
+    let ef = app
+        .world
+        .resource_scope(|world, mut rng: Mut<EvoRng>| {
+            load_population(paths, top, world.resource::<EvoConfig>(), &mut rng.0)
+        })
+        .map_err(|e| format!("failed to load population: {}", e))?;
+    app.insert_resource(LoadedCheckpoint(ef));
+    app.run();
+    Ok(())
+}
+
+fn build_app(run: &RunArgs, config: EvoConfig, replay: bool) -> App {
+    if run.headless {
+        headless_app(config, replay)
+    } else {
+        windowed_app(config, replay)
+    }
+}
+
+/// blobs of the checkpoint replace the random population,
+/// training state is restored unless `replay`
+fn insert_checkpoint(app: &mut App, ef: ExportFile, replay: bool) {
+    if !replay {
+        restore_run_state(&mut app.world, &ef, true);
+    }
+    app.insert_resource(LoadedCheckpoint(ef));
+}
+
+/// app with window, renderer and keyboard contorl
+fn windowed_app(config: EvoConfig, replay: bool) -> App {
+    let mut app = App::new();
+    // config should be inserted before plugins read it
+    app.insert_resource(config).add_plugins((
+        // logs of bevy and training go to the same place
+        EvoLogPlugin,
+        // defualt
+        DefaultPlugins
+            .build()
+            .disable::<LogPlugin>()
+            .set(task_pool_plugin()),
+
+        // custom
+        PhysiWorldPlugin,  // init physical world
+        EvoGraphicsPlugin, // vsync and camera
+    ))
+    .init_resource::<BevyBlockNeurons>();
+    add_contorl_plugins(&mut app, replay);
+    app
+}
+
+/// app without window, renderer and keyboard.
+///
+/// `MinimalPlugins` runs the schedule in a loop without waiting,
+/// so the fixed timestep simulation runs as fast as the CPU allows.
+fn headless_app(config: EvoConfig, replay: bool) -> App {
+    let mut app = App::new();
+    let iterations = config.train.headless_iterations;
+    app.insert_resource(config).add_plugins((
+        // default
+        MinimalPlugins.set(task_pool_plugin()),
+        TransformPlugin,
+        HierarchyPlugin,
+        EvoLogPlugin,
+
+        // custom
+        PhysiWorldPlugin,  // init physical world
+        EvoHeadlessPlugin { iterations, replay }, // iteration limit
+    ))
+    .init_resource::<BevyBlockNeurons>();
+    add_contorl_plugins(&mut app, replay);
+    app
+}
+
+/// task pools with `THREAD_COUNT` threads
+fn task_pool_plugin() -> TaskPoolPlugin {
+    let task_pool_options = if THREAD_COUNT == 0 {
+        TaskPoolOptions::default()
+    } else {
+        TaskPoolOptions::with_num_threads(THREAD_COUNT)
+    };
+    TaskPoolPlugin { task_pool_options }
+}
+
+/// plugins for training, or plugin for replay without evolution
+fn add_contorl_plugins(app: &mut App, replay: bool) {
+    if replay {
+        app.add_plugins(BlobReplayPlugin); // update blob each frame
+    } else {
+        app.add_plugins((
+            EvoIOPlugin,       // import, export and checkpoints
             MutatePlugin,      // mutation contorl
             BlobContorlPlugin, // update blob each frame
-        ))
-        .init_resource::<BevyBlockNeurons>()
-        .run();
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{app::AppExit, ecs::system::SystemState};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use evosim::blob::{blob::BlobInfo, block::NeuronId, geno_blob_builder::BlobGeno};
+    use evosim::contorl::resource::Frames;
+    use evosim::io::{
+        export::collect_export_file, retention::LATEST_FILE, specimen::SpawnSpecimen,
+    };
+
+    /// small population, neither stop nor save checkpoints during `iterations`
+    fn test_config(seed: u64, iterations: usize) -> EvoConfig {
+        let mut config = EvoConfig::default();
+        config.train.seed = Some(seed);
+        config.train.population = 6;
+        config.train.iteration_length = 20;
+        config.train.headless_iterations = iterations + 1;
+        config.train.checkpoints_length = iterations + 1;
+        config.io.save_lineage = false;
+        config.io.save_metrics = false;
+        config.log.save_log = false;
+        config
+    }
+
+    /// all blobs and their neurons in the world
+    #[allow(clippy::type_complexity)]
+    fn collect(app: &mut App) -> ExportFile {
+        let mut state: SystemState<(
+            Query<(Entity, (&BlobGeno, &BlobInfo))>,
+            Query<(&Parent, &NeuronId)>,
+            Res<BevyBlockNeurons>,
+        )> = SystemState::new(&mut app.world);
+        let (blob_q, nn_q, bbn) = state.get(&app.world);
+        collect_export_file(&blob_q, &nn_q, &bbn.nnvec)
+    }
+
+    /// run a headless training for a few iterations and collect the population
+    fn run_seeded(seed: u64, iterations: usize) -> String {
+        let config = test_config(seed, iterations);
+        let frames = iterations * config.train.iteration_length;
+        let mut app = headless_app(config, false);
+        for _ in 0..frames {
+            app.update();
+        }
+        serde_json::to_string(&collect(&mut app)).unwrap()
+    }
+
+    #[test]
+    fn same_seed_same_checkpoint() {
+        let a = run_seeded(42, 3);
+        let b = run_seeded(42, 3);
+        assert_eq!(a, b);
+
+        let c = run_seeded(43, 3);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn resume_continues_run() {
+        let dir = std::env::temp_dir().join(format!("evosim_resume_{}", std::process::id()));
+        let mut config = test_config(42, 4);
+        config.train.checkpoints_length = 2;
+        config.io.export_path = format!("{}/", dir.display());
+        let frames = 2 * config.train.iteration_length as u128;
+
+        // the population is replaced right after the checkpoint
+        let mut app = headless_app(config, false);
+        for _ in 0..frames + 1 {
+            app.update();
+        }
+        let continued = collect(&mut app);
+
+        let fname = std::fs::read_to_string(dir.join(LATEST_FILE)).unwrap();
+        let ef = ExportFile::load(dir.join(fname).to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(ef.header().frames, frames);
+        assert!(ef.run_state().unwrap().has_next());
+
+        let mut app = headless_app(ef.header().config.clone().unwrap(), false);
+        insert_checkpoint(&mut app, ef, false);
+        app.update();
+        let resumed = collect(&mut app);
+        assert_eq!(app.world.resource::<Frames>().0, frames + 1);
+
+        // positions are changed by physics, genos and NNs should be the same
+        let population = |ef: &ExportFile| {
+            let blobs: Vec<_> = ef.iter().map(|(geno, _, nns)| (geno, nns)).collect();
+            serde_json::to_string(&blobs).unwrap()
+        };
+        assert_eq!(population(&resumed), population(&continued));
+    }
+
+    #[test]
+    fn merged_population_fits() {
+        let dir = std::env::temp_dir().join(format!("evosim_merge_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = test_config(42, 2);
+        let population = config.train.population;
+        let mut app = headless_app(config.clone(), false);
+        app.update();
+        let ef = collect(&mut app);
+
+        // islands are cut to the best blobs of the population
+        let paths: Vec<String> = (0..3)
+            .map(|idx| {
+                let path = format!("{}/{}.json", dir.display(), idx);
+                ef.write(&path).unwrap();
+                path
+            })
+            .collect();
+        let mut rng = StdRng::seed_from_u64(0);
+        let merged = load_population(&paths, None, &config, &mut rng).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        merged.check().unwrap();
+        assert_eq!(merged.len(), population);
+
+        // more survivers than the population still end the iteration
+        let oversized = ExportFile::merge(vec![ef; 4]);
+        assert!(oversized.len() as f32 * config.train.survival_rate > population as f32);
+        let mut app = headless_app(config.clone(), false);
+        app.insert_resource(LoadedCheckpoint(oversized));
+        for _ in 0..config.train.iteration_length + 2 {
+            app.update();
+        }
+        assert_eq!(collect(&mut app).len(), population);
+    }
+
+    #[test]
+    fn spawn_specimen_adds_blob() {
+        let config = test_config(42, 1);
+        let population = config.train.population;
+        let mut app = headless_app(config, false);
+        app.update();
+        let ef = collect(&mut app);
+        let nn_len = app.world.resource::<BevyBlockNeurons>().nnvec.len();
+
+        let specimen = ef.pick(&[0]);
+        specimen.check().unwrap();
+        app.world.send_event(SpawnSpecimen {
+            specimen: specimen.clone(),
+            pos: [0.0, 0.0],
+        });
+        app.update();
+
+        let ef = collect(&mut app);
+        ef.check().unwrap();
+        assert_eq!(ef.len(), population + 1);
+        assert_eq!(
+            app.world.resource::<BevyBlockNeurons>().nnvec.len(),
+            nn_len + specimen.flatten_nnvec().len()
+        );
+    }
+
+    #[test]
+    fn explicit_config_must_load() {
+        let cli = Cli::try_parse_from(["evosim", "--config", "missing/config.json"]).unwrap();
+        assert!(load_config(&cli.run, None).is_err());
+        // the saved config is not used either
+        assert!(load_config(&cli.run, Some(EvoConfig::default())).is_err());
+    }
+
+    #[test]
+    fn headless_replay_exits() {
+        let dir = std::env::temp_dir().join(format!("evosim_replay_{}", std::process::id()));
+        let mut config = test_config(42, 1);
+        config.io.export_path = format!("{}/", dir.display());
+        let mut app = headless_app(config.clone(), false);
+        app.update();
+        let ef = collect(&mut app);
+
+        config.train.headless_iterations = 1;
+        let mut app = headless_app(config.clone(), true);
+        insert_checkpoint(&mut app, ef, true);
+        for _ in 1..config.train.iteration_length {
+            app.update();
+            assert!(app.world.resource::<Events<AppExit>>().is_empty());
+        }
+        app.update();
+        assert!(!app.world.resource::<Events<AppExit>>().is_empty());
+        // replay never saves a checkpoint
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn seed_and_run_id_resolved_before_app() {
+        let cli = Cli::try_parse_from(["evosim", "--headless"]).unwrap();
+        let mut saved = test_config(0, 1);
+        saved.train.seed = None;
+        let config = load_config(&cli.run, Some(saved)).unwrap();
+        let seed = config.train.seed.unwrap();
+        let run_id = config.io.run_id.clone().unwrap();
+        assert!(run_id.ends_with(&seed.to_string()));
+
+        // plugins keep the resolved seed and run id
+        let mut app = headless_app(config, false);
+        app.update();
+        let config = app.world.resource::<EvoConfig>();
+        assert_eq!(config.train.seed, Some(seed));
+        assert_eq!(config.io.run_id, Some(run_id));
+    }
 }
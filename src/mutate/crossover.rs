@@ -0,0 +1,311 @@
+//! implementation of crossover (sexual reproduction) between two blobs
+//!
+//! - parents with different tree structure swap a subtree,
+//!   each block in the subtree carries its own `BlockNN`
+//! - parents with the same tree structure keep the morphyology of the first parent,
+//!   and mix the weights of the NNs of each block
+
+use ndarray::Zip;
+use rand::prelude::*;
+
+use crate::{
+    blob::geno_blob_builder::{BlobGeno, GenericGenoNode, GenoNode},
+    brain::{
+        neuron::{BlockNN, GenericNN},
+        nn::BaseNN,
+    },
+    config::MutateConfig,
+};
+
+/// generate an offspring of two parents
+///
+/// nn_id in the returned geno is the index of the returned NN vector,
+/// both parents' nn_id should be index of `nnvec`.
+///
+/// return `None` if no valid offspring is found in `crossover_max_try` times
+pub fn crossover(
+    parent_a: &BlobGeno,
+    parent_b: &BlobGeno,
+    nnvec: &[GenericNN],
+    config: &MutateConfig,
+    rng: &mut dyn RngCore,
+) -> Option<(BlobGeno, Vec<GenericNN>)> {
+    if same_topology(parent_a, parent_b) {
+        Some(nn_crossover(parent_a, parent_b, nnvec, config, rng))
+    } else {
+        subtree_crossover(parent_a, parent_b, config, rng)
+            .map(|geno| collect_nn(geno, |id| nnvec[id].clone()))
+    }
+}
+
+/// parents have the same tree structure if every node is the same kind
+fn same_topology(a: &BlobGeno, b: &BlobGeno) -> bool {
+    a.vec_tree.nodes.len() == b.vec_tree.nodes.len()
+        && a.vec_tree
+            .nodes
+            .iter()
+            .zip(b.vec_tree.nodes.iter())
+            .all(|pair| {
+                matches!(
+                    pair,
+                    (None, None)
+                        | (Some(GenericGenoNode::Parent), Some(GenericGenoNode::Parent))
+                        | (Some(GenericGenoNode::Child(_)), Some(GenericGenoNode::Child(_)))
+                )
+            })
+}
+
+/// replace a random subtree of `parent_a` with the subtree at the same position of `parent_b`.
+///
+/// the swapped subtree is moved to attach to the block in `parent_a`,
+/// nn_id of the swapped nodes still point to NNs of `parent_b`.
+fn subtree_crossover(
+    parent_a: &BlobGeno,
+    parent_b: &BlobGeno,
+    config: &MutateConfig,
+    rng: &mut dyn RngCore,
+) -> Option<BlobGeno> {
+    let tree_a = &parent_a.vec_tree;
+    let tree_b = &parent_b.vec_tree;
+    if tree_a.nodes.len() != tree_b.nodes.len() {
+        return None;
+    }
+
+    // non-root blocks in b, whose parent in a is a block,
+    // and the same position in a is not the parent indicator
+    let candidates: Vec<usize> = (1..tree_b.nodes.len())
+        .filter(|&idx| {
+            matches!(tree_b.nodes[idx], Some(GenericGenoNode::Child(_)))
+                && !matches!(tree_a.nodes[idx], Some(GenericGenoNode::Parent))
+                && matches!(
+                    tree_a.nodes[tree_a.parent(idx).unwrap()],
+                    Some(GenericGenoNode::Child(_))
+                )
+        })
+        .collect();
+
+    for _ in 0..config.crossover_max_try {
+        let &idx = candidates.choose(rng)?;
+
+        let mut offspring = parent_a.clone();
+        let tree = &mut offspring.vec_tree;
+        let direction = (idx - 1) % 4;
+        let (Some(GenericGenoNode::Child(parent)), Some(GenericGenoNode::Child(root))) =
+            (&tree.nodes[tree.parent(idx).unwrap()], &tree_b.nodes[idx])
+        else {
+            continue;
+        };
+        let attach = attach_center(parent, root, direction);
+        let offset = [attach[0] - root.center[0], attach[1] - root.center[1]];
+
+        tree.clean_subtree(idx);
+        for i in tree_b.subtree_indices(idx) {
+            let mut node = tree_b.nodes[i].clone();
+            if let Some(GenericGenoNode::Child(child)) = &mut node {
+                child.center[0] += offset[0];
+                child.center[1] += offset[1];
+            }
+            tree.nodes[i] = node;
+        }
+
+        if offspring.is_valid() {
+            return Some(offspring);
+        }
+    }
+    None
+}
+
+/// center of a child block attached to `parent` at `direction`,
+/// same as the center calculation in random geno generation
+fn attach_center(parent: &GenoNode, child: &GenoNode, direction: usize) -> [f32; 2] {
+    let [px, py] = parent.center;
+    let [pdx, pdy] = parent.size;
+    let [dx, dy] = child.size;
+    match direction {
+        0 => [px, py + pdy + dy],
+        1 => [px, py - pdy - dy],
+        2 => [px - pdx - dx, py],
+        3 => [px + pdx + dx, py],
+        _ => panic!(),
+    }
+}
+
+/// keep morphyology of `parent_a`, mix NNs of each block with `parent_b`
+fn nn_crossover(
+    parent_a: &BlobGeno,
+    parent_b: &BlobGeno,
+    nnvec: &[GenericNN],
+    config: &MutateConfig,
+    rng: &mut dyn RngCore,
+) -> (BlobGeno, Vec<GenericNN>) {
+    let arithmetic = rng.gen_bool(config.nn_arithmetic_crossover_prob as f64);
+
+    // nn of b at the same position
+    let ids_b: Vec<usize> = parent_b.all_usize_nn_ids();
+    let ids_a: Vec<usize> = parent_a.all_usize_nn_ids();
+
+    let mut mixed = Vec::<GenericNN>::new();
+    for (&id_a, &id_b) in ids_a.iter().zip(ids_b.iter()) {
+        mixed.push(crossover_generic_nn(
+            &nnvec[id_a],
+            &nnvec[id_b],
+            arithmetic,
+            rng,
+        ));
+    }
+
+    let mut mixed = mixed.into_iter();
+    collect_nn(parent_a.clone(), |_| mixed.next().unwrap())
+}
+
+/// renumber nn_id of geno from 0, and collect NNs in the same order
+fn collect_nn(
+    mut geno: BlobGeno,
+    mut get_nn: impl FnMut(usize) -> GenericNN,
+) -> (BlobGeno, Vec<GenericNN>) {
+    let mut nns = Vec::<GenericNN>::new();
+    for nn_id in geno.all_nn_ids_mut() {
+        nns.push(get_nn(nn_id.unwrap()));
+        *nn_id = Some(nns.len() - 1);
+    }
+    (geno, nns)
+}
+
+fn crossover_generic_nn(
+    a: &GenericNN,
+    b: &GenericNN,
+    arithmetic: bool,
+    rng: &mut dyn RngCore,
+) -> GenericNN {
+    match (a, b) {
+        (GenericNN::BRAINNN(nn_a), GenericNN::BRAINNN(nn_b)) => {
+            let mut nn = nn_a.clone();
+            nn.nn = crossover_base_nn(&nn_a.nn, &nn_b.nn, arithmetic, rng);
+            GenericNN::BRAINNN(nn)
+        }
+        (GenericNN::BLOCKNN(nn_a), GenericNN::BLOCKNN(nn_b)) => {
+            GenericNN::BLOCKNN(crossover_block_nn(nn_a, nn_b, arithmetic, rng))
+        }
+        // the root is always brain, should not happen with the same topology
+        _ => a.clone(),
+    }
+}
+
+fn crossover_block_nn(
+    a: &BlockNN,
+    b: &BlockNN,
+    arithmetic: bool,
+    rng: &mut dyn RngCore,
+) -> BlockNN {
+    let mut nn = a.clone();
+    nn.inward_nn.nn = crossover_base_nn(&a.inward_nn.nn, &b.inward_nn.nn, arithmetic, rng);
+    nn.outward_nn.nn = crossover_base_nn(&a.outward_nn.nn, &b.outward_nn.nn, arithmetic, rng);
+    nn
+}
+
+/// uniform crossover takes each weight and bias from one of the parents,
+/// arithmetic crossover takes a random weighted average of the parents.
+///
+/// NNs with different shapes can not crossover, `a` is returned
+pub fn crossover_base_nn(
+    a: &BaseNN,
+    b: &BaseNN,
+    arithmetic: bool,
+    rng: &mut dyn RngCore,
+) -> BaseNN {
+    let mut nn = a.clone();
+    if a.shape() != b.shape() {
+        return nn;
+    }
+
+    let alpha: f32 = rng.gen();
+    let mut mix = |x: &mut f32, y: &f32| {
+        if arithmetic {
+            *x = alpha * *x + (1.0 - alpha) * *y;
+        } else if rng.gen_bool(0.5) {
+            *x = *y;
+        }
+    };
+    for (layer, layer_b) in nn.layers.iter_mut().zip(b.layers.iter()) {
+        Zip::from(&mut layer.weights)
+            .and(&layer_b.weights)
+            .for_each(&mut mix);
+        Zip::from(&mut layer.bias).and(&layer_b.bias).for_each(&mut mix);
+    }
+    // recurrent weights are mixed if both are recurrent, otherwise they are from `a`
+    if let (Some(recurrent), Some(recurrent_b)) = (&mut nn.recurrent, &b.recurrent) {
+        Zip::from(&mut recurrent.weights)
+            .and(&recurrent_b.weights)
+            .for_each(&mut mix);
+    }
+    nn
+}
+
+#[cfg(test)]
+mod crossover_test {
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::{brain::neuron::BrainNN, config::EvoConfig};
+
+    /// random geno with NNs appended to `nnvec`
+    fn rand_geno(config: &EvoConfig, nnvec: &mut Vec<GenericNN>, rng: &mut dyn RngCore) -> BlobGeno {
+        let mut geno = BlobGeno::new_rand(&config.geno, rng);
+        for (i, nn_id) in geno.all_nn_ids_mut().into_iter().enumerate() {
+            if i == 0 {
+                nnvec.push(GenericNN::BRAINNN(BrainNN::new(&config.nn, rng)));
+            } else {
+                nnvec.push(GenericNN::BLOCKNN(BlockNN::new(&config.nn, rng)));
+            }
+            *nn_id = Some(nnvec.len() - 1);
+        }
+        geno
+    }
+
+    #[test]
+    fn test_crossover_offspring_validation() {
+        let config = EvoConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mut nnvec = Vec::<GenericNN>::new();
+            let parent_a = rand_geno(&config, &mut nnvec, &mut rng);
+            let parent_b = rand_geno(&config, &mut nnvec, &mut rng);
+            let Some((geno, nns)) =
+                crossover(&parent_a, &parent_b, &nnvec, config.mutate(), &mut rng)
+            else {
+                continue;
+            };
+            assert!(geno.is_valid());
+            assert_eq!(geno.all_usize_nn_ids(), (0..nns.len()).collect::<Vec<usize>>());
+            assert!(matches!(nns[0], GenericNN::BRAINNN(_)));
+            assert!(nns[1..].iter().all(|nn| matches!(nn, GenericNN::BLOCKNN(_))));
+        }
+    }
+
+    #[test]
+    fn test_base_nn_crossover() {
+        let config = EvoConfig::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let a = BlockNN::new(&config.nn, &mut rng).inward_nn.nn;
+        let b = BlockNN::new(&config.nn, &mut rng).inward_nn.nn;
+
+        // every weight of uniform crossover comes from one of the parents
+        let child = crossover_base_nn(&a, &b, false, &mut rng);
+        assert_eq!(child.shape(), a.shape());
+        for ((lc, la), lb) in child.layers.iter().zip(a.layers.iter()).zip(b.layers.iter()) {
+            Zip::from(&lc.weights)
+                .and(&la.weights)
+                .and(&lb.weights)
+                .for_each(|c, x, y| assert!(c == x || c == y));
+        }
+
+        // arithmetic crossover stays between the parents
+        let child = crossover_base_nn(&a, &b, true, &mut rng);
+        for ((lc, la), lb) in child.layers.iter().zip(a.layers.iter()).zip(b.layers.iter()) {
+            Zip::from(&lc.bias)
+                .and(&la.bias)
+                .and(&lb.bias)
+                .for_each(|c, x, y| assert!(*c >= x.min(*y) - 1e-6 && *c <= x.max(*y) + 1e-6));
+        }
+    }
+}
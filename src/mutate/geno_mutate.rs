@@ -3,41 +3,67 @@
 use std::f32::consts::PI;
 
 use rand::prelude::*;
+use rand_distr::{Distribution, Normal};
 
 use crate::{
-    blob::geno_blob_builder::{BlobGeno, GenericGenoNode, GenoNode},
-    consts::{*, mutate_consts::*},
+    blob::{
+        geno_blob_builder::{BlobGeno, GenericGenoNode, GenoNode},
+        lineage::Mutation,
+    },
+    config::{EvoConfig, GenoConfig, MutateConfig},
 };
 
-const CLAMP: [f32;2] = MUTATE_SINGLE_BLOCK_SIZE_CLAMP_SCALER;
-
 /// loop over all blobs to mutate geno.
-/// mutate tree-structure, block-size, joint-limit in the order
+/// mutate tree-structure, block-size, joint-limit in the order,
+/// applied mutations are recorded in the lineage of the geno
 /// 
 /// After the mutation, the genos and the NN is unmatched, 
 /// will be rematched in function `sync_mutate`
 pub fn mutate_geno(
-    geno_q: &mut Vec<BlobGeno>
+    geno_q: &mut Vec<BlobGeno>,
+    config: &EvoConfig,
+    rng: &mut dyn RngCore,
 ) {
-    for mut geno in geno_q {
-        mutate_tree_structure(&mut geno);
-        mutate_block_size(&mut geno);
-        mutate_joint_limit(&mut geno)
+    let mutate_config = config.mutate();
+    for geno in geno_q {
+        let mut mutations = Vec::<Mutation>::new();
+        mutations.extend(mutate_tree_structure(geno, mutate_config, &config.geno, rng));
+
+        let nodes = mutate_block_size(geno, mutate_config, &config.geno, rng);
+        if !nodes.is_empty() {
+            mutations.push(Mutation::BlockSize(nodes));
+        }
+
+        let nodes = mutate_joint_limit(geno, mutate_config, rng);
+        if !nodes.is_empty() {
+            mutations.push(Mutation::JointLimit(nodes));
+        }
+
+        if mutate_cpg(geno, mutate_config, &config.geno, rng) {
+            mutations.push(Mutation::Cpg);
+        }
+
+        geno.lineage.mutations.extend(mutations);
     }
 }
 
 /// gain or lose limbs for a blob
 /// 
 /// gain limb might cause self confilt.
-/// set `MUTATE_GAIN_LIMB_MAX_TRY` to try if gain limb process is unsuccessful.
-pub fn mutate_tree_structure(geno: &mut BlobGeno) {
-    let mut rng: ThreadRng = thread_rng();
-
-    if !rng.gen_bool(MUTATE_TREE_STRUCTURE_PROB as f64) {
-        return;
+/// set `gain_limb_max_try` to try if gain limb process is unsuccessful.
+/// 
+/// return the mutation if the tree changed
+pub fn mutate_tree_structure(
+    geno: &mut BlobGeno,
+    config: &MutateConfig,
+    geno_config: &GenoConfig,
+    rng: &mut dyn RngCore,
+) -> Option<Mutation> {
+    if !rng.gen_bool(config.tree_structure_prob as f64) {
+        return None;
     }
 
-    if rng.gen_bool(MUTATE_GAIN_LIMB_PROB as f64) {
+    if rng.gen_bool(config.gain_limb_prob as f64) {
         // gain limb
         let mut candidates = geno.vec_tree.branch_nodes();
         if candidates.is_empty() {
@@ -45,14 +71,15 @@ pub fn mutate_tree_structure(geno: &mut BlobGeno) {
             candidates.push(0);
         }
 
-        for _ in 0..MUTATE_GAIN_LIMB_MAX_TRY {
-            if let Some(idx) = candidates.iter().choose(&mut rand::thread_rng()) {
+        for _ in 0..config.gain_limb_max_try {
+            if let Some(idx) = candidates.iter().choose(rng) {
                 // loop till get validate limb
-                if gain_limb(geno, *idx) {
-                    break;
+                if let Some(new_idx) = gain_limb(geno, *idx, geno_config, rng) {
+                    return Some(Mutation::GainLimb(new_idx));
                 }
             }
         }
+        None
     } else {
         // TODO: it is better not lose parent indicator, which might cause self-confilt if a node
         // without parent indicator gain four limbs
@@ -62,17 +89,22 @@ pub fn mutate_tree_structure(geno: &mut BlobGeno) {
         if candidates.len() <= 1 {
             // the only leaf is root, which cannot lose limb
             // or the root only have one limb left
-            return;
-        }
-        if let Some(idx) = candidates.iter().choose(&mut rand::thread_rng()) {
-            lose_limb(geno, *idx);
+            return None;
         }
+        let &idx = candidates.iter().choose(rng)?;
+        lose_limb(geno, idx);
+        Some(Mutation::LoseLimb(idx))
     }
 }
 
 /// gain a new limb as the child of the index node
-/// return type means success or fail
-fn gain_limb(geno: &mut BlobGeno, idx: usize) -> bool {
+/// return index of the new limb, `None` means fail
+fn gain_limb(
+    geno: &mut BlobGeno,
+    idx: usize,
+    config: &GenoConfig,
+    rng: &mut dyn RngCore,
+) -> Option<usize> {
     // direction and index of node
     // slots are nodes has `none` as value
     let slots: Vec<(usize, usize)> = geno
@@ -85,20 +117,20 @@ fn gain_limb(geno: &mut BlobGeno, idx: usize) -> bool {
         .collect();
 
     if slots.is_empty() {
-        return false;
+        return None;
     }
-    let choosen = *slots.iter().choose(&mut rand::thread_rng()).unwrap();
+    let choosen = *slots.iter().choose(rng).unwrap();
     if let Some(Some(GenericGenoNode::Child(parent))) = geno.vec_tree.nodes.get(idx) {
         // TODO: new nodes should also have parent indicator
-        geno.vec_tree.nodes[choosen.1] = Some(new_rand_node(parent, choosen.0));
+        geno.vec_tree.nodes[choosen.1] = Some(new_rand_node(parent, choosen.0, config, rng));
         if geno.is_valid() {
-            return true;
+            Some(choosen.1)
         } else {
             geno.vec_tree.nodes[choosen.1] = None;
-            return false;
+            None
         }
     } else {
-        false
+        None
     }
 }
 
@@ -108,8 +140,12 @@ fn gain_limb(geno: &mut BlobGeno, idx: usize) -> bool {
 /// 
 /// Need to know the direction of the node to generate to prevent self confilt
 /// and to calculate the presice position of the new block.
-fn new_rand_node(parent: &GenoNode, direction: usize) -> GenericGenoNode {
-    let mut rng = thread_rng();
+fn new_rand_node(
+    parent: &GenoNode,
+    direction: usize,
+    config: &GenoConfig,
+    rng: &mut dyn RngCore,
+) -> GenericGenoNode {
 
     let parent_size = parent.size;
     let parent_center = parent.center;
@@ -134,9 +170,11 @@ fn new_rand_node(parent: &GenoNode, direction: usize) -> GenericGenoNode {
     // }
 
     // no limitation implementation
+    let scaler = config.rand_size_scaler;
+    let default_size = config.default_block_size;
     let size = [
-        rng.gen_range(RAND_SIZE_SCALER[0] * DEFAULT_BLOCK_SIZE[0]..RAND_SIZE_SCALER[1] * DEFAULT_BLOCK_SIZE[0]),
-        rng.gen_range(RAND_SIZE_SCALER[0] * DEFAULT_BLOCK_SIZE[1]..RAND_SIZE_SCALER[1] * DEFAULT_BLOCK_SIZE[1]),
+        rng.gen_range(scaler[0] * default_size[0]..scaler[1] * default_size[0]),
+        rng.gen_range(scaler[0] * default_size[1]..scaler[1] * default_size[1]),
     ];
 
     // center
@@ -161,12 +199,13 @@ fn new_rand_node(parent: &GenoNode, direction: usize) -> GenericGenoNode {
         ]
     }
 
-    return GenericGenoNode::Child(GenoNode {
+    GenericGenoNode::Child(GenoNode {
         joint_limits,
         size,
         center,
         nn_id: None,
-    });
+        phase_offset: rng.gen_range(-PI..PI),
+    })
 }
 
 /// the blob lose a block at index `idx`, and all its subnodes.
@@ -185,33 +224,43 @@ fn lose_limb(geno: &mut BlobGeno, idx: usize) {
 /// all blocks of the blob can be mutate (but not must be mutate)
 /// 
 /// the mutation must valid, which means this function won't cause self confilt
-pub fn mutate_block_size(geno: &mut BlobGeno) {
-    let mut rng = thread_rng();
-
-    if !rng.gen_bool(MUTATE_BLOCK_SIZE_PROB as f64) {
-        return;
+/// 
+/// return indices of blocks whose size changed
+pub fn mutate_block_size(
+    geno: &mut BlobGeno,
+    config: &MutateConfig,
+    geno_config: &GenoConfig,
+    rng: &mut dyn RngCore,
+) -> Vec<usize> {
+    let clamp = config.single_block_size_clamp_scaler;
+    let default_size = geno_config.default_block_size;
+
+    if !rng.gen_bool(config.block_size_prob as f64) {
+        return Vec::new();
     }
 
     let mut potential_mutations: Vec<(usize, [f32; 2])> = Vec::new();
 
     for (index, i) in geno.vec_tree.nodes.iter().enumerate() {
         if let Some(GenericGenoNode::Child(node)) = i {
-            if !rng.gen_bool(MUTATE_SINGLE_BLOCK_SIZE_PROB as f64) {
+            if !rng.gen_bool(config.single_block_size_prob as f64) {
                 continue;
             }
             let mutation_factor_0 = rng.gen_range(0.9..=1.1);
             let mutation_factor_1 = rng.gen_range(0.9..=1.1);
-            let new_size_0 = (node.size[0] * mutation_factor_0).clamp(DEFAULT_BLOCK_SIZE[0]*CLAMP[0], DEFAULT_BLOCK_SIZE[0]*CLAMP[1]);
-            let new_size_1 = (node.size[1] * mutation_factor_1).clamp(DEFAULT_BLOCK_SIZE[1]*CLAMP[0], DEFAULT_BLOCK_SIZE[1]*CLAMP[1]);
+            let new_size_0 = (node.size[0] * mutation_factor_0).clamp(default_size[0]*clamp[0], default_size[0]*clamp[1]);
+            let new_size_1 = (node.size[1] * mutation_factor_1).clamp(default_size[1]*clamp[0], default_size[1]*clamp[1]);
     
             // Store the mutation
             potential_mutations.push((index, [new_size_0, new_size_1]));
         }
     }
     
-    for (index, new_size) in &potential_mutations {
-        mutate_single_block_size(geno, *index, *new_size);      
-    }
+    potential_mutations
+        .into_iter()
+        .filter(|&(index, new_size)| mutate_single_block_size(geno, index, new_size))
+        .map(|(index, _)| index)
+        .collect()
 }
 
 
@@ -221,12 +270,12 @@ pub fn mutate_block_size(geno: &mut BlobGeno) {
 /// which can casue self-confilt to happen.
 /// 
 /// if the mutation (new size) is not valid (cause self confilt), 
-/// then will not apply
+/// then will not apply, and return false
 fn mutate_single_block_size(
     geno: &mut BlobGeno,
     index: usize,
     new_size: [f32;2]
-) {
+) -> bool {
     let temp_geno = geno.clone();
 
     if let Some(Some(GenericGenoNode::Child(node))) = temp_geno.vec_tree.nodes.get(index) {
@@ -248,7 +297,9 @@ fn mutate_single_block_size(
     // validation check
     if !geno.is_valid() {
         *geno = temp_geno;
+        return false;
     }
+    true
 }
 
 /// if a block has its size changed, all its subnode will have their position changed
@@ -262,7 +313,7 @@ fn get_movevec(
     new_size: [f32;2]
 ) -> Option<([f32;2],[f32;2],[f32;2])> {
     if index == 0 {
-        return None
+        None
     } else {
         let top = [0.0, new_size[1]-old_size[1]];
         let bottom = [0.0, old_size[1]-new_size[1]];
@@ -297,19 +348,58 @@ fn get_movevec(
 }
 
 /// Mutate joint limit of limbs
-pub fn mutate_joint_limit(geno: &mut BlobGeno){
-    let mut rng: ThreadRng = thread_rng();
-
-    for i in geno.vec_tree.nodes.iter_mut(){
-        if !rng.gen_bool(MUTATE_JOINT_LIMIT_PROB as f64) {
+/// 
+/// return indices of mutated limbs
+pub fn mutate_joint_limit(
+    geno: &mut BlobGeno,
+    config: &MutateConfig,
+    rng: &mut dyn RngCore,
+) -> Vec<usize> {
+    let mut mutated = Vec::new();
+    for (index, i) in geno.vec_tree.nodes.iter_mut().enumerate() {
+        if !rng.gen_bool(config.joint_limit_prob as f64) {
             continue;
         }
         if let Some(GenericGenoNode::Child(node)) = i {
             let mutation_factor_0 = rng.gen_range(0.9..=1.1);
             let mutation_factor_1 = rng.gen_range(0.9..=1.1);
-            let new_limit_0 = (node.joint_limits[0] * mutation_factor_0).clamp(MUTATE_JOINT_LIMIT_MIN, 0.0);
-            let new_limit_1 = (node.joint_limits[1] * mutation_factor_1).clamp(0.0, MUTATE_JOINT_LIMIT_MAX);
+            let new_limit_0 = (node.joint_limits[0] * mutation_factor_0).clamp(config.joint_limit_min, 0.0);
+            let new_limit_1 = (node.joint_limits[1] * mutation_factor_1).clamp(0.0, config.joint_limit_max);
             node.joint_limits = [new_limit_0,new_limit_1];
+            mutated.push(index);
         }
     }
-}
\ No newline at end of file
+    mutated
+}
+
+/// Mutate the oscillator of the blob, see `cpg.rs`.
+///
+/// frequency is scaled by `exp(N(0, cpg_std))`, frequency and amplitude are clamped
+/// in their random ranges, phase and phase offsets of all nodes are perturbed by `N(0, cpg_std * PI)` and wrapped
+///
+/// return true if mutated
+pub fn mutate_cpg(
+    geno: &mut BlobGeno,
+    config: &MutateConfig,
+    geno_config: &GenoConfig,
+    rng: &mut dyn RngCore,
+) -> bool {
+    if !rng.gen_bool(config.cpg_prob as f64) {
+        return false;
+    }
+    let normal = Normal::new(0.0, config.cpg_std).unwrap();
+    let wrap = |phase: f32| (phase + PI).rem_euclid(2.0 * PI) - PI;
+
+    let cpg = &mut geno.cpg;
+    cpg.frequency = (cpg.frequency * normal.sample(rng).exp())
+        .clamp(geno_config.cpg_frequency[0], geno_config.cpg_frequency[1]);
+    cpg.amplitude = (cpg.amplitude + normal.sample(rng))
+        .clamp(geno_config.cpg_amplitude[0], geno_config.cpg_amplitude[1]);
+    cpg.phase = wrap(cpg.phase + normal.sample(rng) * PI);
+    for node in geno.vec_tree.nodes.iter_mut() {
+        if let Some(GenericGenoNode::Child(node)) = node {
+            node.phase_offset = wrap(node.phase_offset + normal.sample(rng) * PI);
+        }
+    }
+    true
+}
@@ -1,5 +1,7 @@
 //! all implementations relate to mutation
 
+#[allow(clippy::module_inception)]
 pub mod mutate;
+pub mod crossover;
 mod geno_mutate;
 mod nn_mutate;
\ No newline at end of file
@@ -2,6 +2,7 @@
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::ImpulseJoint;
+use rand::RngCore;
 
 use crate::{
     blob::{
@@ -13,12 +14,20 @@ use crate::{
         resource::BevyBlockNeurons,
     },
     componet::ColliderFlag,
+    config::EvoConfig,
     consts::MUTATE_AND_REFRESH_KEYCODE,
-    contorl::{resource::TrainMutPipe, update::block_action},
+    contorl::{
+        resource::{EvoRng, TrainMutPipe},
+        update::block_action,
+    },
     physics::world::Wall,
+    profile_span,
 };
 
-use super::{geno_mutate::mutate_geno, nn_mutate::mutate_nn};
+use super::{
+    geno_mutate::mutate_geno,
+    nn_mutate::{mutate_nn, record_nn_mutation},
+};
 
 /// all implementations relate to mutation
 /// 
@@ -35,7 +44,13 @@ pub struct MutatePlugin;
 impl Plugin for MutatePlugin {
     fn build(&self, app: &mut App) {
         // this function is not mutation in training process
-        app.add_systems(Update, mutate_and_refresh.after(block_action));
+        app.init_resource::<EvoRng>().add_systems(
+            Update,
+            mutate_and_refresh
+                .after(block_action)
+                .after(mutate_and_refresh_after_train)
+                .run_if(resource_exists::<Input<KeyCode>>()),
+        );
     }
 }
 
@@ -44,6 +59,7 @@ impl Plugin for MutatePlugin {
 /// 
 /// Notice: this function only preform manually mutation after button was pressed,
 /// automatical mutation in training process is fn `mutate_and_refresh_after_train`
+#[allow(clippy::too_many_arguments)]
 pub fn mutate_and_refresh(
     mut commands: Commands,
     mut bbn: ResMut<BevyBlockNeurons>,
@@ -52,6 +68,8 @@ pub fn mutate_and_refresh(
     collider_q: Query<Entity, (With<ColliderFlag>, Without<Wall>)>,
     joint_q: Query<Entity, With<ImpulseJoint>>,
     input: Res<Input<KeyCode>>,
+    config: Res<EvoConfig>,
+    mut rng: ResMut<EvoRng>,
 ) {
     let mut geno_vec = Vec::<BlobGeno>::new();
     let mut info_vec = Vec::<&BlobInfo>::new();
@@ -61,10 +79,11 @@ pub fn mutate_and_refresh(
     }
 
     if input.just_pressed(MUTATE_AND_REFRESH_KEYCODE) {
-        mutate_geno(&mut geno_vec);
-        mutate_nn(&mut bbn.nnvec);
+        mutate_geno(&mut geno_vec, &config, &mut rng.0);
+        let mutated = mutate_nn(&mut bbn.nnvec, config.mutate(), &mut rng.0);
+        record_nn_mutation(&mut geno_vec, &mutated);
 
-        let (mut genovec, nnvec) = sync_mutate(&mut geno_vec, &mut bbn);
+        let (mut genovec, nnvec) = sync_mutate(&mut geno_vec, &mut bbn, &config, &mut rng.0);
 
         // despawn
         for entity in blob_q.iter().chain(collider_q.iter()).chain(joint_q.iter()) {
@@ -73,7 +92,8 @@ pub fn mutate_and_refresh(
 
         // temp empty vector for builder
         let mut temp_nnvec = Vec::<GenericNN>::new();
-        let mut builder = GenoBlobBuilder::from_commands(commands, &mut temp_nnvec);
+        let mut builder =
+            GenoBlobBuilder::from_commands(commands, &mut temp_nnvec, &config, &mut rng.0);
 
         for (geno, &info) in genovec.iter_mut().zip(info_vec.iter()) {
             builder.build(geno, info.center_block_pos.to_array())
@@ -87,6 +107,7 @@ pub fn mutate_and_refresh(
 /// mutate all blobs in both geno and nn,
 /// refresh the field, update all blobs componets,
 /// and update `BevyBlockNeurons` resource
+#[allow(clippy::too_many_arguments)]
 pub fn mutate_and_refresh_after_train(
     mut commands: Commands,
     mut bbn: ResMut<BevyBlockNeurons>,
@@ -96,6 +117,8 @@ pub fn mutate_and_refresh_after_train(
     collider_q: Query<Entity, (With<ColliderFlag>, Without<Wall>)>,
     joint_q: Query<Entity, With<ImpulseJoint>>,
     // input: Res<Input<KeyCode>>,
+    config: Res<EvoConfig>,
+    mut rng: ResMut<EvoRng>,
 ) {
     // emtpy pipe means no tournament selection preformed in this frame
     if pipe.is_empty() {
@@ -104,13 +127,18 @@ pub fn mutate_and_refresh_after_train(
 
     let (mut pipe_genovec, infovec, mut pipe_nnvec) = pipe.pop();
 
-    mutate_geno(&mut pipe_genovec);
-    mutate_nn(&mut pipe_nnvec);
+    let mutation_span = profile_span!("mutation");
+    mutate_geno(&mut pipe_genovec, &config, &mut rng.0);
+    let mutated = mutate_nn(&mut pipe_nnvec, config.mutate(), &mut rng.0);
+    record_nn_mutation(&mut pipe_genovec, &mutated);
 
     bbn.nnvec = pipe_nnvec;
 
-    let (mut genovec, nnvec) = sync_mutate(&mut pipe_genovec, &mut bbn);
+    let (mut genovec, nnvec) = sync_mutate(&mut pipe_genovec, &mut bbn, &config, &mut rng.0);
+    drop(mutation_span);
 
+    // blobs are spawned when the commands are applied, this is the time to queue them
+    let _span = profile_span!("respawn");
     // despawn
     for entity in blob_q.iter().chain(collider_q.iter()).chain(joint_q.iter()) {
         commands.entity(entity).despawn()
@@ -118,7 +146,8 @@ pub fn mutate_and_refresh_after_train(
 
     // temp empty vector for builder
     let mut temp_nnvec = Vec::<GenericNN>::new();
-    let mut builder = GenoBlobBuilder::from_commands(commands, &mut temp_nnvec);
+    let mut builder =
+        GenoBlobBuilder::from_commands(commands, &mut temp_nnvec, &config, &mut rng.0);
 
     for (geno, info) in genovec.iter_mut().zip(infovec.iter()) {
         builder.build(geno, info.center_block_pos.to_array())
@@ -136,8 +165,10 @@ pub fn mutate_and_refresh_after_train(
 /// to make sure the nn_id always match. 
 /// (NN resource do not have id since their id is index, changed id are in `BlobGeno`)
 fn sync_mutate(
-    geno_q: &mut Vec<BlobGeno>,
+    geno_q: &mut [BlobGeno],
     bbn: &mut ResMut<BevyBlockNeurons>,
+    config: &EvoConfig,
+    rng: &mut dyn RngCore,
 ) -> (Vec<BlobGeno>, Vec<GenericNN>) {
     let mut existed_nn_ids = Vec::<usize>::new();
 
@@ -145,10 +176,10 @@ fn sync_mutate(
         // generate NN for new limbs
         for id in geno.all_nn_ids_mut() {
             if id.is_none() {
-                bbn.nnvec.push(GenericNN::BLOCKNN(BlockNN::default()));
+                bbn.nnvec.push(GenericNN::BLOCKNN(BlockNN::new(&config.nn, rng)));
                 *id = Some(bbn.nnvec.len() - 1);
             }
-            existed_nn_ids.push(id.clone().unwrap());
+            existed_nn_ids.push(id.unwrap());
         }
     }
 
@@ -192,6 +223,11 @@ fn sync_mutate(
         }
     }
 
+    // blobs are respawned, recurrent NNs start without memory
+    for nn in bbn.nnvec.iter_mut() {
+        nn.reset_state();
+    }
+
     // copy geno
     (Vec::from_iter(geno_q.iter().cloned()), bbn.nnvec.clone())
 }
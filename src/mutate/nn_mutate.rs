@@ -1,64 +1,212 @@
-//! implementations about neural networks's mutation (weight and bias)
+//! implementations about neural networks's mutation (weight, bias and topology)
 
 use rand::prelude::*;
 use rand_distr::{Distribution, Normal};
 
 use crate::{
+    blob::{
+        geno_blob_builder::{BlobGeno, GenericGenoNode},
+        lineage::Mutation,
+    },
     brain::{
         neuron::{BlockNN, BrainNN, GenericNN},
-        nn::BaseNN,
+        nn::{Activation, BaseNN},
     },
-    consts::mutate_consts::*,
+    config::MutateConfig,
 };
 
 /// mutate Neuron Networks
-pub fn mutate_nn(nnvec: &mut Vec<GenericNN>) {
-    for nn in nnvec.iter_mut() {
-        let mut rng: ThreadRng = thread_rng();
-
-        if !rng.gen_bool(MUTATE_NN_PORB as f64) {
+///
+/// return index of mutated NNs in `nnvec`
+pub fn mutate_nn(
+    nnvec: &mut [GenericNN],
+    config: &MutateConfig,
+    rng: &mut dyn RngCore,
+) -> Vec<usize> {
+    let mut mutated = Vec::new();
+    for (nn_id, nn) in nnvec.iter_mut().enumerate() {
+        if !rng.gen_bool(config.nn_prob as f64) {
             continue;
         }
 
         match nn {
-            GenericNN::BRAINNN(nn) => mutate_brain_nn(nn),
-            GenericNN::BLOCKNN(nn) => mutate_block_nn(nn),
+            GenericNN::BRAINNN(nn) => mutate_brain_nn(nn, config, rng),
+            GenericNN::BLOCKNN(nn) => mutate_block_nn(nn, config, rng),
         }
+        mutated.push(nn_id);
     }
+    mutated
 }
 
-fn mutate_block_nn(nn: &mut BlockNN) {
-    mutate_base_nn(&mut nn.inward_nn.nn);
-    mutate_base_nn(&mut nn.outward_nn.nn);
+/// record NN mutations in the lineage of genos,
+/// `mutated` is the result of `mutate_nn` on the NNs of these genos
+pub fn record_nn_mutation(genos: &mut [BlobGeno], mutated: &[usize]) {
+    for geno in genos.iter_mut() {
+        let nodes: Vec<usize> = geno
+            .vec_tree
+            .nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, node)| match node {
+                Some(GenericGenoNode::Child(node)) => node
+                    .nn_id
+                    .filter(|nn_id| mutated.contains(nn_id))
+                    .map(|_| idx),
+                _ => None,
+            })
+            .collect();
+        if !nodes.is_empty() {
+            geno.lineage.mutations.push(Mutation::Nn(nodes));
+        }
+    }
 }
 
-fn mutate_brain_nn(nn: &mut BrainNN) {
-    mutate_base_nn(&mut nn.nn);
+fn mutate_block_nn(nn: &mut BlockNN, config: &MutateConfig, rng: &mut dyn RngCore) {
+    mutate_base_nn(&mut nn.inward_nn.nn, config, rng);
+    mutate_topology(&mut nn.inward_nn.nn, config, rng);
+    mutate_base_nn(&mut nn.outward_nn.nn, config, rng);
+    mutate_topology(&mut nn.outward_nn.nn, config, rng);
 }
 
+fn mutate_brain_nn(nn: &mut BrainNN, config: &MutateConfig, rng: &mut dyn RngCore) {
+    mutate_base_nn(&mut nn.nn, config, rng);
+    mutate_topology(&mut nn.nn, config, rng);
+}
 
-/// add an random value to the existed weight and bias
-fn mutate_base_nn(nn: &mut BaseNN) {
-    let normal = Normal::new(0.0, MUTATE_NN_STD).unwrap();
 
-    // Use the thread_rng to get a thread-local random number generator
-    let mut rng: ThreadRng = thread_rng();
+/// add an random value to the existed weight and bias
+fn mutate_base_nn(nn: &mut BaseNN, config: &MutateConfig, rng: &mut dyn RngCore) {
+    let normal = Normal::new(0.0, config.nn_std).unwrap();
 
     for layer in &mut nn.layers {
         // Mutate weights
         for weight in layer.weights.iter_mut() {
-            if !rng.gen_bool(MUTATE_NN_WEIGHT_PROB as f64) {
+            if !rng.gen_bool(config.nn_weight_prob as f64) {
                 continue;
             }
-            *weight += normal.sample(&mut rng) as f32;
+            *weight += normal.sample(rng);
         }
 
         // Mutate biases
         for bias in layer.bias.iter_mut() {
-            if !rng.gen_bool(MUTATE_NN_BIAS_PROB as f64) {
+            if !rng.gen_bool(config.nn_bias_prob as f64) {
+                continue;
+            }
+            *bias += normal.sample(rng);
+        }
+    }
+
+    if let Some(recurrent) = &mut nn.recurrent {
+        for weight in recurrent.weights.iter_mut() {
+            if !rng.gen_bool(config.nn_weight_prob as f64) {
                 continue;
             }
-            *bias += normal.sample(&mut rng) as f32;
+            *weight += normal.sample(rng);
         }
     }
 }
+
+/// add or remove a hidden node or a hidden layer, and switch the activation of a node.
+///
+/// widths of input and output never change,
+/// hidden layers stay within `nn_max_hidden_nodes` and `nn_max_hidden_layers`,
+/// and the first layer of a recurrent network is never removed or pushed back
+pub fn mutate_topology(nn: &mut BaseNN, config: &MutateConfig, rng: &mut dyn RngCore) {
+    // first layer which can be inserted before or removed
+    let first = nn.recurrent.is_some() as usize;
+
+    if rng.gen_bool(config.nn_add_node_prob as f64) && nn.hidden_layers() > 0 {
+        let idx = rng.gen_range(0..nn.hidden_layers());
+        if nn.layers[idx].bias.len() < config.nn_max_hidden_nodes {
+            nn.add_node(idx, rng);
+        }
+    }
+
+    if rng.gen_bool(config.nn_remove_node_prob as f64) && nn.hidden_layers() > 0 {
+        let idx = rng.gen_range(0..nn.hidden_layers());
+        let nodes = nn.layers[idx].bias.len();
+        if nodes > 1 {
+            nn.remove_node(idx, rng.gen_range(0..nodes));
+        }
+    }
+
+    if rng.gen_bool(config.nn_add_layer_prob as f64)
+        && nn.hidden_layers() < config.nn_max_hidden_layers
+        && first < nn.layers.len()
+    {
+        let idx = rng.gen_range(first..nn.layers.len());
+        if nn.layers[idx].weights.ncols() <= config.nn_max_hidden_nodes {
+            nn.add_layer(idx);
+        }
+    }
+
+    if rng.gen_bool(config.nn_remove_layer_prob as f64) && first < nn.hidden_layers() {
+        nn.remove_layer(rng.gen_range(first..nn.hidden_layers()));
+    }
+
+    if rng.gen_bool(config.nn_activation_prob as f64) {
+        let idx = rng.gen_range(0..nn.layers.len());
+        let node = rng.gen_range(0..nn.layers[idx].bias.len());
+        let activation = Activation::ALL.choose(rng).unwrap().clone();
+        nn.set_activation(idx, node, activation);
+    }
+}
+
+#[cfg(test)]
+mod nn_mutate_test {
+    use ndarray::Array1;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::{
+        config::NNConfig,
+        consts::{BRAIN_NN_INPUT_LEN, BRAIN_NN_OUTPUT_LEN},
+    };
+
+    #[test]
+    fn test_mutate_topology() {
+        let mut config = MutateConfig::demo();
+        config.nn_add_node_prob = 0.5;
+        config.nn_remove_node_prob = 0.3;
+        config.nn_add_layer_prob = 0.3;
+        config.nn_remove_layer_prob = 0.2;
+        config.nn_activation_prob = 0.5;
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let nn_config = NNConfig {
+            recurrent: true,
+            ..Default::default()
+        };
+        let mut nn = BrainNN::new(&nn_config, &mut rng);
+        let input = Array1::<f32>::ones(BRAIN_NN_INPUT_LEN);
+
+        let mut shapes = Vec::new();
+        for _ in 0..200 {
+            mutate_brain_nn(&mut nn, &config, &mut rng);
+            let shape = nn.nn.shape();
+            // widths of input and output never change
+            assert_eq!(shape.first(), Some(&BRAIN_NN_INPUT_LEN));
+            assert_eq!(shape.last(), Some(&BRAIN_NN_OUTPUT_LEN));
+            assert!(nn.nn.hidden_layers() <= config.nn_max_hidden_layers);
+            assert!(shape[1..shape.len() - 1]
+                .iter()
+                .all(|&nodes| nodes >= 1 && nodes <= config.nn_max_hidden_nodes));
+            let recurrent = nn.nn.recurrent.as_ref().unwrap();
+            assert_eq!(recurrent.weights.dim(), (shape[1], shape[1]));
+            assert_eq!(nn.nn.step(input.clone()).len(), BRAIN_NN_OUTPUT_LEN);
+            shapes.push(shape);
+        }
+        shapes.dedup();
+        assert!(shapes.len() > 1);
+
+        // mutated topology is exported and imported as it is
+        let saved: BrainNN = serde_json::from_str(&serde_json::to_string(&nn).unwrap()).unwrap();
+        assert_eq!(saved.nn.shape(), nn.nn.shape());
+        assert!(saved.nn.same_activations(&nn.nn));
+        // weights are saved in logical order, which may differ from their memory layout
+        let (output, expected) = (saved.nn.forward(input.clone()), nn.nn.forward(input));
+        assert!(output
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).abs() <= 1e-4 * b.abs().max(1.0)));
+    }
+}
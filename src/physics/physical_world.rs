@@ -4,6 +4,7 @@ use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
 use crate::physics::rules::*;
+use crate::profile::{begin_physics_step, end_physics_step, Profiler};
 use crate::physics::world::setup_walls;
 
 /// all implementations relate to physic and the world.
@@ -13,6 +14,11 @@ use crate::physics::world::setup_walls;
 /// - world setup
 /// - gravity setup
 /// - viscosity force
+/// - time step contorl
+/// - timing of the physics step if profiling
+/// 
+/// Notice: debug render is not included,
+/// it belongs to `EvoGraphicsPlugin` so that headless mode can run without renderer
 pub struct PhysiWorldPlugin;
 
 impl Plugin for PhysiWorldPlugin {
@@ -22,14 +28,26 @@ impl Plugin for PhysiWorldPlugin {
             (
                 setup_walls,
                 setup_gravity,
+                setup_timestep,
                 // apply_forces
             ),
         )
         .add_systems(Update, viscosity)
-        .add_plugins((
+        .add_systems(
+            PostUpdate,
+            (
+                begin_physics_step
+                    .after(PhysicsSet::SyncBackend)
+                    .before(PhysicsSet::StepSimulation),
+                end_physics_step
+                    .after(PhysicsSet::StepSimulation)
+                    .before(PhysicsSet::Writeback),
+            )
+                .run_if(resource_exists::<Profiler>()),
+        )
+        .add_plugins(
             // raiper
             RapierPhysicsPlugin::<NoUserData>::default(),
-            RapierDebugRenderPlugin::default(),
-        ));
+        );
     }
 }
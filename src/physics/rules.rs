@@ -1,22 +1,33 @@
 //! additional physical rules applied
 
-use std::time::Instant;
-
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
+use crate::config::{EvoConfig, TrainingMode};
 use crate::consts::*;
+use crate::profile_span;
 
-pub fn setup_gravity(mut rapier_config: ResMut<RapierConfiguration>) {
-    if TRAINING_MODE == "swim" {
+pub fn setup_gravity(mut rapier_config: ResMut<RapierConfiguration>, config: Res<EvoConfig>) {
+    if config.train.mode == TrainingMode::Swim {
         rapier_config.gravity = Vec2::ZERO;
     }
 }
 
+/// using Fixed timestep so that the simulation can speed up
+pub fn setup_timestep(mut rapier_config: ResMut<RapierConfiguration>, config: Res<EvoConfig>) {
+    rapier_config.timestep_mode = TimestepMode::Fixed {
+        dt: config.timestep.rapier_dt,
+        substeps: config.timestep.rapier_substeps,
+    };
+}
+
 /// Create drag force for under-water simulation
 /// Cost about 5% of total running time in Physical Simulation
-pub fn viscosity(mut block_q: Query<(&Collider, &Transform, &Velocity, &mut ExternalForce)>) {
-    let start_time = Instant::now();
+pub fn viscosity(
+    mut block_q: Query<(&Collider, &Transform, &Velocity, &mut ExternalForce)>,
+    config: Res<EvoConfig>,
+) {
+    let _span = profile_span!("viscosity");
     // // parallel implementation, save about 3% of running time (in physical simulation)
     // block_q
     //     .par_iter_mut()
@@ -55,11 +66,6 @@ pub fn viscosity(mut block_q: Query<(&Collider, &Transform, &Velocity, &mut Exte
             + cube_shape.y * angle.cos().abs();
 
         // considering changing drag_coeff
-        force.force = DRAG_COEFF * (-v.linvel * projected_area);
-    }
-
-    let duration = Instant::now() - start_time;
-    if PRINT_FUNCTION_TIME && duration >= MIN_PRINT_DURATION{
-        println!("viscosity: {:?}", duration);
+        force.force = config.physics.drag_coeff * (-v.linvel * projected_area);
     }
 }
@@ -3,21 +3,17 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use crate::{consts::*, componet::ColliderFlag};
+use crate::{componet::ColliderFlag, config::EvoConfig};
 
 /// wall flag, different from `ColliderFlag`
 #[derive(Component)]
 pub struct Wall;
 
-pub fn setup_walls(mut commands: Commands) {
+pub fn setup_walls(mut commands: Commands, config: Res<EvoConfig>) {
 
-    let mut half_window_width = WORLD_WIDTH_SWIM / 2.0;
-    let mut half_window_height = WORLD_HEIGHT_SWIM /2.0;
-    
-    if TRAINING_MODE == "walk" {
-        half_window_width = WORLD_WIDTH_WALK / 2.0;
-        half_window_height = WORLD_HEIGHT_WALK /2.0;
-    }
+    let [world_width, world_height] = config.world_size();
+    let half_window_width = world_width / 2.0;
+    let half_window_height = world_height / 2.0;
 
 
     // Left wall
@@ -0,0 +1,369 @@
+//! profiling of the per-frame pipeline
+//!
+//! Stages of the pipeline are wrapped in `profile_span!`, tracing spans of target `profile`
+//! at `trace` level, which cost nothing unless profiling is enabled in `ProfileConfig`.
+//!
+//! If enabled, `StageLayer` adds the time of every stage span,
+//! and of bevy's system spans (cargo feature `profile`), to a `Histogram` by name.
+//! At the end of each iteration, `export_profile` logs the histograms
+//! and appends them to `<run_id>.profile.jsonl` in `export_path`.
+//!
+//! With cargo feature `profile`, all spans are also written as a Chrome trace
+//! to `<run_id>.trace.json` if `chrome_trace` is set,
+//! which opens in `chrome://tracing` or Perfetto as a flame chart.
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy::utils::tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    Subscriber,
+};
+use serde::Serialize;
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::{
+    config::EvoConfig,
+    contorl::{resource::Frames, train_move::iteration_end},
+    io::{export::create_if_not_exist, retention::run_id},
+    logger_info,
+};
+
+/// target of the stage spans, enabled by `profile=trace`
+pub const PROFILE_TARGET: &str = "profile";
+
+/// number of buckets of `Histogram`, the last one holds everything longer
+const HISTOGRAM_BUCKETS: usize = 32;
+
+/// enter a stage span until the end of the scope
+///
+/// `let _span = profile_span!("nn_forward");`
+#[macro_export]
+macro_rules! profile_span {
+    ($name:expr) => {
+        bevy::log::trace_span!(target: $crate::profile::PROFILE_TARGET, $name).entered()
+    };
+}
+
+/// durations of a stage,
+/// bucket `i` counts durations in `[2^i, 2^(i+1))` microseconds, bucket `0` starts from `0`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub count: usize,
+    pub total: Duration,
+    pub max: Duration,
+    pub buckets: [usize; HISTOGRAM_BUCKETS],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+            buckets: [0; HISTOGRAM_BUCKETS],
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        let micros = duration.as_micros().max(1);
+        let idx = (u128::BITS - 1 - micros.leading_zeros()) as usize;
+        self.buckets[idx.min(HISTOGRAM_BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        self.total / self.count as u32
+    }
+
+    /// upper bound of the bucket holding the `p` quantile, at most `max`
+    pub fn quantile(&self, p: f32) -> Duration {
+        let rank = (p * self.count as f32).ceil().max(1.0) as usize;
+        let mut seen = 0;
+        for (idx, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_micros(1 << (idx + 1)).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// histograms by span name, shared by `StageLayer` and `Profiler`
+#[derive(Debug, Clone, Default)]
+pub struct StageTimes(Arc<Mutex<HashMap<String, Histogram>>>);
+
+impl StageTimes {
+    pub fn record(&self, name: &str, duration: Duration) {
+        let mut times = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match times.get_mut(name) {
+            Some(histogram) => histogram.record(duration),
+            None => {
+                let mut histogram = Histogram::default();
+                histogram.record(duration);
+                times.insert(name.to_string(), histogram);
+            }
+        }
+    }
+
+    /// histograms recorded since the last call, the longest stage first
+    pub fn take(&self) -> Vec<(String, Histogram)> {
+        let times = std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()));
+        let mut times: Vec<(String, Histogram)> = times.into_iter().collect();
+        times.sort_by(|a, b| b.1.total.cmp(&a.1.total).then_with(|| a.0.cmp(&b.0)));
+        times
+    }
+}
+
+/// name and start time of a profiled span, in the span's extensions
+struct StageSpan {
+    name: String,
+    entered: Option<Instant>,
+}
+
+/// tracing layer recording the time between entering and exiting
+/// stage spans and bevy's system spans into `StageTimes`
+pub struct StageLayer(StageTimes);
+
+impl<S> Layer<S> for StageLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let metadata = attrs.metadata();
+        let name = if metadata.target() == PROFILE_TARGET {
+            metadata.name().to_string()
+        } else if metadata.name() == "system" {
+            // bevy's system span, `info_span!("system", name = ...)`
+            let mut visitor = SystemNameVisitor(None);
+            attrs.record(&mut visitor);
+            match visitor.0 {
+                Some(system) => format!("system {}", system),
+                None => return,
+            }
+        } else {
+            return;
+        };
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(StageSpan {
+                name,
+                entered: None,
+            });
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(stage) = span.extensions_mut().get_mut::<StageSpan>() {
+                stage.entered = Some(Instant::now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(stage) = span.extensions_mut().get_mut::<StageSpan>() {
+                if let Some(entered) = stage.entered.take() {
+                    self.0.record(&stage.name, entered.elapsed());
+                }
+            }
+        }
+    }
+}
+
+struct SystemNameVisitor(Option<String>);
+
+impl Visit for SystemNameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = Some(format!("{:?}", value).trim_matches('"').to_string());
+        }
+    }
+}
+
+/// profiling state of the app, as a bevy resource,
+/// exists only if profiling is enabled
+#[derive(Resource, Default)]
+pub struct Profiler {
+    times: StageTimes,
+    /// start of the physics step in this frame
+    physics_start: Option<Instant>,
+}
+
+impl Profiler {
+    /// layer of the tracing subscriber recording into this profiler
+    pub fn layer(&self) -> StageLayer {
+        StageLayer(self.times.clone())
+    }
+}
+
+/// rapier steps in its own systems, so the physics step is timed around its system set
+pub fn begin_physics_step(mut profiler: ResMut<Profiler>) {
+    profiler.physics_start = Some(Instant::now());
+}
+
+pub fn end_physics_step(mut profiler: ResMut<Profiler>) {
+    if let Some(start) = profiler.physics_start.take() {
+        profiler.times.record("physics_step", start.elapsed());
+    }
+}
+
+/// summary of a stage in the profile file
+#[derive(Debug, Serialize)]
+struct StageSummary<'a> {
+    name: &'a str,
+    count: usize,
+    /// seconds
+    total: f32,
+    /// microseconds
+    mean: u128,
+    p50: u128,
+    p99: u128,
+    max: u128,
+    buckets: &'a [usize],
+}
+
+/// log and save the stage times of the iteration at the end of each iteration.
+///
+/// stages run after this system in the last frame, e.g. selection and mutation,
+/// are counted in the next iteration
+pub fn export_profile(frames: Res<Frames>, config: Res<EvoConfig>, profiler: Res<Profiler>) {
+    if !iteration_end(&frames, config.train.iteration_length) {
+        return;
+    }
+    let iteration = (frames.0 / config.train.iteration_length as u128) as usize;
+    let times = profiler.times.take();
+
+    logger_info!("PROFILE of iteration {}", iteration);
+    for (name, histogram) in times.iter() {
+        logger_info!(
+            "{:<48} calls {:>7}  total {:>10.2?}  mean {:>9.2?}  p99 {:>9.2?}  max {:>9.2?}",
+            name,
+            histogram.count,
+            histogram.total,
+            histogram.mean(),
+            histogram.quantile(0.99),
+            histogram.max
+        );
+    }
+
+    if let Err(e) = append_profile(&config, iteration, &times) {
+        warn!("Failed to write profile: {}", e);
+    }
+}
+
+fn append_profile(
+    config: &EvoConfig,
+    iteration: usize,
+    times: &[(String, Histogram)],
+) -> std::io::Result<()> {
+    create_if_not_exist(&config.io.export_path)?;
+    let stages: Vec<StageSummary> = times
+        .iter()
+        .map(|(name, histogram)| StageSummary {
+            name,
+            count: histogram.count,
+            total: histogram.total.as_secs_f32(),
+            mean: histogram.mean().as_micros(),
+            p50: histogram.quantile(0.5).as_micros(),
+            p99: histogram.quantile(0.99).as_micros(),
+            max: histogram.max.as_micros(),
+            buckets: &histogram.buckets,
+        })
+        .collect();
+    let line = serde_json::json!({ "iteration": iteration, "stages": stages });
+
+    let fname = format!("{}{}.profile.jsonl", config.io.export_path, run_id(config));
+    let mut file = OpenOptions::new().create(true).append(true).open(fname)?;
+    writeln!(file, "{}", line)
+}
+
+/// Chrome trace layer writing to `<run_id>.trace.json`,
+/// the guard writes the rest of the trace when dropped
+#[cfg(feature = "profile")]
+pub fn chrome_layer<S>(
+    config: &EvoConfig,
+) -> std::io::Result<(tracing_chrome::ChromeLayer<S>, tracing_chrome::FlushGuard)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    create_if_not_exist(&config.io.export_path)?;
+    Ok(tracing_chrome::ChromeLayerBuilder::new()
+        .file(format!(
+            "{}{}.trace.json",
+            config.io.export_path,
+            run_id(config)
+        ))
+        .include_args(true)
+        .build())
+}
+
+#[cfg(test)]
+mod profile_test {
+    use bevy::utils::tracing::{info_span, subscriber};
+    use tracing_subscriber::{prelude::*, registry::Registry};
+
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        for micros in [0, 1, 3, 3, 100] {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.count, 5);
+        assert_eq!(histogram.buckets[..8], [2, 2, 0, 0, 0, 0, 1, 0]);
+        assert_eq!(histogram.max, Duration::from_micros(100));
+        assert_eq!(histogram.mean(), Duration::from_nanos(21400));
+        assert_eq!(histogram.quantile(0.5), Duration::from_micros(4));
+        // upper bound of the last bucket is capped by max
+        assert_eq!(histogram.quantile(0.99), Duration::from_micros(100));
+    }
+
+    #[test]
+    fn test_stage_layer() {
+        let profiler = Profiler::default();
+        let subscriber = Registry::default().with(profiler.layer());
+        subscriber::with_default(subscriber, || {
+            for _ in 0..3 {
+                let _span = profile_span!("stage");
+            }
+            // spans of other targets are ignored
+            let _span = info_span!("other").entered();
+            // system spans are entered once per run
+            let system = info_span!("system", name = "evosim::update");
+            for _ in 0..2 {
+                let _enter = system.enter();
+            }
+        });
+
+        let times = profiler.times.take();
+        let names: Vec<(&str, usize)> = times
+            .iter()
+            .map(|(name, histogram)| (name.as_str(), histogram.count))
+            .collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&("stage", 3)));
+        assert!(names.contains(&("system evosim::update", 2)));
+        assert!(profiler.times.take().is_empty());
+    }
+}